@@ -0,0 +1,262 @@
+/*
+ * This software is released under the MIT License.
+ * http://opensource.org/licenses/mit-license.php
+ */
+
+// 随伴法による目的関数の誘電率(epsd)についての感度 (TE: Ez のみ)
+//
+// 周波数 ω のDFTの値 E (Σ Ez^n exp(-jωnΔt) Δt) は離散化したヘルムホルツ方程式
+//   (C C' - μ0 ε0 ω~^2 diag(εz)) E = b,  ω~ = 2 sin(ωΔt/2) / Δt
+// を満たす (C C' は差分の curl curl で対称, εz はEzの点の周りの4セルのepsdの平均)
+// 目的関数 F(E) を dF = 2 Re Σ g dE と書くと, 給電 b = g の随伴計算の E_adj (相反性から転置の解と同じ) を使って
+//   dF/dεz = 2 μ0 ε0 ω~^2 Re(E_adj E)
+// となり, セル (material() と同じ番号, 角が点 x..x+1, y..y+1) についてはその4点の 1/4 の和になる
+// 給電は通常の給電点 (Ez -= Δt/ε × 波形 / 面積) なので b = -μ0 D W / (ΔxΔy), D = (exp(jωΔt) - 1) / Δt,
+// W は波形のDFT. 任意の複素数の b を与えるため, 時刻を1/4周期ずらした2つの波形の実数倍の和で給電する
+// 順計算と随伴計算は同じ build, run で行うので, 場が十分減衰するまで計算すること (DFTの打ち切り誤差が感度の誤差になる)
+
+use std::f64::consts::PI;
+use std::io::{self, Write};
+
+use complex::Complex;
+use error::{Error, Result};
+use fdtd2d::{fdtd, Component, Source, EPS0, MU0};
+use monitor::DftMonitor;
+use port::Axis;
+use snapshot::Region;
+
+// 目的関数 (周波数 freq のDFTの値から計算する)
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Objective {
+    // 領域内の |Ez|^2 の和
+    Intensity{region: Region},
+    // 線を通過する電力 (FluxMonitorと同じ定義, 法線の+方向を正)
+    // Hは両隣の線のEzから求めるので, 線とその両隣は非磁性 (μ = μ0) であること
+    Flux{normal: Axis, pos: usize, start: usize, end: usize},
+}
+
+impl Objective {
+    // 値の計算に使うEzの領域
+    pub fn support(&self) -> Region {
+        match *self {
+            Objective::Intensity{region} => region,
+            Objective::Flux{normal: Axis::X, pos, start, end} => Region{x_s: pos.saturating_sub(1), x_l: pos + 2, y_s: start, y_l: end},
+            Objective::Flux{normal: Axis::Y, pos, start, end} => Region{x_s: start, x_l: end, y_s: pos.saturating_sub(1), y_l: pos + 2},
+        }
+    }
+
+    // support のDFTモニタ e (周波数は1つ) から値と, dF = 2 Re Σ g dE の g (配列番号毎) を求める (cell: セルサイズ)
+    pub fn evaluate(&self, e: &DftMonitor, dt: f64, cell: (f64, f64)) -> (f64, Vec<(usize, usize, Complex)>) {
+        let mut grad = Vec::new();
+        match *self {
+            Objective::Intensity{region: r} => {
+                let mut value = 0.0;
+                for y in r.y_s..r.y_l {
+                    for x in r.x_s..r.x_l {
+                        let v = e.value(0, x, y);
+                        value += v.norm_sqr();
+                        grad.push((x, y, v.conj()));
+                    }
+                }
+                (value, grad)
+            }
+            Objective::Flux{normal, pos, start, end} => {
+                // H (線上, 時刻を合わせた値) = κ (E(pos+1) - E(pos-1)),  F = -1/2 Re Σ E conj(H) × 線に沿った幅
+                let w = 2.0 * PI * e.freqs[0];
+                let wt = 2.0 * (0.5 * w * dt).sin() / dt;
+                let (d, len) = match normal {
+                    Axis::X => cell,
+                    Axis::Y => (cell.1, cell.0),
+                };
+                let kappa = Complex::new(0.0, -1.0 / (2.0 * wt * MU0 * d));
+                // F = Re(α u conj(v)), u = E(pos), v = E(pos+1) - E(pos-1)
+                let alpha = kappa.conj().scale(-0.5 * len);
+                let at = |s: usize, k: usize| match normal {
+                    Axis::X => (pos + k - 1, s),
+                    Axis::Y => (s, pos + k - 1),
+                };
+                let mut value = 0.0;
+                for s in start..end {
+                    let (m, c, p) = (at(s, 0), at(s, 1), at(s, 2));
+                    let u = e.value(0, c.0, c.1);
+                    let v = e.value(0, p.0, p.1) - e.value(0, m.0, m.1);
+                    value += (alpha * u * v.conj()).re;
+                    let gv = (alpha.conj() * u.conj()).scale(0.5);
+                    grad.push((c.0, c.1, (alpha * v.conj()).scale(0.5)));
+                    grad.push((p.0, p.1, gv));
+                    grad.push((m.0, m.1, gv.scale(-1.0)));
+                }
+                (value, grad)
+            }
+        }
+    }
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct AdjointSolver {
+    pub objective: Objective,
+    pub freq: f64,
+    // 感度を求めるセルの領域 (material() と同じ番号)
+    pub design: Region,
+    // 随伴計算の給電波形 (搬送波周波数はfreq)
+    pub pulse: Source,
+}
+
+impl AdjointSolver {
+    pub fn new(objective: Objective, freq: f64, design: Region) -> AdjointSolver {
+        let pulse = Source::new(0, 0).pulse(2.0 / freq, 8.0 / freq).carrier(freq);
+        AdjointSolver{objective, freq, design, pulse}
+    }
+
+    pub fn pulse(mut self, duration: f64, t0: f64) -> AdjointSolver {
+        self.pulse = self.pulse.pulse(duration, t0);
+        self
+    }
+
+    // 設計領域の感度に使うEzの点 (セル (x, y) の誘電率は点 x..x+1, y..y+1 の平均に入る)
+    fn nodes(&self) -> Region {
+        let d = self.design;
+        Region{x_s: d.x_s, x_l: d.x_l + 1, y_s: d.y_s, y_l: d.y_l + 1}
+    }
+
+    fn check(&self, f: &fdtd) -> Result<()> {
+        if !self.freq.is_finite() || self.freq <= 0.0 {
+            return Err(Error::InvalidValue{what: "adjoint frequency", value: self.freq});
+        }
+        f.require_uniform("adjoint sensitivity on a graded mesh")?;
+        let (nx, ny) = f.dims();
+        let l = f.lpml();
+        for &(what, r) in [("design region", self.nodes()), ("objective", self.objective.support())].iter() {
+            if r.x_s >= r.x_l {
+                return Err(Error::ReversedRange{what, axis: 'x', start: r.x_s, end: r.x_l});
+            }
+            if r.y_s >= r.y_l {
+                return Err(Error::ReversedRange{what, axis: 'y', start: r.y_s, end: r.y_l});
+            }
+            if r.x_l > nx || r.y_l > ny {
+                return Err(Error::OutOfBounds{what, x: r.x_l - 1, y: r.y_l - 1, nx, ny});
+            }
+            if r.x_s <= l || r.y_s <= l || r.x_l >= nx - l || r.y_l >= ny - l {
+                return Err(Error::InPml{what, x: r.x_s, y: r.y_s, lpml: l});
+            }
+        }
+        Ok(())
+    }
+
+    // 順計算 (design: 設計領域のEzも記録する). 解析モジュールと設計領域, 目的関数のDFTモニタの番号を返す
+    fn forward_solver<B, R>(&self, sources: &[Source], design: bool, build: &mut B, run: &mut R) -> Result<(fdtd, usize, usize)>
+        where B: FnMut() -> Result<fdtd>, R: FnMut(&mut fdtd)
+    {
+        let mut f = build()?;
+        self.check(&f)?;
+        for s in sources {
+            f.add_source(*s)?;
+        }
+        let freqs = [self.freq];
+        let l = f.lpml();
+        let nodes = if design { self.nodes() } else { Region{x_s: l, x_l: l + 1, y_s: l, y_l: l + 1} };
+        let d = DftMonitor::new(&f, Component::Ez, nodes, &freqs)?;
+        let d = f.add_monitor(d)?;
+        let o = DftMonitor::new(&f, Component::Ez, self.objective.support(), &freqs)?;
+        let o = f.add_monitor(o)?;
+        run(&mut f);
+        Ok((f, d, o))
+    }
+
+    // 目的関数の値のみ (順計算1回)
+    pub fn forward<B, R>(&self, sources: &[Source], mut build: B, mut run: R) -> Result<f64>
+        where B: FnMut() -> Result<fdtd>, R: FnMut(&mut fdtd)
+    {
+        let (f, _, o) = self.forward_solver(sources, false, &mut build, &mut run)?;
+        Ok(self.objective.evaluate(&f.monitors()[o], f.dt, f.cell_size()).0)
+    }
+
+    // build で生成した解析モジュール (媒質, 完全導体を設定済み, 給電点なし) に sources を加えた順計算と,
+    // 目的関数から決まる給電の随伴計算を run で行い, 目的関数と設計領域の感度を求める
+    pub fn solve<B, R>(&self, sources: &[Source], mut build: B, mut run: R) -> Result<Sensitivity>
+        where B: FnMut() -> Result<fdtd>, R: FnMut(&mut fdtd)
+    {
+        let (f, d, o) = self.forward_solver(sources, true, &mut build, &mut run)?;
+        let (value, grad) = self.objective.evaluate(&f.monitors()[o], f.dt, f.cell_size());
+        let e = f.monitors()[d].clone();
+        log_info!("adjoint: forward done (objective {:e})", value);
+
+        // 随伴計算の給電 (2つの波形の実数倍 a, b で b = g を与える)
+        let mut g = build()?;
+        let (dx, dy) = g.cell_size();
+        let dt = g.dt;
+        let w = 2.0 * PI * self.freq;
+        let dd = (Complex::from_polar(1.0, w * dt) - Complex::new(1.0, 0.0)).scale(1.0 / dt);
+        let quarter = 0.25 / self.freq;
+        let wa = self.spectrum(self.pulse, dt);
+        let wb = self.spectrum(self.pulse.pulse(self.pulse.duration, self.pulse.t0 + quarter), dt);
+        let det = wa.re * wb.im - wa.im * wb.re;
+        for &(x, y, gk) in &grad {
+            if gk.norm_sqr() == 0.0 {
+                continue;
+            }
+            let c = gk.scale(-dx * dy) / (dd * MU0);
+            let a = (c.re * wb.im - c.im * wb.re) / det;
+            let b = (wa.re * c.im - wa.im * c.re) / det;
+            let mut s = self.pulse;
+            s.x = x;
+            s.y = y;
+            g.add_source(s.amplitude(a))?;
+            g.add_source(s.pulse(s.duration, s.t0 + quarter).amplitude(b))?;
+        }
+        let nodes = self.nodes();
+        let m = DftMonitor::new(&g, Component::Ez, nodes, &[self.freq])?;
+        let m = g.add_monitor(m)?;
+        run(&mut g);
+        let adj = &g.monitors()[m];
+        log_info!("adjoint: adjoint done ({} sources)", g.sources().len());
+
+        // dF/dεz (点毎) → dF/dε (セル毎)
+        let wt = 2.0 * (0.5 * w * dt).sin() / dt;
+        let k = 2.0 * MU0 * EPS0 * wt * wt;
+        let dz = |x: usize, y: usize| k * (adj.value(0, x, y) * e.value(0, x, y)).re;
+        let r = self.design;
+        let gradient = (r.x_s..r.x_l).map(|x| (r.y_s..r.y_l).map(|y| {
+            0.25 * (dz(x, y) + dz(x + 1, y) + dz(x, y + 1) + dz(x + 1, y + 1))
+        }).collect()).collect();
+        Ok(Sensitivity{freq: self.freq, design: r, value, gradient})
+    }
+
+    // 給電波形のDFT Σ s(nΔt) exp(-jωnΔt) Δt (n = 1.. で波形が十分小さくなるまで)
+    fn spectrum(&self, s: Source, dt: f64) -> Complex {
+        let n = ((s.t0 + 8.0 * s.duration) / dt).ceil() as usize + 1;
+        let w = 2.0 * PI * self.freq;
+        (1..n + 1).fold(Complex::default(), |acc, k| {
+            let t = k as f64 * dt;
+            acc + Complex::from_polar(s.waveform(t, dt) * dt, -w * t)
+        })
+    }
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct Sensitivity {
+    pub freq: f64,
+    pub design: Region,
+    // 目的関数の値
+    pub value: f64,
+    // 比誘電率についての感度 dF/dε [x - x_s][y - y_s]
+    pub gradient: Vec<Vec<f64>>,
+}
+
+impl Sensitivity {
+    // 配列番号 (x, y) のセルの感度
+    pub fn at(&self, x: usize, y: usize) -> f64 {
+        self.gradient[x - self.design.x_s][y - self.design.y_s]
+    }
+
+    // x,y,gradient のCSV
+    pub fn write_csv<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "x,y,gradient")?;
+        for (i, col) in self.gradient.iter().enumerate() {
+            for (j, g) in col.iter().enumerate() {
+                writeln!(w, "{},{},{:e}", self.design.x_s + i, self.design.y_s + j, g)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,245 @@
+/*
+ * This software is released under the MIT License.
+ * http://opensource.org/licenses/mit-license.php
+ */
+
+// アンテナの評価 (入力インピーダンス, 反射係数, 放射効率, 指向性, 利得)
+//
+// 給電は集中定数給電 (Source::lumped) のEz. z方向の長さlの給電点の
+//   電圧 V = -Ez l, 電流 I = ∮H·dl (給電点を囲む反時計回り), Z = V / I
+// でHは半ステップ前の値なので exp(jωΔt/2) を掛けてEと同じ時刻に合わせる
+// 放射電力は素子を囲む閉曲線上のポインティングベクトルの積分, 遠方界は同じ閉曲線上の等価電流
+//   J = n × H, M = -n × E  (nは外向き法線)
+// からの2次元の近傍界-遠方界変換で
+//   Ez(ρ, φ) ∝ ∮ (Ez n·r - η Jz) exp(jk r·r') dl  (rは φ 方向の単位ベクトル)
+// 放射強度 U(φ) = k |∮...|^2 / (16π η) (W/m/rad), 指向性 D = 2π U / ∫U dφ, 利得 G = 効率 × D
+// 2次元なので電力はz方向の単位長さ当たりの値に給電点の長さlを掛けて給電点の電力とそろえる
+// 閉曲線はPMLの外側に置き, 給電点と素子を全て囲む (閉曲線の外は損失のない一様な真空とする)
+
+use std::f64::consts::PI;
+use std::io::{self, Write};
+
+use complex::Complex;
+use error::{Error, Result};
+use fdtd2d::{fdtd, Component, C, MU0};
+use monitor::DftMonitor;
+use snapshot::Region;
+
+// 閉曲線の辺 (外向き法線, Ezと接線方向のHのDFTモニタの番号)
+#[derive(Clone,Copy,Debug)]
+struct Edge {
+    normal: (f64, f64),
+    ez: usize,
+    h: usize,
+}
+
+// 閉曲線上の点 (位置, 外向き法線, 線素の長さ, Ez, Jz)
+#[derive(Clone,Copy,Debug)]
+struct Sample {
+    pos: (f64, f64),
+    normal: (f64, f64),
+    dl: f64,
+    ez: Complex,
+    jz: Complex,
+}
+
+#[derive(Clone,Debug)]
+pub struct Antenna {
+    pub freqs: Vec<f64>,
+    // 給電点の位置とz方向の長さ
+    pub feed: (usize, usize),
+    pub length: f64,
+    // 閉曲線 (Regionの外周, x = x_s, x_l-1, y = y_s, y_l-1 の線)
+    pub contour: Region,
+    // 給電点のEz, Hx, HyのDFTモニタの番号
+    feed_monitors: (usize, usize, usize),
+    edges: Vec<Edge>,
+}
+
+impl Antenna {
+    // sources()のsource番目の給電点と閉曲線contourのDFTモニタを登録する (計算の前に呼ぶ)
+    pub fn new(f: &mut fdtd, source: usize, contour: Region, freqs: &[f64]) -> Result<Antenna> {
+        f.require_uniform("antenna metrics on a graded mesh")?;
+        let s = match f.sources().get(source) {
+            Some(s) => *s,
+            None => return Err(Error::InvalidValue{what: "antenna source index", value: source as f64}),
+        };
+        if !s.length.is_finite() || s.length <= 0.0 {
+            return Err(Error::InvalidValue{what: "antenna feed length", value: s.length});
+        }
+        let r = contour;
+        if r.x_l < r.x_s + 2 || r.y_l < r.y_s + 2 {
+            return Err(Error::ReversedRange{what: "far-field contour", axis: if r.x_l < r.x_s + 2 { 'x' } else { 'y' }, start: r.x_s, end: r.x_l});
+        }
+        let (nx, ny) = f.dims();
+        // 辺の外側のHも使う
+        if r.x_s == 0 || r.y_s == 0 {
+            return Err(Error::OutOfBounds{what: "far-field contour", x: r.x_s, y: r.y_s, nx, ny});
+        }
+        if s.x <= r.x_s || s.x + 1 >= r.x_l || s.y <= r.y_s || s.y + 1 >= r.y_l {
+            return Err(Error::OutOfBounds{what: "antenna feed inside the contour", x: s.x, y: s.y, nx, ny});
+        }
+
+        // 全ての領域を検査してから登録する (閉曲線が配列の端, PMLに掛かる場合はDftMonitor::newのエラー)
+        let (x, y) = (s.x, s.y);
+        let feed = vec![DftMonitor::new(f, Component::Ez, Region{x_s: x, x_l: x + 1, y_s: y, y_l: y + 1}, freqs)?,
+                        DftMonitor::new(f, Component::Hx, Region{x_s: x, x_l: x + 1, y_s: y - 1, y_l: y + 1}, freqs)?,
+                        DftMonitor::new(f, Component::Hy, Region{x_s: x - 1, x_l: x + 1, y_s: y, y_l: y + 1}, freqs)?];
+        // 下, 上の辺は Hx (線の両側の2行), 左, 右の辺は Hy (線の両側の2列)
+        let mut lines = Vec::with_capacity(4);
+        for &(yy, n) in [(r.y_s, -1.0), (r.y_l - 1, 1.0)].iter() {
+            let line = Region{x_s: r.x_s, x_l: r.x_l, y_s: yy, y_l: yy + 1};
+            let strip = Region{x_s: r.x_s, x_l: r.x_l, y_s: yy - 1, y_l: yy + 1};
+            lines.push(((0.0, n), DftMonitor::new(f, Component::Ez, line, freqs)?, DftMonitor::new(f, Component::Hx, strip, freqs)?));
+        }
+        for &(xx, n) in [(r.x_s, -1.0), (r.x_l - 1, 1.0)].iter() {
+            let line = Region{x_s: xx, x_l: xx + 1, y_s: r.y_s, y_l: r.y_l};
+            let strip = Region{x_s: xx - 1, x_l: xx + 1, y_s: r.y_s, y_l: r.y_l};
+            lines.push(((n, 0.0), DftMonitor::new(f, Component::Ez, line, freqs)?, DftMonitor::new(f, Component::Hy, strip, freqs)?));
+        }
+
+        let mut ids = Vec::with_capacity(3);
+        for m in feed {
+            ids.push(f.add_monitor(m)?);
+        }
+        let mut edges = Vec::with_capacity(4);
+        for (normal, ez, h) in lines {
+            edges.push(Edge{normal, ez: f.add_monitor(ez)?, h: f.add_monitor(h)?});
+        }
+
+        Ok(Antenna{freqs: freqs.to_vec(), feed: (x, y), length: s.length, contour, feed_monitors: (ids[0], ids[1], ids[2]), edges})
+    }
+
+    // 閉曲線上の点 (台形則の重みを線素の長さに含める)
+    fn samples(&self, f: &fdtd, n: usize) -> Vec<Sample> {
+        let m = f.monitors();
+        let (dx, dy) = f.cell_size();
+        let shift = Complex::from_polar(1.0, PI * self.freqs[n] * f.dt);
+        let mut out = Vec::new();
+        for e in &self.edges {
+            let (ez, h) = (&m[e.ez], &m[e.h]);
+            let r = ez.region;
+            let horizontal = e.normal.0 == 0.0;
+            let count = if horizontal { r.x_l - r.x_s } else { r.y_l - r.y_s };
+            for k in 0..count {
+                let (x, y) = if horizontal { (r.x_s + k, r.y_s) } else { (r.x_s, r.y_s + k) };
+                // 接線方向のHを線の両側の平均でEzの位置にする
+                let (hm, hp) = if horizontal { (h.value(n, x, y - 1), h.value(n, x, y)) } else { (h.value(n, x - 1, y), h.value(n, x, y)) };
+                let ht = (hm + hp).scale(0.5) * shift;
+                // Jz = nx Hy - ny Hx
+                let jz = if horizontal { ht.scale(-e.normal.1) } else { ht.scale(e.normal.0) };
+                // 台形則 (両端は半分)
+                let dl = if k == 0 || k + 1 == count { 0.5 } else { 1.0 } * if horizontal { dx } else { dy };
+                out.push(Sample{pos: f.position(x, y), normal: e.normal, dl, ez: ez.value(n, x, y), jz});
+            }
+        }
+        out
+    }
+
+    // 計算後の評価 (z0: 反射係数の基準インピーダンス, angles: 指向性の角度の分割数)
+    pub fn metrics(&self, f: &fdtd, z0: f64, angles: usize) -> AntennaMetrics {
+        let eta = MU0 * C;
+        let m = f.monitors();
+        let (dx, dy) = f.cell_size();
+        let (x, y) = self.feed;
+        let na = angles.max(1);
+        let phi: Vec<f64> = (0..na).map(|i| 2.0 * PI * i as f64 / na as f64).collect();
+        let r = self.contour;
+        let (cx, cy) = {
+            let (a, b) = (f.position(r.x_s, r.y_s), f.position(r.x_l - 1, r.y_l - 1));
+            (0.5 * (a.0 + b.0), 0.5 * (a.1 + b.1))
+        };
+
+        let mut out = AntennaMetrics{freqs: self.freqs.clone(), z0, impedance: Vec::new(), s11: Vec::new(),
+                                     accepted: Vec::new(), radiated: Vec::new(), efficiency: Vec::new(),
+                                     angles: phi.clone(), directivity: Vec::new(), gain: Vec::new()};
+        for (n, &freq) in self.freqs.iter().enumerate() {
+            let shift = Complex::from_polar(1.0, PI * freq * f.dt);
+            let (me, mx, my) = (&m[self.feed_monitors.0], &m[self.feed_monitors.1], &m[self.feed_monitors.2]);
+            let ez = me.value(n, x, y);
+            let i = ((my.value(n, x, y) - my.value(n, x - 1, y)).scale(dy) - (mx.value(n, x, y) - mx.value(n, x, y - 1)).scale(dx)) * shift;
+            let v = ez.scale(-self.length);
+            let z = v / i;
+            let accepted = 0.5 * (v * i.conj()).re;
+
+            let samples = self.samples(f, n);
+            // 放射電力 (単位長さ当たり) -1/2 Re ∮ Ez Jz* dl
+            let flux: f64 = samples.iter().map(|s| -0.5 * (s.ez * s.jz.conj()).re * s.dl).sum();
+            let radiated = flux * self.length;
+
+            let k = 2.0 * PI * freq / C;
+            let u: Vec<f64> = phi.iter().map(|&p| {
+                let (ux, uy) = (p.cos(), p.sin());
+                let mut sum = Complex::default();
+                for s in &samples {
+                    let ((px, py), (nx, ny)) = (s.pos, s.normal);
+                    let phase = Complex::from_polar(s.dl, k * (ux * (px - cx) + uy * (py - cy)));
+                    sum += (s.ez.scale(nx * ux + ny * uy) - s.jz.scale(eta)) * phase;
+                }
+                k * sum.norm_sqr() / (16.0 * PI * eta)
+            }).collect();
+            let total: f64 = u.iter().sum::<f64>() * 2.0 * PI / na as f64;
+            let efficiency = if accepted > 0.0 { flux * self.length / accepted } else { 0.0 };
+            let d: Vec<f64> = u.iter().map(|u| if total > 0.0 { 2.0 * PI * u / total } else { 0.0 }).collect();
+
+            out.impedance.push(z);
+            out.s11.push((z - Complex::new(z0, 0.0)) / (z + Complex::new(z0, 0.0)));
+            out.accepted.push(accepted);
+            out.radiated.push(radiated);
+            out.efficiency.push(efficiency);
+            out.gain.push(d.iter().map(|d| d * efficiency).collect());
+            out.directivity.push(d);
+        }
+        out
+    }
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct AntennaMetrics {
+    pub freqs: Vec<f64>,
+    // 反射係数の基準インピーダンス (Ω)
+    pub z0: f64,
+    // 入力インピーダンス (Ω), 反射係数
+    pub impedance: Vec<Complex>,
+    pub s11: Vec<Complex>,
+    // 給電点で受け入れた電力と閉曲線からの放射電力 (W, 給電点の長さ分), 放射効率
+    pub accepted: Vec<f64>,
+    pub radiated: Vec<f64>,
+    pub efficiency: Vec<f64>,
+    // 角度 (rad, x軸から反時計回り), [周波数][角度] の指向性と利得 (真数)
+    pub angles: Vec<f64>,
+    pub directivity: Vec<Vec<f64>>,
+    pub gain: Vec<Vec<f64>>,
+}
+
+impl AntennaMetrics {
+    // リターンロス -20 log10 |S11| (dB)
+    pub fn return_loss(&self) -> Vec<f64> {
+        self.s11.iter().map(|s| -20.0 * s.abs().log10()).collect()
+    }
+
+    // freq,z_re,z_im,s11_re,s11_im,return_loss,accepted,radiated,efficiency,directivity,gain のCSV
+    // (指向性と利得は最大値のdBi)
+    pub fn write_csv<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "freq,z_re,z_im,s11_re,s11_im,return_loss,accepted,radiated,efficiency,directivity,gain")?;
+        let max = |v: &[f64]| 10.0 * v.iter().fold(0.0f64, |a, &b| a.max(b)).log10();
+        for (n, rl) in self.return_loss().into_iter().enumerate() {
+            let (z, s) = (self.impedance[n], self.s11[n]);
+            writeln!(w, "{:e},{:e},{:e},{:e},{:e},{:e},{:e},{:e},{:e},{:e},{:e}", self.freqs[n], z.re, z.im, s.re, s.im, rl,
+                     self.accepted[n], self.radiated[n], self.efficiency[n], max(&self.directivity[n]), max(&self.gain[n]))?;
+        }
+        Ok(())
+    }
+
+    // freq,angle,directivity,directivity_dbi,gain,gain_dbi のCSV (角度は度)
+    pub fn write_pattern_csv<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "freq,angle,directivity,directivity_dbi,gain,gain_dbi")?;
+        for (n, freq) in self.freqs.iter().enumerate() {
+            for (k, a) in self.angles.iter().enumerate() {
+                let (d, g) = (self.directivity[n][k], self.gain[n][k]);
+                writeln!(w, "{:e},{:e},{:e},{:e},{:e},{:e}", freq, a.to_degrees(), d, 10.0 * d.log10(), g, 10.0 * g.log10())?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,262 @@
+/*
+ * This software is released under the MIT License.
+ * http://opensource.org/licenses/mit-license.php
+ */
+
+// フォトニック結晶のバンド構造 (Bloch境界条件)
+//
+// 単位セルの場を複素数 ψ = ψr + jψi とし, 実部と虚部をそれぞれ通常の解析モジュールで同時に計算する
+// 境界条件 ψ(x + a, y) = ψ(x, y) exp(jθx), ψ(x, y + b) = ψ(x, y) exp(jθy) (θ = k·周期) は
+// 配列の外周の1層 (番号0とn-1) を反対側の内側の値に位相を掛けた値で半ステップ毎に書き換えて与える
+// 単位セルは配列番号 1..n-1 で周期は a = (nx-2)dx, b = (ny-2)dy. PML層数0で生成し,
+// 媒質は周期的に設定する (セル0とセルnx-2, セル0とセルny-2 が同じになるように)
+// k点毎にセル内にランダムに置いた広帯域の給電点で励振し, 観測点の時系列から Harminv で固有周波数を求める
+
+use std::f64::consts::PI;
+use std::io::{self, Write};
+
+use eigenmode::Polarization;
+use error::{Error, Result};
+use fdtd2d::{fdtd, Component, Source, C};
+use harminv::{Harminv, Mode};
+use monitor::Probe;
+use random::Random;
+
+// 折れ線 corners (逆格子の単位 2π/a, 2π/b) の各辺をdivisions等分したk点の列 (両端を含む)
+pub fn k_path(corners: &[(f64, f64)], divisions: usize) -> Vec<(f64, f64)> {
+    let n = divisions.max(1);
+    let mut points = Vec::new();
+    for (i, w) in corners.windows(2).enumerate() {
+        let ((x0, y0), (x1, y1)) = (w[0], w[1]);
+        let first = if i == 0 { 0 } else { 1 };
+        for k in first..n + 1 {
+            let r = k as f64 / n as f64;
+            points.push((x0 + (x1 - x0) * r, y0 + (y1 - y0) * r));
+        }
+    }
+    if corners.len() == 1 {
+        points.push(corners[0]);
+    }
+    points
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct BandSolver {
+    pub polarization: Polarization,
+    // 探索する周波数範囲 (Hz)
+    pub fmin: f64,
+    pub fmax: f64,
+    // 給電点と観測点の数
+    pub sources: usize,
+    pub probes: usize,
+    // k点毎の計算ステップ数
+    pub steps: usize,
+    // 給電点と観測点の配置の乱数の種
+    pub seed: u64,
+    // Harminvの誤差の推定値がこれを超えるモードは除く
+    pub max_error: f64,
+}
+
+impl BandSolver {
+    pub fn new(polarization: Polarization, fmin: f64, fmax: f64) -> BandSolver {
+        BandSolver{polarization, fmin, fmax, sources: 4, probes: 4, steps: 4000, seed: 1, max_error: 1e-4}
+    }
+
+    pub fn sources(mut self, n: usize) -> BandSolver {
+        self.sources = n.max(1);
+        self
+    }
+
+    pub fn probes(mut self, n: usize) -> BandSolver {
+        self.probes = n.max(1);
+        self
+    }
+
+    pub fn steps(mut self, n: usize) -> BandSolver {
+        self.steps = n;
+        self
+    }
+
+    pub fn seed(mut self, s: u64) -> BandSolver {
+        self.seed = s;
+        self
+    }
+
+    pub fn max_error(mut self, e: f64) -> BandSolver {
+        self.max_error = e;
+        self
+    }
+
+    // 帯域全体を覆う給電パルス (搬送波は帯域の中心)
+    fn pulse(&self) -> Source {
+        let d = 1.0 / (PI * 0.5 * (self.fmax - self.fmin));
+        Source::new(0, 0).pulse(d, 4.0 * d).carrier(0.5 * (self.fmin + self.fmax))
+    }
+
+    // k点 points (逆格子の単位) 毎に build で生成した単位セル (媒質, 完全導体を設定済み, PML層数0) を計算する
+    pub fn solve<B>(&self, points: &[(f64, f64)], mut build: B) -> Result<Bands>
+        where B: FnMut() -> Result<fdtd>
+    {
+        if !self.fmin.is_finite() || !self.fmax.is_finite() || self.fmin < 0.0 || self.fmax <= self.fmin {
+            return Err(Error::InvalidValue{what: "band frequency range", value: self.fmax});
+        }
+        if self.sources == 0 {
+            return Err(Error::InvalidValue{what: "number of band sources", value: 0.0});
+        }
+        if self.probes == 0 {
+            return Err(Error::InvalidValue{what: "number of band probes", value: 0.0});
+        }
+        let pulse = self.pulse();
+        let harminv = Harminv::new(self.fmin, self.fmax).t_start(pulse.t0 + 4.0 * pulse.duration);
+        let c = self.polarization.component();
+        let mut period = (0.0, 0.0);
+        let mut modes = Vec::with_capacity(points.len());
+
+        for (n, &(kx, ky)) in points.iter().enumerate() {
+            let (mut re, mut im) = (build()?, build()?);
+            if re.lpml() != 0 || im.lpml() != 0 {
+                return Err(Error::Unsupported{what: "PML with bloch boundaries"});
+            }
+            re.require_uniform("bloch boundaries on a graded mesh")?;
+            let (nx, ny) = re.dims();
+            if nx < 3 || ny < 3 || im.dims() != (nx, ny) {
+                return Err(Error::InvalidValue{what: "unit cell size", value: nx.min(ny) as f64});
+            }
+            let (dx, dy) = re.cell_size();
+            period = ((nx - 2) as f64 * dx, (ny - 2) as f64 * dy);
+
+            // 配置は全てのk点で同じにする (バンドの連続性を保つため)
+            let mut rng = Random::new(self.seed);
+            let mut feeds = Vec::with_capacity(self.sources);
+            for _ in 0..self.sources {
+                let mut s = pulse;
+                s.x = 1 + rng.below(nx - 2);
+                s.y = 1 + rng.below(ny - 2);
+                s.amplitude = if rng.uniform() < 0.5 { -1.0 } else { 1.0 } * rng.range(0.5, 1.0);
+                feeds.push(s);
+            }
+            for _ in 0..self.probes {
+                let (x, y) = (1 + rng.below(nx - 2), 1 + rng.below(ny - 2));
+                re.add_probe(Probe::new(c, x, y))?;
+                im.add_probe(Probe::new(c, x, y))?;
+            }
+
+            let theta = (2.0 * PI * kx, 2.0 * PI * ky);
+            let (e, h) = ([Component::Ex, Component::Ey, Component::Ez], [Component::Hx, Component::Hy, Component::Hz]);
+            for _ in 0..self.steps {
+                re.step_e();
+                im.step_e();
+                if self.polarization == Polarization::Te {
+                    inject(&mut re, c, &feeds);
+                }
+                bloch(&mut re, &mut im, &e, theta);
+                re.step_h();
+                im.step_h();
+                if self.polarization == Polarization::Tm {
+                    inject(&mut re, c, &feeds);
+                }
+                bloch(&mut re, &mut im, &h, theta);
+            }
+
+            // 全ての観測点の実部と虚部の和を解析する
+            let (pr, pi) = (re.probes(), im.probes());
+            let dt = pr[0].dt();
+            let mut signal = vec![0.0; pr[0].values.len()];
+            for p in pr.iter().chain(pi) {
+                for (s, v) in signal.iter_mut().zip(&p.values) {
+                    *s += v;
+                }
+            }
+            let t0 = pr[0].times.first().cloned().unwrap_or(0.0);
+            let h = Harminv{t_start: harminv.t_start - t0, ..harminv.clone()};
+            let found: Vec<Mode> = h.analyze(&signal, dt)?.into_iter()
+                .filter(|m| m.error <= self.max_error && m.freq > 0.0)
+                .collect();
+            log_info!("k point {}/{} ({}, {}): {} bands", n + 1, points.len(), kx, ky, found.len());
+            modes.push(found);
+        }
+
+        Ok(Bands{polarization: self.polarization, points: points.to_vec(), period, modes})
+    }
+}
+
+// 実部の成分cに給電波形を加える (時刻は解析モジュールの現在の時刻)
+fn inject(f: &mut fdtd, c: Component, feeds: &[Source]) {
+    let (t, dt) = (f.time(), f.dt);
+    let field = f.field_mut(c);
+    for s in feeds {
+        field[s.x][s.y] += s.waveform(t, dt);
+    }
+}
+
+// 成分 comps の外周の1層を反対側の内側の値 × exp(±jθ) にする
+fn bloch(re: &mut fdtd, im: &mut fdtd, comps: &[Component], theta: (f64, f64)) {
+    let (cx, sx, cy, sy) = (theta.0.cos(), theta.0.sin(), theta.1.cos(), theta.1.sin());
+    for &c in comps {
+        let (r, i) = (re.field_mut(c), im.field_mut(c));
+        let (nx, ny) = (r.len(), r[0].len());
+        // 回転 (a + jb) exp(±jθ)
+        let rot = |a: f64, b: f64, cs: f64, sn: f64| (a * cs - b * sn, a * sn + b * cs);
+        for y in 0..ny {
+            let (a, b) = rot(r[nx - 2][y], i[nx - 2][y], cx, -sx);
+            r[0][y] = a;
+            i[0][y] = b;
+            let (a, b) = rot(r[1][y], i[1][y], cx, sx);
+            r[nx - 1][y] = a;
+            i[nx - 1][y] = b;
+        }
+        for x in 0..nx {
+            let (a, b) = rot(r[x][ny - 2], i[x][ny - 2], cy, -sy);
+            r[x][0] = a;
+            i[x][0] = b;
+            let (a, b) = rot(r[x][1], i[x][1], cy, sy);
+            r[x][ny - 1] = a;
+            i[x][ny - 1] = b;
+        }
+    }
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct Bands {
+    pub polarization: Polarization,
+    // k点 (逆格子の単位)
+    pub points: Vec<(f64, f64)>,
+    // 単位セルの周期 (m)
+    pub period: (f64, f64),
+    // k点毎のモード (周波数の昇順)
+    pub modes: Vec<Vec<Mode>>,
+}
+
+impl Bands {
+    // k点の経路に沿った距離 (2π/a の単位)
+    pub fn distance(&self) -> Vec<f64> {
+        let (a, b) = self.period;
+        let r = if b > 0.0 { a / b } else { 1.0 };
+        let mut d = 0.0;
+        let mut out = Vec::with_capacity(self.points.len());
+        for (k, p) in self.points.iter().enumerate() {
+            if k > 0 {
+                let q = self.points[k - 1];
+                d += ((p.0 - q.0).powi(2) + ((p.1 - q.1) * r).powi(2)).sqrt();
+            }
+            out.push(d);
+        }
+        out
+    }
+
+    // 規格化周波数 f a / c
+    pub fn normalized(&self, freq: f64) -> f64 {
+        freq * self.period.0 / C
+    }
+
+    // k,kx,ky,distance,band,freq,normalized,Q,error のCSV (1行に1モード)
+    pub fn write_csv<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "k,kx,ky,distance,band,freq,normalized,Q,error")?;
+        for (k, ((p, d), modes)) in self.points.iter().zip(self.distance()).zip(&self.modes).enumerate() {
+            for (n, m) in modes.iter().enumerate() {
+                writeln!(w, "{},{:e},{:e},{:e},{},{:e},{:e},{:e},{:e}", k, p.0, p.1, d, n, m.freq, self.normalized(m.freq), m.q, m.error)?;
+            }
+        }
+        Ok(())
+    }
+}
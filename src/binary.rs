@@ -0,0 +1,403 @@
+/*
+ * This software is released under the MIT License.
+ * http://opensource.org/licenses/mit-license.php
+ */
+
+// バイナリ出力 (NumPy .npy/.npz, リトルエンディアンの生データ + JSONメタデータ)
+//
+// Pythonからの読み込み例
+//   np.load("EData1.npy"), np.load("probe.npz")["values"]
+//   m = json.load(open("EData1.json"))        (RawBackend: スナップショット毎に1組)
+//   np.fromfile("EData1.raw", dtype=m["dtype"]).reshape(m["shape"])
+//   export_raw は配列毎に "stem_名前.raw" と "stem_名前.json" を出力する
+
+use std::fs::File;
+use std::io::{BufWriter, Result, Write};
+use std::path::{Path, PathBuf};
+
+use complex::Complex;
+use monitor::{DftMonitor, Probe};
+use snapshot::{LineEnding, Snapshot, SnapshotBackend};
+
+// 出力精度
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Dtype {
+    F32,
+    F64,
+}
+
+impl Dtype {
+    // NumPyの型記述子
+    pub fn descr(&self, complex: bool) -> &'static str {
+        match (*self, complex) {
+            (Dtype::F32, false) => "<f4",
+            (Dtype::F64, false) => "<f8",
+            (Dtype::F32, true) => "<c8",
+            (Dtype::F64, true) => "<c16",
+        }
+    }
+}
+
+#[derive(Clone,Debug)]
+pub enum ArrayData {
+    Real(Vec<f64>),
+    Complex(Vec<Complex>),
+}
+
+// 名前付き多次元配列 (C順, 最後の軸が最も速く変化)
+#[derive(Clone,Debug)]
+pub struct Array {
+    pub name: String,
+    pub shape: Vec<usize>,
+    pub data: ArrayData,
+}
+
+impl Array {
+    pub fn real(name: &str, shape: &[usize], data: Vec<f64>) -> Array {
+        Array{name: name.to_string(), shape: shape.to_vec(), data: ArrayData::Real(data)}
+    }
+
+    pub fn complex(name: &str, shape: &[usize], data: Vec<Complex>) -> Array {
+        Array{name: name.to_string(), shape: shape.to_vec(), data: ArrayData::Complex(data)}
+    }
+
+    pub fn is_complex(&self) -> bool {
+        match self.data {
+            ArrayData::Real(_) => false,
+            ArrayData::Complex(_) => true,
+        }
+    }
+
+    // リトルエンディアンのバイト列
+    pub fn to_bytes(&self, dtype: Dtype) -> Vec<u8> {
+        let vals: Vec<f64> = match self.data {
+            ArrayData::Real(ref v) => v.clone(),
+            ArrayData::Complex(ref v) => v.iter().flat_map(|c| vec![c.re, c.im]).collect(),
+        };
+        let mut b = Vec::with_capacity(vals.len() * 8);
+        for v in vals {
+            match dtype {
+                Dtype::F32 => b.extend_from_slice(&(v as f32).to_le_bytes()),
+                Dtype::F64 => b.extend_from_slice(&v.to_le_bytes()),
+            }
+        }
+        b
+    }
+
+    // to_bytesの逆変換
+    pub fn from_bytes(name: &str, shape: &[usize], b: &[u8], dtype: Dtype, complex: bool) -> Array {
+        let vals: Vec<f64> = match dtype {
+            Dtype::F32 => b.chunks(4).map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]) as f64).collect(),
+            Dtype::F64 => b.chunks(8).map(|c| f64::from_le_bytes([c[0], c[1], c[2], c[3], c[4], c[5], c[6], c[7]])).collect(),
+        };
+        if complex {
+            Array::complex(name, shape, vals.chunks(2).map(|c| Complex::new(c[0], c[1])).collect())
+        } else {
+            Array::real(name, shape, vals)
+        }
+    }
+}
+
+// 座標の間隔 (点が1つなら0, 不均一メッシュで等間隔でなければNaN. JSONではnullになるので座標列x, yを使う)
+pub fn spacing(v: &[f64]) -> f64 {
+    if v.len() < 2 {
+        return 0.0;
+    }
+    let d = v[1] - v[0];
+    if v.windows(2).all(|p| ((p[1] - p[0]) - d).abs() <= 1e-9 * d.abs()) { d } else { f64::NAN }
+}
+
+// 配列に付随するメタデータ (spacing, originは軸毎)
+#[derive(Clone,Debug,Default)]
+pub struct Metadata {
+    pub component: String,
+    pub axes: Vec<String>,
+    pub spacing: Vec<f64>,
+    pub origin: Vec<f64>,
+    pub time: Option<f64>,
+    pub step: Option<usize>,
+    // 追加の数値列 (周波数など)
+    pub extra: Vec<(String, Vec<f64>)>,
+}
+
+// バイナリ出力可能なデータ
+pub trait Export {
+    fn arrays(&self) -> Vec<Array>;
+    fn metadata(&self) -> Metadata;
+}
+
+impl Export for Snapshot {
+    fn arrays(&self) -> Vec<Array> {
+        let shape = [self.ys.len(), self.xs.len()];
+        let mut a: Vec<Array> = self.components.iter()
+            .map(|(c, v)| Array::real(c.name(), &shape, v.clone()))
+            .collect();
+        for (m, v) in &self.materials {
+            a.push(Array::real(m.name(), &shape, v.clone()));
+        }
+        a.push(Array::real("x", &[self.xs.len()], self.xs.clone()));
+        a.push(Array::real("y", &[self.ys.len()], self.ys.clone()));
+        a
+    }
+
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            component: self.components.iter().map(|c| c.0.name()).collect::<Vec<_>>().join(","),
+            axes: vec!["y".to_string(), "x".to_string()],
+            spacing: vec![spacing(&self.ys), spacing(&self.xs)],
+            origin: vec![self.ys.first().cloned().unwrap_or(0.0), self.xs.first().cloned().unwrap_or(0.0)],
+            time: Some(self.time),
+            step: Some(self.step),
+            extra: vec![("x".to_string(), self.xs.clone()), ("y".to_string(), self.ys.clone())],
+        }
+    }
+}
+
+impl Export for Probe {
+    fn arrays(&self) -> Vec<Array> {
+        vec![Array::real("values", &[self.values.len()], self.values.clone()),
+             Array::real("t", &[self.times.len()], self.times.clone())]
+    }
+
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            component: self.component.name().to_string(),
+            axes: vec!["t".to_string()],
+            spacing: vec![self.dt()],
+            origin: vec![self.times.first().cloned().unwrap_or(0.0)],
+            time: self.times.last().cloned(),
+            step: Some(self.values.len()),
+            extra: vec![("position".to_string(), vec![self.x as f64, self.y as f64])],
+        }
+    }
+}
+
+impl Export for DftMonitor {
+    fn arrays(&self) -> Vec<Array> {
+        let data = self.data.iter().flat_map(|d| d.iter().cloned()).collect();
+        vec![Array::complex(self.component.name(), &[self.freqs.len(), self.height(), self.width()], data),
+             Array::real("freqs", &[self.freqs.len()], self.freqs.clone()),
+             Array::real("x", &[self.xs.len()], self.xs.clone()),
+             Array::real("y", &[self.ys.len()], self.ys.clone())]
+    }
+
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            component: self.component.name().to_string(),
+            axes: vec!["f".to_string(), "y".to_string(), "x".to_string()],
+            spacing: vec![0.0, spacing(&self.ys), spacing(&self.xs)],
+            origin: vec![0.0, self.ys.first().cloned().unwrap_or(0.0), self.xs.first().cloned().unwrap_or(0.0)],
+            time: None,
+            step: None,
+            extra: vec![("freqs".to_string(), self.freqs.clone()), ("x".to_string(), self.xs.clone()), ("y".to_string(), self.ys.clone())],
+        }
+    }
+}
+
+// .npy形式 (version 1.0)
+pub fn write_npy<W: Write>(w: &mut W, a: &Array, dtype: Dtype) -> Result<()> {
+    let shape = match a.shape.len() {
+        1 => format!("({},)", a.shape[0]),
+        _ => format!("({})", a.shape.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(", ")),
+    };
+    let mut header = format!("{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}", dtype.descr(a.is_complex()), shape);
+    // マジック(6) + バージョン(2) + 長さ(2) + ヘッダ + 改行 を64バイト境界に揃える
+    let pad = 64 - (10 + header.len() + 1) % 64;
+    header.push_str(&" ".repeat(pad % 64));
+    header.push('\n');
+
+    w.write_all(b"\x93NUMPY\x01\x00")?;
+    w.write_all(&(header.len() as u16).to_le_bytes())?;
+    w.write_all(header.as_bytes())?;
+    w.write_all(&a.to_bytes(dtype))
+}
+
+pub fn save_npy<P: AsRef<Path>>(path: P, a: &Array, dtype: Dtype) -> Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    write_npy(&mut w, a, dtype)?;
+    w.flush()
+}
+
+// .npz形式 (無圧縮zip, 各配列を"名前.npy"として格納)
+pub fn save_npz<P: AsRef<Path>>(path: P, arrays: &[Array], dtype: Dtype) -> Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    let mut central = Vec::new();
+    let mut offset: u32 = 0;
+
+    for a in arrays {
+        let mut body = Vec::new();
+        write_npy(&mut body, a, dtype)?;
+        let name = format!("{}.npy", a.name);
+        let crc = crc32(&body);
+
+        let mut local = Vec::new();
+        local.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        zip_common(&mut local, crc, body.len() as u32, name.len() as u16);
+        local.extend_from_slice(&0u16.to_le_bytes()); // extra
+        local.extend_from_slice(name.as_bytes());
+        w.write_all(&local)?;
+        w.write_all(&body)?;
+
+        central.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        central.extend_from_slice(&20u16.to_le_bytes()); // version made by
+        zip_common(&mut central, crc, body.len() as u32, name.len() as u16);
+        central.extend_from_slice(&[0u8; 12]); // extra, comment, disk, internal attr, external attr
+        central.extend_from_slice(&offset.to_le_bytes());
+        central.extend_from_slice(name.as_bytes());
+
+        offset += (local.len() + body.len()) as u32;
+    }
+
+    w.write_all(&central)?;
+    let mut end = Vec::new();
+    end.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+    end.extend_from_slice(&[0u8; 4]); // disk
+    end.extend_from_slice(&(arrays.len() as u16).to_le_bytes());
+    end.extend_from_slice(&(arrays.len() as u16).to_le_bytes());
+    end.extend_from_slice(&(central.len() as u32).to_le_bytes());
+    end.extend_from_slice(&offset.to_le_bytes());
+    end.extend_from_slice(&0u16.to_le_bytes());
+    w.write_all(&end)?;
+    w.flush()
+}
+
+// ローカルヘッダとセントラルディレクトリの共通部分
+fn zip_common(b: &mut Vec<u8>, crc: u32, size: u32, name_len: u16) {
+    b.extend_from_slice(&20u16.to_le_bytes()); // version needed
+    b.extend_from_slice(&0u16.to_le_bytes()); // flags
+    b.extend_from_slice(&0u16.to_le_bytes()); // 無圧縮
+    b.extend_from_slice(&0u16.to_le_bytes()); // 時刻
+    b.extend_from_slice(&0x21u16.to_le_bytes()); // 日付 1980-01-01
+    b.extend_from_slice(&crc.to_le_bytes());
+    b.extend_from_slice(&size.to_le_bytes());
+    b.extend_from_slice(&size.to_le_bytes());
+    b.extend_from_slice(&name_len.to_le_bytes());
+}
+
+// CRC-32 (IEEE)
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+// 生データ(.raw)とJSONメタデータ(.json)
+pub fn save_raw<P: AsRef<Path>>(path: P, a: &Array, meta: &Metadata, dtype: Dtype) -> Result<()> {
+    let path = path.as_ref();
+    let mut w = BufWriter::new(File::create(path)?);
+    w.write_all(&a.to_bytes(dtype))?;
+    w.flush()?;
+
+    let mut j = BufWriter::new(File::create(path.with_extension("json"))?);
+    j.write_all(sidecar_json(a, meta, dtype).as_bytes())?;
+    j.flush()
+}
+
+pub fn sidecar_json(a: &Array, meta: &Metadata, dtype: Dtype) -> String {
+    let mut s = String::from("{\n");
+    s += &format!("  \"name\": \"{}\",\n", a.name);
+    s += &format!("  \"component\": \"{}\",\n", meta.component);
+    s += &format!("  \"dtype\": \"{}\",\n", dtype.descr(a.is_complex()));
+    s += &format!("  \"shape\": [{}],\n", a.shape.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(", "));
+    s += &format!("  \"axes\": [{}],\n", meta.axes.iter().map(|n| format!("\"{}\"", n)).collect::<Vec<_>>().join(", "));
+    s += &format!("  \"spacing\": {},\n", json_list(&meta.spacing));
+    s += &format!("  \"origin\": {},\n", json_list(&meta.origin));
+    for (k, v) in &meta.extra {
+        s += &format!("  \"{}\": {},\n", k, json_list(v));
+    }
+    s += &format!("  \"step\": {},\n", meta.step.map(|n| n.to_string()).unwrap_or_else(|| "null".to_string()));
+    s += &format!("  \"time\": {}\n", meta.time.map(json_f64).unwrap_or_else(|| "null".to_string()));
+    s += "}\n";
+    s
+}
+
+fn json_f64(v: f64) -> String {
+    if v.is_finite() { format!("{:e}", v) } else { "null".to_string() }
+}
+
+fn json_list(v: &[f64]) -> String {
+    format!("[{}]", v.iter().map(|&x| json_f64(x)).collect::<Vec<_>>().join(", "))
+}
+
+// 全配列を1つの.npzへ出力
+pub fn export_npz<P: AsRef<Path>, E: Export>(path: P, e: &E, dtype: Dtype) -> Result<()> {
+    save_npz(path, &e.arrays(), dtype)
+}
+
+// 配列毎に "stem_名前.raw" と "stem_名前.json" を出力
+pub fn export_raw<E: Export>(dir: &Path, stem: &str, e: &E, dtype: Dtype) -> Result<()> {
+    let meta = e.metadata();
+    for a in e.arrays() {
+        save_raw(dir.join(format!("{}_{}.raw", stem, a.name)), &a, &meta, dtype)?;
+    }
+    Ok(())
+}
+
+// スナップショットの成分のみを1配列にまとめる (成分が複数なら [成分, y, x])
+fn stack_components(snap: &Snapshot) -> Array {
+    let mut shape = vec![snap.ys.len(), snap.xs.len()];
+    if snap.components.len() > 1 {
+        shape.insert(0, snap.components.len());
+    }
+    let data = snap.components.iter().flat_map(|c| c.1.iter().cloned()).collect();
+    Array::real("field", &shape, data)
+}
+
+// .npy出力
+#[derive(Clone,Copy,Debug)]
+pub struct NpyBackend {
+    pub dtype: Dtype,
+}
+
+impl SnapshotBackend for NpyBackend {
+    fn extension(&self) -> &str {
+        "npy"
+    }
+
+    fn write(&mut self, path: &Path, snap: &Snapshot, _eol: LineEnding) -> Result<()> {
+        save_npy(path, &stack_components(snap), self.dtype)
+    }
+}
+
+// .npz出力 (成分毎の配列と座標x, y)
+#[derive(Clone,Copy,Debug)]
+pub struct NpzBackend {
+    pub dtype: Dtype,
+}
+
+impl SnapshotBackend for NpzBackend {
+    fn extension(&self) -> &str {
+        "npz"
+    }
+
+    fn write(&mut self, path: &Path, snap: &Snapshot, _eol: LineEnding) -> Result<()> {
+        export_npz(path, snap, self.dtype)
+    }
+}
+
+// 生データ出力 (成分を1ファイルにまとめ, 同名の.jsonを付加)
+#[derive(Clone,Copy,Debug)]
+pub struct RawBackend {
+    pub dtype: Dtype,
+}
+
+impl SnapshotBackend for RawBackend {
+    fn extension(&self) -> &str {
+        "raw"
+    }
+
+    fn write(&mut self, path: &Path, snap: &Snapshot, _eol: LineEnding) -> Result<()> {
+        let mut meta = snap.metadata();
+        if snap.components.len() > 1 {
+            meta.axes.insert(0, "component".to_string());
+            meta.spacing.insert(0, 0.0);
+            meta.origin.insert(0, 0.0);
+        }
+        save_raw(PathBuf::from(path), &stack_components(snap), &meta, self.dtype)
+    }
+}
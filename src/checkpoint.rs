@@ -0,0 +1,89 @@
+/*
+ * This software is released under the MIT License.
+ * http://opensource.org/licenses/mit-license.php
+ */
+
+// 計算状態の保存と再開
+//
+// ファイル構成: "RFDTDCKP" + u32 バージョン(VERSION) + fdtd::write_state の内容
+// 場, PMLの分離成分, 係数, 媒質, 給電点, 観測点とDFTモニタの積算値, ステップ数を全て含むため,
+// 読み込んだ状態から計算を続けると中断しなかった場合とビット単位で一致する
+// (Observerは保存されないので再登録が必要)
+
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Result, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use fdtd2d::{fdtd, fdtdBuilder};
+use observer::{Control, Observer};
+
+const MAGIC: &[u8; 8] = b"RFDTDCKP";
+const VERSION: u32 = 5;
+
+// 一時ファイルに書いてから置き換えるため, 書き込み中に止まっても前回の保存は残る
+pub fn save_checkpoint<P: AsRef<Path>>(path: P, f: &fdtd) -> Result<()> {
+    let path = path.as_ref();
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    {
+        let mut w = BufWriter::new(File::create(&tmp)?);
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        f.write_state(&mut w)?;
+        w.flush()?;
+        w.get_ref().sync_all()?;
+    }
+    fs::rename(&tmp, path)
+}
+
+pub fn load_checkpoint<P: AsRef<Path>>(path: P) -> Result<fdtd> {
+    let mut r = BufReader::new(File::open(path)?);
+    let mut head = [0u8; 12];
+    r.read_exact(&mut head)?;
+    if &head[..8] != MAGIC {
+        return Err(Error::new(ErrorKind::InvalidData, "not an Rfdtd checkpoint file"));
+    }
+    let version = u32::from_le_bytes([head[8], head[9], head[10], head[11]]);
+    if version != VERSION {
+        return Err(Error::new(ErrorKind::InvalidData, format!("unsupported checkpoint version {}", version)));
+    }
+    fdtdBuilder::from_state(&mut r)
+}
+
+// nステップ毎に保存するObserver (保存に失敗した場合はエラーを記録して計算を止める)
+pub struct Checkpointer {
+    path: PathBuf,
+    every: usize,
+    error: Rc<RefCell<Option<Error>>>,
+}
+
+impl Checkpointer {
+    pub fn new<P: AsRef<Path>>(path: P, every: usize) -> Checkpointer {
+        Checkpointer{path: path.as_ref().to_path_buf(), every, error: Rc::new(RefCell::new(None))}
+    }
+
+    // 保存エラーの受け取り口 (solverへ登録した後も読めるように, 登録前に取り出しておく)
+    pub fn error(&self) -> Rc<RefCell<Option<Error>>> {
+        self.error.clone()
+    }
+}
+
+impl Observer for Checkpointer {
+    fn interval(&self) -> usize {
+        self.every
+    }
+
+    fn every(&mut self, f: &fdtd) -> Control {
+        match save_checkpoint(&self.path, f) {
+            Ok(()) => Control::Continue,
+            Err(e) => {
+                log_error!("{}: checkpoint save failed: {}", self.path.display(), e);
+                *self.error.borrow_mut() = Some(e);
+                Control::Stop
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+/*
+ * This software is released under the MIT License.
+ * http://opensource.org/licenses/mit-license.php
+ */
+
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
+
+// 複素数
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub fn new(re: f64, im: f64) -> Complex {
+        Complex{re, im}
+    }
+
+    // r * exp(j theta)
+    pub fn from_polar(r: f64, theta: f64) -> Complex {
+        Complex{re: r * theta.cos(), im: r * theta.sin()}
+    }
+
+    pub fn conj(&self) -> Complex {
+        Complex{re: self.re, im: -self.im}
+    }
+
+    pub fn norm_sqr(&self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    pub fn abs(&self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    pub fn arg(&self) -> f64 {
+        self.im.atan2(self.re)
+    }
+
+    pub fn exp(&self) -> Complex {
+        Complex::from_polar(self.re.exp(), self.im)
+    }
+
+    pub fn sqrt(&self) -> Complex {
+        Complex::from_polar(self.abs().sqrt(), 0.5 * self.arg())
+    }
+
+    pub fn ln(&self) -> Complex {
+        Complex{re: self.abs().ln(), im: self.arg()}
+    }
+
+    pub fn scale(&self, a: f64) -> Complex {
+        Complex{re: self.re * a, im: self.im * a}
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, o: Complex) -> Complex {
+        Complex{re: self.re + o.re, im: self.im + o.im}
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+    fn sub(self, o: Complex) -> Complex {
+        Complex{re: self.re - o.re, im: self.im - o.im}
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+    fn mul(self, o: Complex) -> Complex {
+        Complex{re: self.re * o.re - self.im * o.im, im: self.re * o.im + self.im * o.re}
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+    fn div(self, o: Complex) -> Complex {
+        let d = o.norm_sqr();
+        Complex{re: (self.re * o.re + self.im * o.im) / d, im: (self.im * o.re - self.re * o.im) / d}
+    }
+}
+
+impl Mul<f64> for Complex {
+    type Output = Complex;
+    fn mul(self, a: f64) -> Complex {
+        self.scale(a)
+    }
+}
+
+impl Neg for Complex {
+    type Output = Complex;
+    fn neg(self) -> Complex {
+        Complex{re: -self.re, im: -self.im}
+    }
+}
+
+impl AddAssign for Complex {
+    fn add_assign(&mut self, o: Complex) {
+        self.re += o.re;
+        self.im += o.im;
+    }
+}
+
+impl SubAssign for Complex {
+    fn sub_assign(&mut self, o: Complex) {
+        self.re -= o.re;
+        self.im -= o.im;
+    }
+}
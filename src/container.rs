@@ -0,0 +1,487 @@
+/*
+ * This software is released under the MIT License.
+ * http://opensource.org/licenses/mit-license.php
+ */
+
+// 解析結果をまとめて格納する階層型コンテナ (.rfd)
+//
+// ファイル構成 (数値は全てリトルエンディアン)
+//   ヘッダ    : "RFDTDRES" + u32 バージョン(1)
+//   チャンク* : "CHNK" + u64 以降のバイト数 + 本体
+//     本体    : u16 パス長 + パス("/probes/p0/values" 等)
+//               u16 属性数 + 属性* (u16 名前長 + 名前 + u8 型 + 値)
+//                 型 0:f64, 1:i64, 2:文字列(u32 長さ + UTF-8), 3:f64列(u32 個数 + f64*)
+//               u8 データ型 (0:なし, 1:f32, 2:f64, 3:complex64, 4:complex128)
+//               u8 次元数 + u64 各次元の長さ
+//               データ (C順)
+//   索引      : "INDX" + u64 エントリ数 + (u16 パス長 + パス + u64 チャンク位置)*
+//   末尾      : u64 索引の位置 + "RFDTDEND"
+//
+// 計算が途中で止まり索引が無い場合も、先頭からチャンクを辿って読み込める
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use binary::{spacing, Array, ArrayData, Dtype, Export, Metadata};
+use fdtd2d::{fdtd, Component, Material};
+use snapshot::Snapshot;
+use vtk::ALL_MATERIALS;
+
+const MAGIC: &[u8; 8] = b"RFDTDRES";
+const END: &[u8; 8] = b"RFDTDEND";
+const VERSION: u32 = 1;
+
+// 属性値
+#[derive(Clone,Debug,PartialEq)]
+pub enum Attr {
+    Num(f64),
+    Int(i64),
+    Text(String),
+    Nums(Vec<f64>),
+}
+
+// 書き込み
+pub struct ResultWriter {
+    w: BufWriter<File>,
+    pos: u64,
+    index: Vec<(String, u64)>,
+    dtype: Dtype,
+}
+
+impl ResultWriter {
+    pub fn create<P: AsRef<Path>>(path: P, dtype: Dtype) -> Result<ResultWriter> {
+        let mut w = BufWriter::new(File::create(path)?);
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        Ok(ResultWriter{w, pos: 12, index: Vec::new(), dtype})
+    }
+
+    // 1エントリ (属性のみの場合はarrayをNone)
+    pub fn write(&mut self, path: &str, attrs: &[(&str, Attr)], array: Option<&Array>) -> Result<()> {
+        let mut b = Vec::new();
+        put_str(&mut b, path);
+        b.extend_from_slice(&(attrs.len() as u16).to_le_bytes());
+        for (k, v) in attrs {
+            put_str(&mut b, k);
+            match *v {
+                Attr::Num(x) => {
+                    b.push(0);
+                    b.extend_from_slice(&x.to_le_bytes());
+                }
+                Attr::Int(x) => {
+                    b.push(1);
+                    b.extend_from_slice(&x.to_le_bytes());
+                }
+                Attr::Text(ref s) => {
+                    b.push(2);
+                    b.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                    b.extend_from_slice(s.as_bytes());
+                }
+                Attr::Nums(ref v) => {
+                    b.push(3);
+                    b.extend_from_slice(&(v.len() as u32).to_le_bytes());
+                    for x in v {
+                        b.extend_from_slice(&x.to_le_bytes());
+                    }
+                }
+            }
+        }
+        match array {
+            Some(a) => {
+                b.push(dtype_code(self.dtype, a.is_complex()));
+                b.push(a.shape.len() as u8);
+                for &n in &a.shape {
+                    b.extend_from_slice(&(n as u64).to_le_bytes());
+                }
+                b.extend_from_slice(&a.to_bytes(self.dtype));
+            }
+            None => b.extend_from_slice(&[0, 0]),
+        }
+
+        self.index.push((path.to_string(), self.pos));
+        self.w.write_all(b"CHNK")?;
+        self.w.write_all(&(b.len() as u64).to_le_bytes())?;
+        self.w.write_all(&b)?;
+        self.pos += 12 + b.len() as u64;
+        Ok(())
+    }
+
+    // 解析条件 "/params"
+    pub fn write_params(&mut self, f: &fdtd) -> Result<()> {
+        let attrs: Vec<(&str, Attr)> = f.params().into_iter().map(|(k, v)| (k, Attr::Num(v))).collect();
+        self.write("/params", &attrs, None)
+    }
+
+    // 媒質定数 "/geometry/epsd" 等 (PMLを含む全セル [y, x])
+    pub fn write_geometry(&mut self, f: &fdtd) -> Result<()> {
+        let (nx, ny) = f.dims();
+        let xs: Vec<f64> = (0..nx).map(|x| f.position(x, 0).0).collect();
+        let ys: Vec<f64> = (0..ny).map(|y| f.position(0, y).1).collect();
+        for &m in ALL_MATERIALS.iter() {
+            let mut v = Vec::with_capacity(nx * ny);
+            for y in 0..ny {
+                for x in 0..nx {
+                    v.push(f.material(m, x, y));
+                }
+            }
+            let a = Array::real(m.name(), &[ny, nx], v);
+            self.write(&format!("/geometry/{}", m.name()),
+                       &[("spacing", Attr::Nums(vec![spacing(&ys), spacing(&xs)])), ("origin", Attr::Nums(vec![ys[0], xs[0]])),
+                         ("x", Attr::Nums(xs.clone())), ("y", Attr::Nums(ys.clone()))], Some(&a))?;
+        }
+        Ok(())
+    }
+
+    // 給電点 "/sources/0" 等
+    pub fn write_sources(&mut self, f: &fdtd) -> Result<()> {
+        for (n, s) in f.sources().iter().enumerate() {
+            self.write(&format!("/sources/{}", n),
+                       &[("x", Attr::Int(s.x as i64)), ("y", Attr::Int(s.y as i64)),
+                         ("duration", Attr::Num(s.duration)), ("t0", Attr::Num(s.t0)),
+                         ("freq", Attr::Num(s.freq)), ("amplitude", Attr::Num(s.amplitude)),
+                         ("resistance", Attr::Num(s.resistance)), ("length", Attr::Num(s.length))], None)?;
+        }
+        Ok(())
+    }
+
+    // 観測点, DFTモニタ, スナップショット等を "group/配列名" として格納 (メタデータはgroupの属性)
+    pub fn write_export<E: Export>(&mut self, group: &str, e: &E) -> Result<()> {
+        let m = e.metadata();
+        self.write(group, &meta_attrs(&m), None)?;
+        for a in e.arrays() {
+            self.write(&format!("{}/{}", group, a.name), &[], Some(&a))?;
+        }
+        Ok(())
+    }
+
+    // スナップショット "/snapshots/00000100/ez" 等
+    pub fn write_snapshot(&mut self, snap: &Snapshot) -> Result<()> {
+        self.write_export(&format!("/snapshots/{:08}", snap.step), snap)
+    }
+
+    // 索引と末尾を書いて閉じる
+    pub fn finish(mut self) -> Result<()> {
+        let at = self.pos;
+        self.w.write_all(b"INDX")?;
+        self.w.write_all(&(self.index.len() as u64).to_le_bytes())?;
+        for (p, o) in &self.index {
+            let mut b = Vec::new();
+            put_str(&mut b, p);
+            self.w.write_all(&b)?;
+            self.w.write_all(&o.to_le_bytes())?;
+        }
+        self.w.write_all(&at.to_le_bytes())?;
+        self.w.write_all(END)?;
+        self.w.flush()
+    }
+}
+
+fn meta_attrs(m: &Metadata) -> Vec<(&str, Attr)> {
+    let mut a = vec![("component", Attr::Text(m.component.clone())),
+                     ("axes", Attr::Text(m.axes.join(","))),
+                     ("spacing", Attr::Nums(m.spacing.clone())),
+                     ("origin", Attr::Nums(m.origin.clone()))];
+    if let Some(t) = m.time {
+        a.push(("time", Attr::Num(t)));
+    }
+    if let Some(s) = m.step {
+        a.push(("step", Attr::Int(s as i64)));
+    }
+    for (k, v) in &m.extra {
+        a.push((k.as_str(), Attr::Nums(v.clone())));
+    }
+    a
+}
+
+fn put_str(b: &mut Vec<u8>, s: &str) {
+    b.extend_from_slice(&(s.len() as u16).to_le_bytes());
+    b.extend_from_slice(s.as_bytes());
+}
+
+fn dtype_code(d: Dtype, complex: bool) -> u8 {
+    match (d, complex) {
+        (Dtype::F32, false) => 1,
+        (Dtype::F64, false) => 2,
+        (Dtype::F32, true) => 3,
+        (Dtype::F64, true) => 4,
+    }
+}
+
+// 格納されたエントリ
+#[derive(Clone,Debug)]
+pub struct Entry {
+    pub path: String,
+    pub attrs: Vec<(String, Attr)>,
+    pub shape: Vec<usize>,
+    // データ型と複素数か否か (属性のみの場合None)
+    pub dtype: Option<(Dtype, bool)>,
+    data_pos: u64,
+    data_len: u64,
+}
+
+impl Entry {
+    pub fn attr(&self, key: &str) -> Option<&Attr> {
+        self.attrs.iter().find(|a| a.0 == key).map(|a| &a.1)
+    }
+}
+
+// 読み込み
+pub struct ResultFile {
+    r: BufReader<File>,
+    entries: Vec<Entry>,
+}
+
+impl ResultFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<ResultFile> {
+        let mut r = BufReader::new(File::open(path)?);
+        let mut head = [0u8; 12];
+        r.read_exact(&mut head)?;
+        if &head[..8] != MAGIC {
+            return Err(invalid("not an Rfdtd result file"));
+        }
+        let len = r.seek(SeekFrom::End(0))?;
+
+        // 索引から, 無ければ先頭から順に
+        let mut offsets = Vec::new();
+        let mut tail = [0u8; 16];
+        if len >= 28 {
+            r.seek(SeekFrom::End(-16))?;
+            r.read_exact(&mut tail)?;
+        }
+        if &tail[8..] == END {
+            r.seek(SeekFrom::Start(u64_at(&tail, 0) + 4))?;
+            let n = read_u64(&mut r)?;
+            for _ in 0..n {
+                read_string(&mut r)?;
+                offsets.push(read_u64(&mut r)?);
+            }
+        } else {
+            let mut pos = 12;
+            while pos + 12 <= len {
+                r.seek(SeekFrom::Start(pos))?;
+                let mut tag = [0u8; 4];
+                r.read_exact(&mut tag)?;
+                if &tag != b"CHNK" {
+                    break;
+                }
+                let size = read_u64(&mut r)?;
+                if size > len - pos - 12 {
+                    break;
+                }
+                offsets.push(pos);
+                pos += 12 + size;
+            }
+        }
+
+        let mut entries = Vec::with_capacity(offsets.len());
+        for o in offsets {
+            entries.push(read_entry(&mut r, o, len)?);
+        }
+        Ok(ResultFile{r, entries})
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn entry(&self, path: &str) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.path == path)
+    }
+
+    // prefix直下の子の名前
+    pub fn list(&self, prefix: &str) -> Vec<String> {
+        let p = format!("{}/", prefix.trim_end_matches('/'));
+        let mut v: Vec<String> = Vec::new();
+        for e in &self.entries {
+            if e.path.starts_with(&p) {
+                let c = e.path[p.len()..].split('/').next().unwrap_or("").to_string();
+                if !c.is_empty() && !v.contains(&c) {
+                    v.push(c);
+                }
+            }
+        }
+        v
+    }
+
+    pub fn attr(&self, path: &str, key: &str) -> Option<&Attr> {
+        self.entry(path).and_then(|e| e.attr(key))
+    }
+
+    // 配列の読み込み
+    pub fn read(&mut self, path: &str) -> Result<Array> {
+        let e = match self.entry(path) {
+            Some(e) => e.clone(),
+            None => return Err(Error::new(ErrorKind::NotFound, format!("no entry {}", path))),
+        };
+        let (dtype, complex) = match e.dtype {
+            Some(d) => d,
+            None => return Err(invalid(&format!("{} has no data", path))),
+        };
+        let size = match (dtype, complex) {
+            (Dtype::F32, false) => 4,
+            (Dtype::F64, true) => 16,
+            _ => 8,
+        };
+        if e.shape.iter().try_fold(size, |a: u64, &n| a.checked_mul(n as u64)) != Some(e.data_len) {
+            return Err(invalid(&format!("{}: data size does not match its shape", path)));
+        }
+        self.r.seek(SeekFrom::Start(e.data_pos))?;
+        let mut b = vec![0u8; e.data_len as usize];
+        self.r.read_exact(&mut b)?;
+        let name = path.rsplit('/').next().unwrap_or(path);
+        Ok(Array::from_bytes(name, &e.shape, &b, dtype, complex))
+    }
+
+    // 格納されたスナップショットのグループ名 (ステップ順)
+    pub fn snapshots(&self) -> Vec<String> {
+        let mut v = self.list("/snapshots");
+        v.sort();
+        v
+    }
+
+    // スナップショット "/snapshots/{name}" の読み込み
+    pub fn read_snapshot(&mut self, name: &str) -> Result<Snapshot> {
+        let group = format!("/snapshots/{}", name);
+        let step = match self.attr(&group, "step") {
+            Some(&Attr::Int(s)) => s as usize,
+            _ => return Err(invalid(&format!("{} is not a snapshot", group))),
+        };
+        let time = match self.attr(&group, "time") {
+            Some(&Attr::Num(t)) => t,
+            _ => 0.0,
+        };
+        let real = |a: Array| match a.data {
+            ArrayData::Real(v) => Ok(v),
+            ArrayData::Complex(_) => Err(invalid(&format!("{} is complex", a.name))),
+        };
+        let mut snap = Snapshot{step, time, xs: real(self.read(&format!("{}/x", group))?)?,
+                                ys: real(self.read(&format!("{}/y", group))?)?,
+                                components: Vec::new(), materials: Vec::new()};
+        for c in self.list(&group) {
+            let path = format!("{}/{}", group, c);
+            if let Some(k) = Component::from_name(&c) {
+                snap.components.push((k, real(self.read(&path)?)?));
+            } else if let Some(m) = Material::from_name(&c) {
+                snap.materials.push((m, real(self.read(&path)?)?));
+            }
+        }
+        Ok(snap)
+    }
+}
+
+// at: チャンクの位置, len: ファイル長 (壊れたファイルでも本体の範囲を越えて読まない)
+fn read_entry<R: Read + Seek>(r: &mut R, at: u64, len: u64) -> Result<Entry> {
+    r.seek(SeekFrom::Start(at))?;
+    let mut tag = [0u8; 4];
+    r.read_exact(&mut tag)?;
+    if &tag != b"CHNK" {
+        return Err(invalid("broken chunk"));
+    }
+    let size = read_u64(r)?;
+    if at.checked_add(12).and_then(|p| p.checked_add(size)).is_none_or(|end| end > len) {
+        return Err(invalid("truncated chunk"));
+    }
+    // 本体の残りに n バイト以上あるか
+    let room = |used: u64, n: u64| if used.checked_add(n).is_some_and(|u| u <= size) { Ok(()) } else { Err(invalid("broken chunk")) };
+    let path = read_string(r)?;
+    let mut used = 2 + path.len() as u64;
+
+    let nattr = read_u16(r)?;
+    used += 2;
+    let mut attrs = Vec::with_capacity(nattr as usize);
+    for _ in 0..nattr {
+        let k = read_string(r)?;
+        used += 3 + k.len() as u64;
+        let v = match read_u8(r)? {
+            0 => {
+                used += 8;
+                Attr::Num(f64::from_bits(read_u64(r)?))
+            }
+            1 => {
+                used += 8;
+                Attr::Int(read_u64(r)? as i64)
+            }
+            2 => {
+                let n = read_u32(r)? as usize;
+                room(used, 4 + n as u64)?;
+                let mut b = vec![0u8; n];
+                r.read_exact(&mut b)?;
+                used += 4 + n as u64;
+                Attr::Text(String::from_utf8_lossy(&b).into_owned())
+            }
+            3 => {
+                let n = read_u32(r)? as usize;
+                room(used, 4 + 8 * n as u64)?;
+                let mut v = Vec::with_capacity(n);
+                for _ in 0..n {
+                    v.push(f64::from_bits(read_u64(r)?));
+                }
+                used += 4 + 8 * n as u64;
+                Attr::Nums(v)
+            }
+            _ => return Err(invalid("unknown attribute type")),
+        };
+        attrs.push((k, v));
+    }
+
+    let dtype = match read_u8(r)? {
+        0 => None,
+        1 => Some((Dtype::F32, false)),
+        2 => Some((Dtype::F64, false)),
+        3 => Some((Dtype::F32, true)),
+        4 => Some((Dtype::F64, true)),
+        _ => return Err(invalid("unknown data type")),
+    };
+    let ndim = read_u8(r)?;
+    used += 2;
+    let mut shape = Vec::with_capacity(ndim as usize);
+    for _ in 0..ndim {
+        shape.push(read_u64(r)? as usize);
+        used += 8;
+    }
+
+    let data_len = size.checked_sub(used).ok_or_else(|| invalid("broken chunk"))?;
+    Ok(Entry{path, attrs, shape, dtype, data_pos: at + 12 + used, data_len})
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+fn u64_at(b: &[u8], i: usize) -> u64 {
+    let mut a = [0u8; 8];
+    a.copy_from_slice(&b[i..i + 8]);
+    u64::from_le_bytes(a)
+}
+
+fn read_u8<R: Read>(r: &mut R) -> Result<u8> {
+    let mut b = [0u8; 1];
+    r.read_exact(&mut b)?;
+    Ok(b[0])
+}
+
+fn read_u16<R: Read>(r: &mut R) -> Result<u16> {
+    let mut b = [0u8; 2];
+    r.read_exact(&mut b)?;
+    Ok(u16::from_le_bytes(b))
+}
+
+fn read_u32<R: Read>(r: &mut R) -> Result<u32> {
+    let mut b = [0u8; 4];
+    r.read_exact(&mut b)?;
+    Ok(u32::from_le_bytes(b))
+}
+
+fn read_u64<R: Read>(r: &mut R) -> Result<u64> {
+    let mut b = [0u8; 8];
+    r.read_exact(&mut b)?;
+    Ok(u64::from_le_bytes(b))
+}
+
+fn read_string<R: Read>(r: &mut R) -> Result<String> {
+    let n = read_u16(r)? as usize;
+    let mut b = vec![0u8; n];
+    r.read_exact(&mut b)?;
+    String::from_utf8(b).map_err(|_| invalid("invalid path"))
+}
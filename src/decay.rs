@@ -0,0 +1,102 @@
+/*
+ * This software is released under the MIT License.
+ * http://opensource.org/licenses/mit-license.php
+ */
+
+// 場の減衰による自動終了
+//
+// interval ステップ毎に指標(全エネルギー, または観測点の値の2乗和)を計算し,
+// 直近 window ステップの最大値がそれまでのピークから threshold_db 以下に下がったら終了する
+// (window ステップ経過前, ピークが0の間は判定しない)
+
+use std::collections::VecDeque;
+
+use fdtd2d::{fdtd, StopReason};
+
+// 減衰を判定する指標
+#[derive(Clone,Debug,PartialEq)]
+pub enum Signal {
+    // PMLを除いた領域の電磁界エネルギー
+    Energy,
+    // 登録した観測点(fdtd::probesの番号, 空の場合は全て)の値の2乗和
+    Probes(Vec<usize>),
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct Decay {
+    pub signal: Signal,
+    // ピークに対する閾値 (dB, 負の値)
+    pub threshold_db: f64,
+    // 判定に使う直近のステップ数
+    pub window: usize,
+    // 指標を計算する間隔
+    pub interval: usize,
+    // 通算ステップ数の上限
+    pub max_steps: usize,
+    peak: f64,
+    start: Option<usize>,
+    recent: VecDeque<(usize, f64)>,
+}
+
+impl Decay {
+    pub fn new(signal: Signal, threshold_db: f64, max_steps: usize) -> Decay {
+        Decay{signal, threshold_db, window: 200, interval: 10, max_steps, peak: 0.0, start: None, recent: VecDeque::new()}
+    }
+
+    pub fn energy(threshold_db: f64, max_steps: usize) -> Decay {
+        Decay::new(Signal::Energy, threshold_db, max_steps)
+    }
+
+    pub fn probes(probes: &[usize], threshold_db: f64, max_steps: usize) -> Decay {
+        Decay::new(Signal::Probes(probes.to_vec()), threshold_db, max_steps)
+    }
+
+    pub fn window(mut self, n: usize) -> Decay {
+        self.window = n.max(1);
+        self
+    }
+
+    pub fn interval(mut self, n: usize) -> Decay {
+        self.interval = n.max(1);
+        self
+    }
+
+    // 現在の指標
+    pub fn measure(&self, f: &fdtd) -> f64 {
+        match self.signal {
+            Signal::Energy => f.energy(),
+            Signal::Probes(ref idx) => {
+                let p = f.probes();
+                let last = |i: usize| p.get(i).and_then(|p| p.values.last()).map_or(0.0, |v| v * v);
+                if idx.is_empty() { (0..p.len()).map(last).sum() } else { idx.iter().map(|&i| last(i)).sum() }
+            }
+        }
+    }
+
+    // ピークに対する直近windowの最大値 (dB)
+    pub fn level_db(&self) -> f64 {
+        let m = self.recent.iter().fold(0.0f64, |a, r| a.max(r.1));
+        if self.peak > 0.0 { 10.0 * (m / self.peak).log10() } else { 0.0 }
+    }
+
+    // step()の後に呼び, 終了すべきなら理由を返す
+    pub fn update(&mut self, f: &fdtd) -> Option<StopReason> {
+        let n = f.step_count();
+        let start = *self.start.get_or_insert(n);
+        if n.is_multiple_of(self.interval) {
+            let m = self.measure(f);
+            self.peak = self.peak.max(m);
+            self.recent.push_back((n, m));
+            while self.recent.front().is_some_and(|r| r.0 + self.window <= n) {
+                self.recent.pop_front();
+            }
+            if self.peak > 0.0 && n >= start + self.window && self.level_db() <= self.threshold_db {
+                return Some(StopReason::Decayed(self.level_db()));
+            }
+        }
+        if n >= self.max_steps {
+            return Some(StopReason::MaxSteps);
+        }
+        None
+    }
+}
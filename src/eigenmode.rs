@@ -0,0 +1,195 @@
+/*
+ * This software is released under the MIT License.
+ * http://opensource.org/licenses/mit-license.php
+ */
+
+// 導波路断面の固有モード (線上の1次元差分法)
+//
+// 線を法線方向に伝搬するモード exp(-jβu) について, 線上の媒質(epsd, mud)から
+//   TE (Ez, Hx, Hy): d/ds(1/μt dEz/ds) + k0^2 εz Ez = β^2 Ez / μl
+//   TM (Hz, Ex, Ey): d/ds(1/εt dHz/ds) + k0^2 μz Hz = β^2 Hz / εl
+// (sは線に沿った座標, t/lは接線/法線方向の成分の位置の値) をYee格子と同じ差分で解く
+// k0 と β は時間・法線方向の差分の数値分散を含めた値に換算するので, 格子上を伝搬するモードと一致する
+// 線の両端の外側では場を0とする (誘電体導波路では界が十分減衰する幅を取る)
+
+use std::f64::consts::PI;
+
+use complex::Complex;
+use error::{Error, Result};
+use fdtd2d::{fdtd, Component, C};
+use linalg;
+use port::{Axis, Port};
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Polarization {
+    // Ez, Hx, Hy (電界が解析面に垂直)
+    Te,
+    // Hz, Ex, Ey (磁界が解析面に垂直)
+    Tm,
+}
+
+impl Polarization {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Polarization::Te => "te",
+            Polarization::Tm => "tm",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Polarization> {
+        match name {
+            "te" => Some(Polarization::Te),
+            "tm" => Some(Polarization::Tm),
+            _ => None,
+        }
+    }
+
+    // 線上の分布を表す成分
+    pub fn component(&self) -> Component {
+        match *self {
+            Polarization::Te => Component::Ez,
+            Polarization::Tm => Component::Hz,
+        }
+    }
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct Eigenmode {
+    pub polarization: Polarization,
+    pub normal: Axis,
+    pub pos: usize,
+    pub start: usize,
+    pub end: usize,
+    pub freq: f64,
+    // 伝搬定数 (rad/m), 実効屈折率 β/k0
+    pub beta: f64,
+    pub neff: f64,
+    // 線上のEz(TE)またはHz(TM)の分布 (2乗和を1, 絶対値の最大の点を正に規格化)
+    pub profile: Vec<f64>,
+    // モードの直交性の重み (TE: 1/μl, TM: 1/εl)
+    pub weight: Vec<f64>,
+}
+
+impl Eigenmode {
+    // 同じ線のポート (TEのみ)
+    pub fn port(&self, name: &str, forward: bool) -> Result<Port> {
+        if self.polarization != Polarization::Te {
+            return Err(Error::Unsupported{what: "port excitation of TM modes"});
+        }
+        Ok(Port::new(name, self.normal, self.pos, self.start, self.end, forward).profile(self.profile.clone()))
+    }
+
+    // 線上の場をモードの和で表したときの係数 (重み付き内積による射影)
+    pub fn overlap(&self, field: &[Complex]) -> Complex {
+        let norm: f64 = self.profile.iter().zip(&self.weight).map(|(v, w)| w * v * v).sum();
+        let s = field.iter().zip(self.profile.iter().zip(&self.weight))
+            .fold(Complex::default(), |s, (z, (v, w))| s + z.scale(w * v));
+        s.scale(1.0 / norm)
+    }
+}
+
+// 法線normal, 法線方向の位置pos, 接線方向の範囲 [start, end) の線
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct ModeSolver {
+    pub normal: Axis,
+    pub pos: usize,
+    pub start: usize,
+    pub end: usize,
+    pub polarization: Polarization,
+}
+
+impl ModeSolver {
+    pub fn new(normal: Axis, pos: usize, start: usize, end: usize, polarization: Polarization) -> ModeSolver {
+        ModeSolver{normal, pos, start, end, polarization}
+    }
+
+    // 接線方向の位置s, 法線方向の位置uの配列番号
+    fn at(&self, s: usize, u: usize) -> (usize, usize) {
+        match self.normal {
+            Axis::X => (u, s),
+            Axis::Y => (s, u),
+        }
+    }
+
+    // 周波数freqの伝搬モード (β^2 > 0) を β の大きい順に最大count個
+    pub fn solve(&self, f: &fdtd, freq: f64, count: usize) -> Result<Vec<Eigenmode>> {
+        let (nx, ny) = f.dims();
+        let (nt, nl) = if self.normal == Axis::X { (ny, nx) } else { (nx, ny) };
+        let (x, y) = self.at(self.end, self.pos);
+        if self.end <= self.start {
+            return Err(Error::ReversedRange{what: "mode line", axis: if self.normal == Axis::X { 'y' } else { 'x' }, start: self.start, end: self.end});
+        }
+        if self.pos == 0 || self.pos + 1 >= nl || self.start == 0 || self.end >= nt {
+            return Err(Error::OutOfBounds{what: "mode line", x, y, nx, ny});
+        }
+        if !freq.is_finite() || freq <= 0.0 {
+            return Err(Error::InvalidValue{what: "mode frequency", value: freq});
+        }
+        f.require_uniform("mode solver on a graded mesh")?;
+
+        let (dx, dy) = f.cell_size();
+        let (ds, du) = if self.normal == Axis::X { (dy, dx) } else { (dx, dy) };
+        // 時間の差分の数値分散を含む波数
+        let k0 = 2.0 * (PI * freq * f.dt).sin() / (C * f.dt);
+        let (p, n) = (self.pos, self.end - self.start);
+        let m = |c: Component, s: usize, u: usize| {
+            let (x, y) = self.at(s, u);
+            f.local_medium(c, x, y)
+        };
+
+        // A v = λ B v (Bは対角)
+        let (mut d, mut e, mut b) = (vec![0.0; n], vec![0.0; n.saturating_sub(1)], vec![0.0; n]);
+        // k-1番目とk番目の点の間の接線方向の成分 (k = 0..n)
+        let between = |k: usize| -> f64 {
+            let s = self.start + k;
+            match (self.polarization, self.normal) {
+                (Polarization::Te, Axis::X) => m(Component::Hx, s - 1, p),
+                (Polarization::Te, Axis::Y) => m(Component::Hy, s - 1, p),
+                (Polarization::Tm, Axis::X) => m(Component::Ex, s, p),
+                (Polarization::Tm, Axis::Y) => m(Component::Ey, s, p),
+            }
+        };
+        for k in 0..n {
+            let s = self.start + k;
+            let (node, long) = match (self.polarization, self.normal) {
+                (Polarization::Te, Axis::X) => (m(Component::Ez, s, p), 0.5 * (m(Component::Hy, s, p - 1) + m(Component::Hy, s, p))),
+                (Polarization::Te, Axis::Y) => (m(Component::Ez, s, p), 0.5 * (m(Component::Hx, s, p - 1) + m(Component::Hx, s, p))),
+                (Polarization::Tm, Axis::X) => (m(Component::Hz, s, p), 0.5 * (m(Component::Ey, s, p) + m(Component::Ey, s, p + 1))),
+                (Polarization::Tm, Axis::Y) => (m(Component::Hz, s, p), 0.5 * (m(Component::Ex, s, p) + m(Component::Ex, s, p + 1))),
+            };
+            let (lower, upper) = (between(k), between(k + 1));
+            d[k] = -(1.0 / lower + 1.0 / upper) / (ds * ds) + k0 * k0 * node;
+            if k + 1 < n {
+                e[k] = 1.0 / (upper * ds * ds);
+            }
+            b[k] = 1.0 / long;
+        }
+        // B^-1/2 A B^-1/2 で対称化
+        let ds2: Vec<f64> = d.iter().zip(&b).map(|(d, b)| d / b).collect();
+        let es2: Vec<f64> = e.iter().enumerate().map(|(k, e)| e / (b[k] * b[k + 1]).sqrt()).collect();
+
+        let mut modes = Vec::new();
+        for (lambda, g) in linalg::tridiagonal_eigen(&ds2, &es2, count) {
+            if lambda <= 0.0 {
+                break;
+            }
+            // 法線方向の差分: (2/Δ sin(βΔ/2))^2 = λ
+            let arg = 0.5 * du * lambda.sqrt();
+            if arg >= 1.0 {
+                log_warn!("mode at {:e} Hz is beyond the grid resolution (skipped)", freq);
+                continue;
+            }
+            let beta = 2.0 * arg.asin() / du;
+            let mut v: Vec<f64> = g.iter().zip(&b).map(|(g, b)| g / b.sqrt()).collect();
+            let norm = v.iter().map(|x| x * x).sum::<f64>().sqrt();
+            let peak = v.iter().fold(0.0f64, |a, &x| if x.abs() > a.abs() { x } else { a });
+            let sign = if peak < 0.0 { -1.0 } else { 1.0 };
+            for x in v.iter_mut() {
+                *x *= sign / norm;
+            }
+            modes.push(Eigenmode{polarization: self.polarization, normal: self.normal, pos: self.pos, start: self.start, end: self.end,
+                                 freq, beta, neff: beta * C / (2.0 * PI * freq), profile: v, weight: b.clone()});
+        }
+        Ok(modes)
+    }
+}
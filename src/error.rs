@@ -0,0 +1,61 @@
+/*
+ * This software is released under the MIT License.
+ * http://opensource.org/licenses/mit-license.php
+ */
+
+// 設定の誤り等のエラー
+
+use std::error;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum Error {
+    // 配列の範囲外 (対象, 座標, PMLを含む配列のセル数)
+    OutOfBounds{what: &'static str, x: usize, y: usize, nx: usize, ny: usize},
+    // 範囲の開始が終端以上
+    ReversedRange{what: &'static str, axis: char, start: usize, end: usize},
+    // PML内に置けないもの (給電点, 観測点)
+    InPml{what: &'static str, x: usize, y: usize, lpml: usize},
+    // 媒質定数等の値が不正
+    InvalidValue{what: &'static str, value: f64},
+    // init_source前のfeed
+    NoSource,
+    // 対応していない機能
+    Unsupported{what: &'static str},
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::OutOfBounds{what, x, y, nx, ny} =>
+                write!(f, "{} ({}, {}) is outside the grid ({} x {} cells including PML)", what, x, y, nx, ny),
+            Error::ReversedRange{what, axis, start, end} =>
+                write!(f, "{}: {} range {}..{} is empty or reversed", what, axis, start, end),
+            Error::InPml{what, x, y, lpml} =>
+                write!(f, "{} ({}, {}) is inside the PML ({} layers)", what, x, y, lpml),
+            Error::InvalidValue{what, value} => write!(f, "invalid {}: {}", what, value),
+            Error::NoSource => write!(f, "no source has been initialized (call init_source first)"),
+            Error::Unsupported{what} => write!(f, "{} is not supported", what),
+            Error::Io(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
@@ -3,8 +3,22 @@
  * http://opensource.org/licenses/mit-license.php
  */
 
+use std::f64::consts::PI;
 use std::fs::File;
 use std::io::*;
+use std::mem;
+use std::ops::Range;
+use std::thread;
+
+use decay::Decay;
+use error::{self, Error as FdtdError};
+use mesh;
+use complex::Complex;
+use monitor::{DftMonitor, Probe};
+use snapshot::Region;
+use observer::{Control, Observer};
+
+use snapshot::{GnuplotBackend, LineEnding, SnapshotWriter};
 
 // デフォルト解析領域分割数
 const NX0: i32 = 120;
@@ -23,12 +37,6 @@ const LPML: i32 = 8;
 const ORDER: i32 = 4;
 const RMAX: f64 = -120.0;// (dB)
 
-const NX: i32 = NX0 + 2 * LPML;
-const NY: i32 = NY0 + 2 * LPML;
-
-const NX_P: usize = NX as usize;
-const NY_P: usize = NY as usize;
-
 
 // 背景媒質
 #[allow(non_upper_case_globals)]
@@ -43,9 +51,173 @@ const sigmbk: f64 = 0.0;
 const copml: f64 = -1.5280063e-4;
 
 // 定数
-const EPS0: f64 = 8.8541878e-12;
-const MU0: f64 = 1.2566371e-6;
-const C: f64 = 2.9979246e8;
+pub const EPS0: f64 = 8.8541878e-12;
+pub const MU0: f64 = 1.2566371e-6;
+pub const C: f64 = 2.9979246e8;
+
+// 給電パルス幅 (s)
+pub const DURATION: f64 = 0.1e-9;
+
+// 1セルあたりの配列数 (場・係数・PML用 38, 媒質定数 4, 完全導体 1)
+const ARRAYS: usize = 42;
+
+// 状態ファイルから復元できる最大セル数 (PMLを含む, 約 8192 x 8192)
+const MAX_STATE_CELLS: u64 = 1 << 26;
+
+// 状態保存の対象とする配列 (場, 係数, PML用)
+macro_rules! state_arrays {
+    ($($a:ident),*) => {
+        fn state_arrays(&self) -> Vec<&Vec<Vec<f64>>> {
+            vec![$(&self.$a),*]
+        }
+
+        fn state_arrays_mut(&mut self) -> Vec<&mut Vec<Vec<f64>>> {
+            vec![$(&mut self.$a),*]
+        }
+    }
+}
+
+// 場の成分
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Component {
+    Ex,
+    Ey,
+    Ez,
+    Hx,
+    Hy,
+    Hz,
+}
+
+impl Component {
+    pub const ALL: [Component; 6] = [Component::Ex, Component::Ey, Component::Ez, Component::Hx, Component::Hy, Component::Hz];
+
+    // 出力用の成分名
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Component::Ex => "ex",
+            Component::Ey => "ey",
+            Component::Ez => "ez",
+            Component::Hx => "hx",
+            Component::Hy => "hy",
+            Component::Hz => "hz",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Component> {
+        match name {
+            "ex" => Some(Component::Ex),
+            "ey" => Some(Component::Ey),
+            "ez" => Some(Component::Ez),
+            "hx" => Some(Component::Hx),
+            "hy" => Some(Component::Hy),
+            "hz" => Some(Component::Hz),
+            _ => None,
+        }
+    }
+}
+
+// 媒質定数の種類
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Material {
+    Eps,
+    Mu,
+    SigmaE,
+    SigmaM,
+    Pec,
+}
+
+impl Material {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Material::Eps => "epsd",
+            Material::Mu => "mud",
+            Material::SigmaE => "sgmed",
+            Material::SigmaM => "sgmmd",
+            Material::Pec => "pec",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Material> {
+        match name {
+            "epsd" => Some(Material::Eps),
+            "mud" => Some(Material::Mu),
+            "sgmed" => Some(Material::SigmaE),
+            "sgmmd" => Some(Material::SigmaM),
+            "pec" => Some(Material::Pec),
+            _ => None,
+        }
+    }
+}
+
+// 媒質定数
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Medium {
+    pub eps: f64,
+    pub mu: f64,
+    pub sigma_e: f64,
+    pub sigma_m: f64,
+}
+
+impl Default for Medium {
+    fn default() -> Medium {
+        Medium{eps: epsbk, mu: mubk, sigma_e: sigebk, sigma_m: sigmbk}
+    }
+}
+
+// 給電点 (ガウスパルス, freq > 0 の場合は搬送波で変調)
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Source {
+    pub x: usize,
+    pub y: usize,
+    pub duration: f64,
+    pub t0: f64,
+    // 搬送波の周波数 (0でベースバンドのパルス)
+    pub freq: f64,
+    pub amplitude: f64,
+    // 集中定数給電の内部抵抗 (Ω, 0で電流源) とz方向の長さ (m)
+    // 抵抗 > 0 の場合は波形を開放電圧とする抵抗付き電圧源 (ノートン等価の電流源 V/R と並列の抵抗)
+    pub resistance: f64,
+    pub length: f64,
+    // 電流から電界への係数 (dt/ε, add_sourceで給電点の媒質から決める)
+    coef: f64,
+}
+
+impl Source {
+    // 既定はinit_sourceと同じパルス
+    pub fn new(x: usize, y: usize) -> Source {
+        Source{x, y, duration: DURATION, t0: DURATION * 4.0, freq: 0.0, amplitude: 1.0, resistance: 0.0, length: 0.0, coef: 0.0}
+    }
+
+    pub fn pulse(mut self, duration: f64, t0: f64) -> Source {
+        self.duration = duration;
+        self.t0 = t0;
+        self
+    }
+
+    pub fn carrier(mut self, freq: f64) -> Source {
+        self.freq = freq;
+        self
+    }
+
+    pub fn amplitude(mut self, a: f64) -> Source {
+        self.amplitude = a;
+        self
+    }
+
+    // 内部抵抗r, z方向の長さlengthの集中定数給電にする
+    pub fn lumped(mut self, r: f64, length: f64) -> Source {
+        self.resistance = r;
+        self.length = length;
+        self
+    }
+
+    // 時刻tの電界更新で注入する波形 (injectと同じ式にamplitudeと搬送波を掛ける)
+    pub fn waveform(&self, t: f64, dt: f64) -> f64 {
+        let tau = t - 0.5 * dt - self.t0;
+        let v = self.amplitude * (-(tau / self.duration).powi(2)).exp();
+        if self.freq > 0.0 { v * (2.0 * PI * self.freq * tau).cos() } else { v }
+    }
+}
 
 // PML領域の位置格納構造体
 #[derive(Clone,Debug)]
@@ -121,6 +293,9 @@ pub struct fdtd {
     mud: Vec<Vec<f64>>,
     sgmmd : Vec<Vec<f64>>,
 
+    // 完全導体の位置
+    pec: Vec<Vec<bool>>,
+
     // PML領域
     pml_s: Vec<pml>,
 
@@ -129,17 +304,70 @@ pub struct fdtd {
     duration: f64,
     t0: f64,
 
+    // 給電点
+    sources: Vec<Source>,
+
     // セルサイズ設定
     nx: i32,
     ny: i32,
+    dx: f64,
+    dy: f64,
+
+    // 列, 行毎のセル幅 (PMLを含む, PML内は解析領域の端のセル幅) と節点の座標 (解析領域の端を原点)
+    xw: Vec<f64>,
+    yw: Vec<f64>,
+    xn: Vec<f64>,
+    yn: Vec<f64>,
+
+    // PML層数
+    lpml: i32,
+
+    // 電磁界計算のスレッド数
+    threads: usize,
+
+    // 完了したステップ数 (時刻は nstep * dt)
+    nstep: usize,
+
+    // 毎ステップ記録する観測点, DFTモニタ
+    probes: Vec<Probe>,
+    monitors: Vec<DftMonitor>,
+
+    // 時間ループのフックと終了要求
+    observers: Vec<Box<dyn Observer>>,
+    stop: bool,
+}
+
+// 計算を終了した理由
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum StopReason {
+    // 指定ステップ数
+    Steps,
+    // 指定時刻
+    Time,
+    // Observerによる終了要求
+    Observer,
+    // 場が減衰した (ピークに対する直近の値, dB)
+    Decayed(f64),
+    // 減衰前にステップ数の上限に達した
+    MaxSteps,
+}
+
+// run, run_until の結果
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct RunSummary {
+    // 通算のステップ数と時刻
+    pub steps: usize,
+    pub time: f64,
+    pub stop: StopReason,
 }
 
 #[allow(non_camel_case_types)]
 pub struct fdtdBuilder;
 
+#[allow(clippy::new_ret_no_self)]
 pub trait New<T> {
     #[allow(non_camel_case_types, non_snake_case)]
-    fn newSize(T, T) -> fdtd;
+    fn newSize(x: T, y: T) -> fdtd;
 
     fn new() -> fdtd;
 }
@@ -149,44 +377,80 @@ pub trait New<T> {
 #[allow(non_snake_case)]
 impl New<i32> for fdtdBuilder {
     fn newSize(x:i32, y:i32) -> fdtd {
-        let nxa = (x + 2 * LPML) as usize;
-        let nya = (y + 2 * LPML) as usize;
-
-        let mut n = fdtd{dt: 0.0, ex:vec![vec![0.0; nxa]; nya], ey:vec![vec![0.0; nxa]; nya], ez:vec![vec![0.0; nxa]; nya], hx:vec![vec![0.0; nxa]; nya], hy:vec![vec![0.0; nxa]; nya], hz:vec![vec![0.0; nxa]; nya],
-        aexpml: vec![vec![0.0; nxa]; nya], aeypml: vec![vec![0.0; nxa]; nya], aex: vec![vec![0.0; nxa]; nya], aey: vec![vec![0.0; nxa]; nya], aez: vec![vec![0.0; nxa]; nya],
-        bexpml: vec![vec![0.0; nxa]; nya], beypml: vec![vec![0.0; nxa]; nya], bexy: vec![vec![0.0; nxa]; nya], beyx: vec![vec![0.0; nxa]; nya], bezx: vec![vec![0.0; nxa]; nya], bezy: vec![vec![0.0; nxa]; nya],
-        amxpml: vec![vec![0.0; nxa]; nya], amypml: vec![vec![0.0; nxa]; nya], amx: vec![vec![0.0; nxa]; nya], amy: vec![vec![0.0; nxa]; nya], amz: vec![vec![0.0; nxa]; nya],
-        bmxpml: vec![vec![0.0; nxa]; nya], bmypml: vec![vec![0.0; nxa]; nya], bmxy: vec![vec![0.0; nxa]; nya], bmyx: vec![vec![0.0; nxa]; nya], bmzx: vec![vec![0.0; nxa]; nya], bmzy: vec![vec![0.0; nxa]; nya],
-        expml: vec![vec![0.0; nxa]; nya], eypml: vec![vec![0.0; nxa]; nya], ezx: vec![vec![0.0; nxa]; nya], ezy: vec![vec![0.0; nxa]; nya],
-        hxpml: vec![vec![0.0; nxa]; nya], hypml: vec![vec![0.0; nxa]; nya], hzx: vec![vec![0.0; nxa]; nya], hzy: vec![vec![0.0; nxa]; nya], 
-        epsd: vec![vec![epsbk; nxa+1]; nya+1], sgmed: vec![vec![sigebk; nxa+1]; nya+1],
-        mud: vec![vec![mubk; nxa+1]; nya+1], sgmmd : vec![vec![sigmbk; nxa+1]; nya+1],
-        pml_s: Vec::new(), befed: 0.0, duration: 0.0, t0: 0.0, nx: x + 2 * LPML, ny: y + 2 * LPML};
-
-        println!("Set cell size x:{}, y:{}", x, y);
-        //時間ステップ
-        let v = C / ((epsbk * mubk).sqrt());
-        n.dt = 0.99999/(v * ((1.0 / (DX * DX) + 1.0 / (DY * DY)).sqrt()));
-
-        return n;
+        log_info!("Set cell size x:{}, y:{}", x, y);
+        fdtdBuilder::newGrid(x, y, DX, DY, LPML)
     }
 
     fn new() -> fdtd {
-        let mut n = fdtd{dt: 0.0, ex:vec![vec![0.0; NX_P]; NY_P], ey:vec![vec![0.0; NX_P]; NY_P], ez:vec![vec![0.0; NX_P]; NY_P], hx:vec![vec![0.0; NX_P]; NY_P], hy:vec![vec![0.0; NX_P]; NY_P], hz:vec![vec![0.0; NX_P]; NY_P],
-        aexpml: vec![vec![0.0; NX_P]; NY_P], aeypml: vec![vec![0.0; NX_P]; NY_P], aex: vec![vec![0.0; NX_P]; NY_P], aey: vec![vec![0.0; NX_P]; NY_P], aez: vec![vec![0.0; NX_P]; NY_P],
-        bexpml: vec![vec![0.0; NX_P]; NY_P], beypml: vec![vec![0.0; NX_P]; NY_P], bexy: vec![vec![0.0; NX_P]; NY_P], beyx: vec![vec![0.0; NX_P]; NY_P], bezx: vec![vec![0.0; NX_P]; NY_P], bezy: vec![vec![0.0; NX_P]; NY_P],
-        amxpml: vec![vec![0.0; NX_P]; NY_P], amypml: vec![vec![0.0; NX_P]; NY_P], amx: vec![vec![0.0; NX_P]; NY_P], amy: vec![vec![0.0; NX_P]; NY_P], amz: vec![vec![0.0; NX_P]; NY_P],
-        bmxpml: vec![vec![0.0; NX_P]; NY_P], bmypml: vec![vec![0.0; NX_P]; NY_P], bmxy: vec![vec![0.0; NX_P]; NY_P], bmyx: vec![vec![0.0; NX_P]; NY_P], bmzx: vec![vec![0.0; NX_P]; NY_P], bmzy: vec![vec![0.0; NX_P]; NY_P],
-        expml: vec![vec![0.0; NX_P]; NY_P], eypml: vec![vec![0.0; NX_P]; NY_P], ezx: vec![vec![0.0; NX_P]; NY_P], ezy: vec![vec![0.0; NX_P]; NY_P],
-        hxpml: vec![vec![0.0; NX_P]; NY_P], hypml: vec![vec![0.0; NX_P]; NY_P], hzx: vec![vec![0.0; NX_P]; NY_P], hzy: vec![vec![0.0; NX_P]; NY_P], 
-        epsd: vec![vec![epsbk; NX_P+1]; NY_P+1], sgmed: vec![vec![sigebk; NX_P+1]; NY_P+1],
-        mud: vec![vec![mubk; NX_P+1]; NY_P+1], sgmmd : vec![vec![sigmbk; NX_P+1]; NY_P+1],
-        pml_s: Vec::new(), befed: 0.0, duration: 0.0, t0: 0.0, nx: NX, ny: NY};
-        //時間ステップ
+        fdtdBuilder::newGrid(NX0, NY0, DX, DY, LPML)
+    }
+}
+
+#[allow(non_snake_case)]
+impl fdtdBuilder {
+    // セル数, セルサイズ, PML層数を指定して生成 (配列は[x][y])
+    pub fn newGrid(x: i32, y: i32, dx: f64, dy: f64, lpml: i32) -> fdtd {
+        let nxa = (x + 2 * lpml) as usize;
+        let nya = (y + 2 * lpml) as usize;
+        let z = || vec![vec![0.0; nya]; nxa];
+        let c = |v: f64| vec![vec![v; nya+1]; nxa+1];
+
+        let mut n = fdtd{dt: 0.0, ex: z(), ey: z(), ez: z(), hx: z(), hy: z(), hz: z(),
+        aexpml: z(), aeypml: z(), aex: z(), aey: z(), aez: z(),
+        bexpml: z(), beypml: z(), bexy: z(), beyx: z(), bezx: z(), bezy: z(),
+        amxpml: z(), amypml: z(), amx: z(), amy: z(), amz: z(),
+        bmxpml: z(), bmypml: z(), bmxy: z(), bmyx: z(), bmzx: z(), bmzy: z(),
+        expml: z(), eypml: z(), ezx: z(), ezy: z(),
+        hxpml: z(), hypml: z(), hzx: z(), hzy: z(),
+        epsd: c(epsbk), sgmed: c(sigebk), mud: c(mubk), sgmmd: c(sigmbk),
+        pec: vec![vec![false; nya]; nxa],
+        pml_s: Vec::new(), befed: 0.0, duration: 0.0, t0: 0.0, sources: Vec::new(),
+        nx: x + 2 * lpml, ny: y + 2 * lpml, dx, dy,
+        xw: vec![dx; nxa], yw: vec![dy; nya],
+        xn: (0..nxa+1).map(|i| (i as f64 - lpml as f64) * dx).collect(),
+        yn: (0..nya+1).map(|i| (i as f64 - lpml as f64) * dy).collect(),
+        lpml, threads: 1,
+        nstep: 0, probes: Vec::new(), monitors: Vec::new(),
+        observers: Vec::new(), stop: false};
+
+        n.dt = fdtdBuilder::time_step(dx, dy);
+
+        n
+    }
+
+    // 列, 行毎のセル幅 (PMLを除いた解析領域, mesh::Gradingで生成) とPML層数を指定して生成
+    // PML内のセル幅は解析領域の端のセル幅, dtは最小のセル幅で決める
+    pub fn newGraded(xw: &[f64], yw: &[f64], lpml: i32) -> error::Result<fdtd> {
+        for &w in xw.iter().chain(yw.iter()) {
+            if !(w > 0.0 && w.is_finite()) {
+                return Err(FdtdError::InvalidValue{what: "cell width", value: w});
+            }
+        }
+        if xw.is_empty() || yw.is_empty() {
+            return Err(FdtdError::InvalidValue{what: "number of cells", value: 0.0});
+        }
+        let pad = |w: &[f64]| {
+            let l = lpml as usize;
+            let mut v = vec![w[0]; l];
+            v.extend_from_slice(w);
+            v.extend(vec![w[w.len() - 1]; l]);
+            v
+        };
+        let (xw, yw) = (pad(xw), pad(yw));
+        let mut f = fdtdBuilder::newGrid(xw.len() as i32 - 2 * lpml, yw.len() as i32 - 2 * lpml, xw[0], yw[0], lpml);
+        f.set_widths(xw, yw);
+        Ok(f)
+    }
+
+    //時間ステップ
+    pub fn time_step(dx: f64, dy: f64) -> f64 {
         let v = C / ((epsbk * mubk).sqrt());
-        n.dt = 0.99999/(v * ((1.0 / (DX * DX) + 1.0 / (DY * DY)).sqrt()));
+        0.99999/(v * ((1.0 / (dx * dx) + 1.0 / (dy * dy)).sqrt()))
+    }
 
-        return n;
+    // 配列のセル数(PMLを含む)に対するおおよその使用メモリ (bytes)
+    pub fn memory_estimate(nx: usize, ny: usize) -> usize {
+        (ARRAYS - 1) * nx * ny * 8 + nx * ny
     }
 }
 
@@ -194,99 +458,284 @@ impl New<i32> for fdtdBuilder {
 impl fdtd {
     // 初期化設定(媒質の設定後に実行)
     pub fn setup(&mut self) {
-        for y in 0..self.nx as usize{
-            for x in 0..self.ny as usize{
+        for x in 0..self.nx as usize{
+            // 節点に掛かる差分は双対セル幅 (隣り合うセル幅の平均), セル中心に掛かる差分はセル幅で割る
+            let (dxd, dxc) = (dual(&self.xw, x), self.xw[x]);
+            for y in 0..self.ny as usize{
+                let (dyd, dyc) = (dual(&self.yw, y), self.yw[y]);
                 let epsx = 0.5 * (self.epsd[x+1][y+1] + self.epsd[x+1][y]) * EPS0;
                 let sgex = 0.5 * (self.sgmed[x+1][y+1] + self.sgmed[x+1][y]);
                 let mut a = 0.5 * sgex * self.dt / epsx;
                 self.aex[x][y] = (1.0 - a) / (1.0 + a);
-                self.bexy[x][y] = self.dt / epsx / (1.0 + a) / DY;
+                self.bexy[x][y] = self.dt / epsx / (1.0 + a) / dyd;
 
                 let epsy = 0.5 * (self.epsd[x+1][y+1] + self.epsd[x][y+1]) * EPS0;
                 let sgey = 0.5 * (self.sgmed[x+1][y+1] + self.sgmed[x][y+1]);
                 a = 0.5 * sgey * self.dt / epsy;
                 self.aey[x][y] = (1.0 - a) / (1.0 + a);
-                self.beyx[x][y] = self.dt / epsy / (1.0 + a) / DX;
+                self.beyx[x][y] = self.dt / epsy / (1.0 + a) / dxd;
 
                 let epsz = 0.25 * (self.epsd[x+1][y+1] + self.epsd[x+1][y] + self.epsd[x][y+1] + self.epsd[x][y]) * EPS0;
                 let sgez = 0.25 * (self.sgmed[x+1][y+1] + self.sgmed[x+1][y] + self.sgmed[x][y+1] + self.sgmed[x][y]);
                 a = 0.5 * sgez * self.dt / epsz;
                 self.aez[x][y] = (1.0 - a) / (1.0 + a);
-                self.bezy[x][y] = self.dt / epsz / (1.0 + a) / DY;
-                self.bezx[x][y] = self.dt / epsz / (1.0 + a) / DX;
+                self.bezy[x][y] = self.dt / epsz / (1.0 + a) / dyd;
+                self.bezx[x][y] = self.dt / epsz / (1.0 + a) / dxd;
 
                 let mux = 0.5 * (self.mud[x+1][y+1] + self.mud[x][y+1]) * MU0;
                 let sgmx = 0.5 * (self.sgmmd[x+1][y+1] + self.sgmmd[x][y+1]);
                 a = 0.5 * sgmx * self.dt / mux;
                 self.amx[x][y] = (1.0 - a) / (1.0 + a);
-                self.bmxy[x][y] = self.dt / mux / (1.0 + a) / DY;
+                self.bmxy[x][y] = self.dt / mux / (1.0 + a) / dyc;
 
                 let muy = 0.5 * (self.mud[x+1][y+1] + self.mud[x+1][y]) * MU0;
                 let sgmy = 0.5 * (self.sgmmd[x+1][y+1] + self.sgmmd[x+1][y]);
                 a = 0.5 * sgmy * self.dt / muy;
                 self.amy[x][y] = (1.0 - a) / (1.0 + a);
-                self.bmyx[x][y] = self.dt / muy / (1.0 + a) / DX;
+                self.bmyx[x][y] = self.dt / muy / (1.0 + a) / dxc;
 
                 let muz = self.mud[x+1][y+1] * MU0;
                 let sgmz = self.sgmmd[x+1][y+1];
                 a = 0.5 * sgmz * self.dt / muz;
                 self.amz[x][y] = (1.0 - a) / (1.0 + a);
-                self.bmzx[x][y] = self.dt / muz / (1.0 + a) / DX;
-                self.bmzy[x][y] = self.dt / muz / (1.0 + a) / DY;
+                self.bmzx[x][y] = self.dt / muz / (1.0 + a) / dxc;
+                self.bmzy[x][y] = self.dt / muz / (1.0 + a) / dyc;
+            }
+        }
+        for s in self.sources.clone() {
+            if s.resistance > 0.0 {
+                self.lumped_coefficients(&s);
             }
         }
 
         self.init_pml();
     }
 
-    // 電界計算
+    // 電界計算 (x方向に分割して並列計算)
     pub fn e_cal(&mut self) {
+        let (nx, ny, th) = (self.nx as usize, self.ny as usize, self.threads);
+
         //Ex
-        for y in 1..(self.ny-1) as usize {
-            for x in 0..(self.nx-1) as usize {
-                self.ex[x][y] = self.aex[x][y] * self.ex[x][y] + self.bexy[x][y] * (self.hz[x][y] - self.hz[x][y-1]);
+        let (a, b, hz) = (&self.aex, &self.bexy, &self.hz);
+        columns(th, &mut self.ex, 0..nx-1, |x, ex| {
+            for y in 1..ny-1 {
+                ex[y] = a[x][y] * ex[y] + b[x][y] * (hz[x][y] - hz[x][y-1]);
             }
-        }
+        });
 
         //Ey
-        for y in 0..(self.ny-1) as usize {
-            for x in 1..(self.nx-1) as usize {
-                self.ey[x][y] = self.aey[x][y] * self.ey[x][y] - self.beyx[x][y] * (self.hz[x][y] - self.hz[x-1][y]);
+        let (a, b) = (&self.aey, &self.beyx);
+        columns(th, &mut self.ey, 1..nx-1, |x, ey| {
+            for y in 0..ny-1 {
+                ey[y] = a[x][y] * ey[y] - b[x][y] * (hz[x][y] - hz[x-1][y]);
             }
-        }
+        });
 
         //Ez
-        for y in 1..(self.ny-1) as usize {
-            for x in 1..(self.nx-1) as usize {
-                self.ez[x][y] = self.aez[x][y] * self.ez[x][y] + self.bezx[x][y] * (self.hy[x][y] - self.hy[x-1][y])
-                                                               - self.bezy[x][y] * (self.hx[x][y] - self.hx[x][y-1]);
+        let (a, bx, by, hx, hy) = (&self.aez, &self.bezx, &self.bezy, &self.hx, &self.hy);
+        columns(th, &mut self.ez, 1..nx-1, |x, ez| {
+            for y in 1..ny-1 {
+                ez[y] = a[x][y] * ez[y] + bx[x][y] * (hy[x][y] - hy[x-1][y])
+                                        - by[x][y] * (hx[x][y] - hx[x][y-1]);
             }
-        }
+        });
     }
 
-    // 磁界計算
+    // 磁界計算 (x方向に分割して並列計算)
     pub fn h_cal(&mut self) {
+        let (nx, ny, th) = (self.nx as usize, self.ny as usize, self.threads);
+
         //Hx
-        for y in 0..(self.ny-1) as usize {
-            for x in 1..(self.nx-1) as usize {
-                self.hx[x][y] = self.amx[x][y] * self.hx[x][y] - self.bmxy[x][y] * (self.ez[x][y+1] - self.ez[x][y]);
+        let (a, b, ez) = (&self.amx, &self.bmxy, &self.ez);
+        columns(th, &mut self.hx, 1..nx-1, |x, hx| {
+            for y in 0..ny-1 {
+                hx[y] = a[x][y] * hx[y] - b[x][y] * (ez[x][y+1] - ez[x][y]);
             }
-        }
+        });
 
         //Hy
-        for y in 1..(self.ny-1) as usize {
-            for x in 0..(self.nx-1) as usize {
-                self.hy[x][y] = self.amy[x][y] * self.hy[x][y] + self.bmyx[x][y] * (self.ez[x+1][y] - self.ez[x][y]);
+        let (a, b) = (&self.amy, &self.bmyx);
+        columns(th, &mut self.hy, 0..nx-1, |x, hy| {
+            for y in 1..ny-1 {
+                hy[y] = a[x][y] * hy[y] + b[x][y] * (ez[x+1][y] - ez[x][y]);
             }
-        }
+        });
 
         //Hz
-        for y in 0..(self.ny-1) as usize {
-            for x in 0..(self.nx-1) as usize {
-                self.hz[x][y] = self.amz[x][y] * self.hz[x][y] - self.bmzx[x][y] * (self.ey[x+1][y] - self.ey[x][y])
-                                                               + self.bmzy[x][y] * (self.ex[x][y+1] - self.ex[x][y]);
+        let (a, bx, by, ex, ey) = (&self.amz, &self.bmzx, &self.bmzy, &self.ex, &self.ey);
+        columns(th, &mut self.hz, 0..nx-1, |x, hz| {
+            for y in 0..ny-1 {
+                hz[y] = a[x][y] * hz[y] - bx[x][y] * (ey[x+1][y] - ey[x][y])
+                                        + by[x][y] * (ex[x][y+1] - ex[x][y]);
             }
+        });
+    }
+
+    // 1ステップ進める
+    // E更新 → 給電 → PML(E) → 観測点/DFTモニタ(時刻 n*dt) → H更新 → PML(H)
+    // Observerは observer.rs の順に呼ぶ
+    pub fn step(&mut self) {
+        self.step_e();
+        self.step_h();
+    }
+
+    // ステップの前半 (E更新から観測点/DFTモニタ, after_eまで)
+    // 複数の解析モジュールの境界の値を半ステップ毎に合わせる場合に step_h と組で使う
+    pub fn step_e(&mut self) {
+        self.stop = false;
+        self.nstep += 1;
+        let t = self.time();
+
+        self.e_cal();
+        for s in &self.sources {
+            let area = self.node_area(s.x, s.y);
+            self.ez[s.x][s.y] -= s.coef * s.waveform(t, self.dt) / area;
+        }
+        self.e_pml();
+
+        let mut probes = mem::take(&mut self.probes);
+        let mut monitors = mem::take(&mut self.monitors);
+        for p in &mut probes {
+            p.record(self, t);
         }
+        for m in &mut monitors {
+            m.accumulate(self, t);
+        }
+        self.probes = probes;
+        self.monitors = monitors;
+        self.observe(|o, f| o.after_e(f));
+    }
+
+    // ステップの後半 (H更新から after_h, 間隔毎のObserverまで)
+    pub fn step_h(&mut self) {
+        self.h_cal();
+        self.h_pml();
+        self.observe(|o, f| o.after_h(f));
+
+        let n = self.nstep;
+        self.observe(|o, f| match o.interval() {
+            i if i > 0 && n.is_multiple_of(i) => o.every(f),
+            _ => Control::Continue,
+        });
+    }
+
+    fn observe<F: FnMut(&mut Box<dyn Observer>, &fdtd) -> Control>(&mut self, mut call: F) {
+        if self.observers.is_empty() {
+            return;
+        }
+        let mut obs = mem::take(&mut self.observers);
+        for o in &mut obs {
+            if call(o, self) == Control::Stop {
+                self.stop = true;
+            }
+        }
+        self.observers = obs;
+    }
+
+    // nステップ進める
+    pub fn run(&mut self, n: usize) -> RunSummary {
+        for _ in 0..n {
+            self.step();
+            if self.stop {
+                return self.summary(StopReason::Observer);
+            }
+        }
+        self.summary(StopReason::Steps)
+    }
+
+    // 時刻timeに達するまで進める
+    pub fn run_until(&mut self, time: f64) -> RunSummary {
+        while self.time() < time - 0.5 * self.dt {
+            self.step();
+            if self.stop {
+                return self.summary(StopReason::Observer);
+            }
+        }
+        self.summary(StopReason::Time)
+    }
+
+    // 場が減衰するまで, またはd.max_stepsまで進める
+    pub fn run_decay(&mut self, d: &mut Decay) -> RunSummary {
+        while self.nstep < d.max_steps {
+            self.step();
+            if self.stop {
+                return self.summary(StopReason::Observer);
+            }
+            if let Some(r) = d.update(self) {
+                return self.summary(r);
+            }
+        }
+        self.summary(StopReason::MaxSteps)
+    }
+
+    // PMLを除いた領域の電磁界エネルギー (単位長さあたり, J/m)
+    pub fn energy(&self) -> f64 {
+        let l = self.lpml as usize;
+        let (nx, ny) = self.dims();
+        let mut w = 0.0;
+        for x in l..nx-l {
+            for y in l..ny-l {
+                let e = self.ex[x][y].powi(2) + self.ey[x][y].powi(2) + self.ez[x][y].powi(2);
+                let h = self.hx[x][y].powi(2) + self.hy[x][y].powi(2) + self.hz[x][y].powi(2);
+                w += (self.epsd[x+1][y+1] * EPS0 * e + self.mud[x+1][y+1] * MU0 * h) * self.xw[x] * self.yw[y];
+            }
+        }
+        0.5 * w
+    }
+
+    // Observerの登録 (戻り値は番号)
+    pub fn add_observer<O: Observer + 'static>(&mut self, o: O) -> usize {
+        self.observers.push(Box::new(o));
+        self.observers.len() - 1
+    }
+
+    // 直前のstep()でObserverが終了を要求したか
+    pub fn stop_requested(&self) -> bool {
+        self.stop
+    }
+
+    fn summary(&self, stop: StopReason) -> RunSummary {
+        RunSummary{steps: self.nstep, time: self.time(), stop}
+    }
+
+    // 完了したステップ数
+    pub fn step_count(&self) -> usize {
+        self.nstep
+    }
+
+    // 現在の時刻 (電界の時刻, 磁界は dt/2 後)
+    pub fn time(&self) -> f64 {
+        self.nstep as f64 * self.dt
+    }
+
+    // 観測点, DFTモニタの登録 (戻り値は番号)
+    pub fn add_probe(&mut self, p: Probe) -> error::Result<usize> {
+        self.check_point("probe", p.x, p.y, true)?;
+        self.probes.push(p);
+        Ok(self.probes.len() - 1)
+    }
+
+    pub fn add_monitor(&mut self, m: DftMonitor) -> error::Result<usize> {
+        self.check_monitor(m.region)?;
+        self.monitors.push(m);
+        Ok(self.monitors.len() - 1)
+    }
+
+    pub fn probes(&self) -> &[Probe] {
+        &self.probes
+    }
+
+    pub fn monitors(&self) -> &[DftMonitor] {
+        &self.monitors
+    }
+
+    // 電磁界計算のスレッド数 (1で逐次計算)
+    pub fn set_threads(&mut self, n: usize) {
+        self.threads = n.max(1);
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
     }
 
     // PML初期化 1壁
@@ -294,8 +743,11 @@ impl fdtd {
     fn initPml(&mut self, xs: i32, xl: i32, ys: i32, yl: i32) {
         self.pml_s.push(pml{x_s: xs, x_l: xl, y_s: ys, y_l: yl});
 
-        let smax0x = copml * RMAX * (ORDER + 1) as f64 / (LPML as f64 * DX);
-        let smax0y = copml * RMAX * (ORDER + 1) as f64 / (LPML as f64 * DY);
+        // PML内のセル幅は一定 (解析領域の端のセル幅) なので, 層の厚さは層数 * 端のセル幅
+        let (nxu, nyu) = (self.nx as usize, self.ny as usize);
+        let smax = |w: f64| copml * RMAX * (ORDER + 1) as f64 / (self.lpml as f64 * w);
+        let (smax0xl, smax0xr) = (smax(self.xw[0]), smax(self.xw[nxu - 1]));
+        let (smax0yl, smax0yr) = (smax(self.yw[0]), smax(self.yw[nyu - 1]));
 
         let epspml: f64 = epsbk * EPS0;
         let mupml: f64 = mubk * MU0;
@@ -309,53 +761,55 @@ impl fdtd {
 
                 let mut a;
 
-                if x < LPML { // 左側のPML初期設定
-                    sigmxm = (((LPML - x) as f64 - 0.5) / (LPML as f64)).powi(ORDER) * smax0x;
-                    sigmxe = (((LPML - x) as f64) / (LPML as f64)).powi(ORDER) * smax0x;
+                if x < self.lpml { // 左側のPML初期設定
+                    sigmxm = (((self.lpml - x) as f64 - 0.5) / (self.lpml as f64)).powi(ORDER) * smax0xl;
+                    sigmxe = (((self.lpml - x) as f64) / (self.lpml as f64)).powi(ORDER) * smax0xl;
                 }
-                else if x >= self.nx - LPML { // 右側のPML初期設定
-                    sigmxm = (((x - self.nx + LPML) as f64 + 0.5) / (LPML as f64)).powi(ORDER) * smax0x;
-                    sigmxe = (((x - self.nx + LPML) as f64) / (LPML as f64)).powi(ORDER) * smax0x;
+                else if x >= self.nx - self.lpml { // 右側のPML初期設定
+                    sigmxm = (((x - self.nx + self.lpml) as f64 + 0.5) / (self.lpml as f64)).powi(ORDER) * smax0xr;
+                    sigmxe = (((x - self.nx + self.lpml) as f64) / (self.lpml as f64)).powi(ORDER) * smax0xr;
                 }
                 else {
                     sigmxm = 0.0;
                     sigmxe = 0.0;
                 }
 
-                if y < LPML { // 上側のPML初期設定
-                    sigmym = (((LPML - y) as f64 - 0.5) / (LPML as f64)).powi(ORDER) * smax0y;
-                    sigmye = (((LPML - y) as f64) / (LPML as f64)).powi(ORDER) * smax0y;
+                if y < self.lpml { // 上側のPML初期設定
+                    sigmym = (((self.lpml - y) as f64 - 0.5) / (self.lpml as f64)).powi(ORDER) * smax0yl;
+                    sigmye = (((self.lpml - y) as f64) / (self.lpml as f64)).powi(ORDER) * smax0yl;
                 }
-                else if y >= self.ny - LPML { // 下側のPML初期設定
-                    sigmym = (((y - self.ny + LPML) as f64 + 0.5) / (LPML as f64)).powi(ORDER) * smax0y;
-                    sigmye = (((y - self.ny + LPML) as f64) / (LPML as f64)).powi(ORDER) * smax0y;
+                else if y >= self.ny - self.lpml { // 下側のPML初期設定
+                    sigmym = (((y - self.ny + self.lpml) as f64 + 0.5) / (self.lpml as f64)).powi(ORDER) * smax0yr;
+                    sigmye = (((y - self.ny + self.lpml) as f64) / (self.lpml as f64)).powi(ORDER) * smax0yr;
                 }
                 else {
                     sigmym = 0.0;
                     sigmye = 0.0;
                 }
 
+                let (xu, yu) = (x as usize, y as usize);
+
                 // PML電界の初期設定
-                sigmxe = sigmxe * epsbk;
+                sigmxe *= epsbk;
                 a = 0.5 * sigmxe * self.dt / epspml;
                 self.aexpml[x as usize][y as usize] = (1.0 - a) / (1.0 + a);
-                self.bexpml[x as usize][y as usize] = self.dt / epspml / (1.0 + a) / DX;
+                self.bexpml[x as usize][y as usize] = self.dt / epspml / (1.0 + a) / dual(&self.xw, xu);
 
-                sigmye = sigmye * epsbk;
+                sigmye *= epsbk;
                 a = 0.5 * sigmye * self.dt / epspml;
                 self.aeypml[x as usize][y as usize] = (1.0 - a) / (1.0 + a);
-                self.beypml[x as usize][y as usize] = self.dt / epspml / (1.0 + a) / DY;
+                self.beypml[x as usize][y as usize] = self.dt / epspml / (1.0 + a) / dual(&self.yw, yu);
 
                 // PML磁界の初期設定
-                sigmxm = sigmxm * epsbk;
+                sigmxm *= epsbk;
                 a = 0.5 * sigmxm * self.dt / epspml;
                 self.amxpml[x as usize][y as usize] = (1.0 - a) / (1.0 + a);
-                self.bmxpml[x as usize][y as usize] = self.dt / mupml / (1.0 + a) / DX;
+                self.bmxpml[x as usize][y as usize] = self.dt / mupml / (1.0 + a) / self.xw[xu];
 
-                sigmym = sigmym * epsbk;
+                sigmym *= epsbk;
                 a = 0.5 * sigmym * self.dt / epspml;
                 self.amypml[x as usize][y as usize] = (1.0 - a) / (1.0 + a);
-                self.bmypml[x as usize][y as usize] = self.dt / mupml / (1.0 + a) / DY;
+                self.bmypml[x as usize][y as usize] = self.dt / mupml / (1.0 + a) / self.yw[yu];
 
             }
         }
@@ -364,12 +818,17 @@ impl fdtd {
 
     // PML内初期化設定
     fn init_pml(&mut self) {
-        let nxt = self.nx.clone();
-        let nyt = self.ny.clone();
-        self.initPml(0, LPML, 0, nyt.clone());
-        self.initPml(nxt.clone()-LPML, nxt.clone(), 0, nyt.clone());
-        self.initPml(LPML, nxt.clone()-LPML, 0, LPML);
-        self.initPml(LPML, nxt.clone()-LPML, nxt.clone()-LPML, nyt.clone());
+        let nxt = self.nx;
+        let nyt = self.ny;
+        self.pml_s.clear();
+        // PML層数0の場合は外周を完全導体とする
+        if self.lpml == 0 {
+            return;
+        }
+        self.initPml(0, self.lpml, 0, nyt);
+        self.initPml(nxt-self.lpml, nxt, 0, nyt);
+        self.initPml(self.lpml, nxt-self.lpml, 0, self.lpml);
+        self.initPml(self.lpml, nxt-self.lpml, nyt-self.lpml, nyt);
     }
 
     // PML内電界計算
@@ -380,7 +839,7 @@ impl fdtd {
             for y in (n.y_s+1) as usize..(n.y_l-1) as usize {
                 for x in (n.x_s as usize)..(n.x_l-1) as usize {
                     self.expml[x][y] = self.aeypml[x][y] * self.expml[x][y] + self.beypml[x][y] * (self.hz[x][y] - self.hz[x][y-1]);
-                    self.ex[x][y] = self.expml[x][y].clone();
+                    self.ex[x][y] = self.expml[x][y];
                 }
             }
 
@@ -388,12 +847,12 @@ impl fdtd {
             for y in (n.y_s as usize)..((n.y_l-1) as usize) {
                 for x in ((n.x_s+1) as usize)..((n.x_l-1) as usize) {
                     self.eypml[x][y] = self.aexpml[x][y] * self.eypml[x][y] - self.bexpml[x][y] * (self.hz[x][y] - self.hz[x-1][y]);
-                    self.ey[x][y] = self.eypml[x][y].clone();
+                    self.ey[x][y] = self.eypml[x][y];
                 }
             }
 
             //Ez
-            for y in ((n.y_s+1) as usize)..((n.y_l-1) as usize) as usize {
+            for y in ((n.y_s+1) as usize)..(n.y_l-1) as usize {
                 for x in ((n.x_s+1) as usize)..((n.x_l-1) as usize) {
                     self.ezx[x][y] = self.aexpml[x][y] * self.ezx[x][y] + self.bexpml[x][y] * (self.hy[x][y] - self.hy[x-1][y]);
                     self.ezy[x][y] = self.aeypml[x][y] * self.ezy[x][y] - self.beypml[x][y] * (self.hx[x][y] - self.hx[x][y-1]);
@@ -412,7 +871,7 @@ impl fdtd {
             for y in (n.y_s as usize)..((n.y_l - 1) as usize) {
                 for x in ((n.x_s + 1) as usize)..((n.x_l - 1) as usize) {
                     self.hxpml[x][y] = self.amypml[x][y] * self.hxpml[x][y] - self.bmypml[x][y] * (self.ez[x][y+1] - self.ez[x][y]);
-                    self.hx[x][y] = self.hxpml[x][y].clone();
+                    self.hx[x][y] = self.hxpml[x][y];
                 }
             }
 
@@ -420,12 +879,12 @@ impl fdtd {
             for y in ((n.y_s + 1) as usize)..((n.y_l - 1) as usize) {
                 for x in (n.x_s as usize)..((n.x_l - 1) as usize) {
                     self.hypml[x][y] = self.amxpml[x][y] * self.hypml[x][y] + self.bmxpml[x][y] * (self.ez[x+1][y] - self.ez[x][y]);
-                    self.hy[x][y] = self.hypml[x][y].clone();
+                    self.hy[x][y] = self.hypml[x][y];
                 }
             }
 
             //Hz
-            for y in (n.y_s as usize)..((n.y_l - 1) as usize) as usize {
+            for y in (n.y_s as usize)..(n.y_l - 1) as usize {
                 for x in (n.x_s as usize)..((n.x_l - 1) as usize) {
                     self.hzx[x][y] = self.amxpml[x][y] * self.hzx[x][y] - self.bmxpml[x][y] * (self.ey[x+1][y] - self.ey[x][y]);
                     self.hzy[x][y] = self.amypml[x][y] * self.hzy[x][y] + self.bmypml[x][y] * (self.ex[x][y+1] - self.ex[x][y]);
@@ -437,19 +896,37 @@ impl fdtd {
     }
 
     // 障害物媒質設定 epsr: 障害物の誘電率
-    pub fn epsmu(&mut self, x_s: usize, x_l: usize, y_s: usize, y_l: usize, epsr: f64){
+    pub fn epsmu(&mut self, x_s: usize, x_l: usize, y_s: usize, y_l: usize, epsr: f64) -> error::Result<()> {
+        self.medium(x_s, x_l, y_s, y_l, Medium{eps: epsr, ..Medium::default()})
+    }
+
+    // 障害物媒質設定 (誘電率, 透磁率, 導電率, 磁気伝導率)
+    pub fn medium(&mut self, x_s: usize, x_l: usize, y_s: usize, y_l: usize, m: Medium) -> error::Result<()> {
+        self.check_rect("medium", x_s, x_l, y_s, y_l)?;
+        for &(what, v) in [("permittivity", m.eps), ("permeability", m.mu)].iter() {
+            if !(v > 0.0 && v.is_finite()) {
+                return Err(FdtdError::InvalidValue{what, value: v});
+            }
+        }
+        for &(what, v) in [("conductivity", m.sigma_e), ("magnetic conductivity", m.sigma_m)].iter() {
+            if !(v >= 0.0 && v.is_finite()) {
+                return Err(FdtdError::InvalidValue{what, value: v});
+            }
+        }
         for y in y_s+1..y_l {
             for x in x_s+1..x_l {
-                self.epsd[x][y] = epsr;
-                self.mud[x][y] = 1.0;
-                self.sgmed[x][y] = 0.0;
-                self.sgmmd[x][y] = 0.0;
+                self.epsd[x][y] = m.eps;
+                self.mud[x][y] = m.mu;
+                self.sgmed[x][y] = m.sigma_e;
+                self.sgmmd[x][y] = m.sigma_m;
             }
         }
+        Ok(())
     }
 
     // 障害物設定 完全導体
-    pub fn pec_rect(&mut self, x_s: usize, x_l: usize, y_s: usize, y_l: usize){
+    pub fn pec_rect(&mut self, x_s: usize, x_l: usize, y_s: usize, y_l: usize) -> error::Result<()> {
+        self.check_rect("pec_rect", x_s, x_l, y_s, y_l)?;
         for y in y_s..y_l {
             for x in x_s..x_l-1 {
                 self.aex[x][y] = 0.0;
@@ -467,57 +944,576 @@ impl fdtd {
                 self.aez[x][y] = 0.0;
                 self.bezx[x][y] = 0.0;
                 self.bezy[x][y] = 0.0;
+                self.pec[x][y] = true;
             }
         }
+        Ok(())
+    }
+
+    // 範囲 [x_s, x_l) x [y_s, y_l) の検査
+    fn check_rect(&self, what: &'static str, x_s: usize, x_l: usize, y_s: usize, y_l: usize) -> error::Result<()> {
+        if x_s >= x_l {
+            return Err(FdtdError::ReversedRange{what, axis: 'x', start: x_s, end: x_l});
+        }
+        if y_s >= y_l {
+            return Err(FdtdError::ReversedRange{what, axis: 'y', start: y_s, end: y_l});
+        }
+        let (nx, ny) = self.dims();
+        if x_l > nx || y_l > ny {
+            return Err(FdtdError::OutOfBounds{what, x: x_l, y: y_l, nx, ny});
+        }
+        Ok(())
+    }
+
+    // 観測領域の検査 (PMLに掛かるものは不可)
+    pub fn check_monitor(&self, r: Region) -> error::Result<()> {
+        self.check_rect("monitor", r.x_s, r.x_l, r.y_s, r.y_l)?;
+        let (nx, ny) = self.dims();
+        let l = self.lpml as usize;
+        if r.x_s < l || r.y_s < l || r.x_l > nx - l || r.y_l > ny - l {
+            return Err(FdtdError::InPml{what: "monitor", x: r.x_s, y: r.y_s, lpml: l});
+        }
+        Ok(())
+    }
+
+    // 点(x, y)の検査 (inner: PML内を不可とする)
+    fn check_point(&self, what: &'static str, x: usize, y: usize, inner: bool) -> error::Result<()> {
+        let (nx, ny) = self.dims();
+        if x >= nx || y >= ny {
+            return Err(FdtdError::OutOfBounds{what, x, y, nx, ny});
+        }
+        let l = self.lpml as usize;
+        if inner && (x < l || y < l || x >= nx - l || y >= ny - l) {
+            return Err(FdtdError::InPml{what, x, y, lpml: l});
+        }
+        Ok(())
     }
 
     // 電流源の初期化
-    pub fn init_source(&mut self, x: usize, y: usize){
+    pub fn init_source(&mut self, x: usize, y: usize) -> error::Result<()> {
+        self.check_point("source", x, y, true)?;
         let epsz = 0.25 * (self.epsd[x+1][y+1] + self.epsd[x][y+1] + self.epsd[x+1][y] + self.epsd[x][y]) * EPS0;
         self.befed = self.dt / epsz;
-        self.duration = 0.1e-9;
+        self.duration = DURATION;
         self.t0 = self.duration * 4.0;
+        self.sources.push(Source{coef: self.befed, ..Source::new(x, y)});
+        Ok(())
+    }
+
+    // 波形を指定した給電点の追加 (戻り値は番号)
+    pub fn add_source(&mut self, s: Source) -> error::Result<usize> {
+        self.check_point("source", s.x, s.y, true)?;
+        if !s.duration.is_finite() || s.duration <= 0.0 {
+            return Err(FdtdError::InvalidValue{what: "source duration", value: s.duration});
+        }
+        if !s.resistance.is_finite() || s.resistance < 0.0 {
+            return Err(FdtdError::InvalidValue{what: "source resistance", value: s.resistance});
+        }
+        let coef = if s.resistance > 0.0 {
+            if !s.length.is_finite() || s.length <= 0.0 {
+                return Err(FdtdError::InvalidValue{what: "lumped source length", value: s.length});
+            }
+            self.lumped_coefficients(&s)
+        } else {
+            let (x, y) = (s.x, s.y);
+            let epsz = 0.25 * (self.epsd[x+1][y+1] + self.epsd[x][y+1] + self.epsd[x+1][y] + self.epsd[x][y]) * EPS0;
+            self.dt / epsz
+        };
+        self.sources.push(Source{coef, ..s});
+        Ok(self.sources.len() - 1)
+    }
+
+    // 集中定数給電の抵抗を給電点のEzの係数に含め (導電率 length/(R 給電点の面積) と同じ扱い), 給電の係数を返す
+    // setupで係数を作り直すので, setupの後にも適用する
+    fn lumped_coefficients(&mut self, s: &Source) -> f64 {
+        let (x, y) = (s.x, s.y);
+        let e = &self.epsd;
+        let g = &self.sgmed;
+        let epsz = 0.25 * (e[x+1][y+1] + e[x+1][y] + e[x][y+1] + e[x][y]) * EPS0;
+        let sgez = 0.25 * (g[x+1][y+1] + g[x+1][y] + g[x][y+1] + g[x][y]) + s.length / (s.resistance * self.node_area(x, y));
+        let a = 0.5 * sgez * self.dt / epsz;
+        self.aez[x][y] = (1.0 - a) / (1.0 + a);
+        self.bezy[x][y] = self.dt / epsz / (1.0 + a) / dual(&self.yw, y);
+        self.bezx[x][y] = self.dt / epsz / (1.0 + a) / dual(&self.xw, x);
+        // 電流 V/R
+        self.dt / epsz / (1.0 + a) / s.resistance
     }
 
     // 電流の計算
-    pub fn feed(&mut self, x: usize, y: usize, t: f64){
+    pub fn feed(&mut self, x: usize, y: usize, t: f64) -> error::Result<()> {
+        if self.sources.is_empty() {
+            return Err(FdtdError::NoSource);
+        }
+        self.check_point("source", x, y, true)?;
+        self.inject(x, y, t);
+        Ok(())
+    }
+
+    fn inject(&mut self, x: usize, y: usize, t: f64) {
         let mut tmp:f64 = (t - 0.5 * self.dt - self.t0) / self.duration;
         tmp = tmp.powi(2);
         let iz = (-tmp).exp();
         //let iz = (-((t - 0.5 * self.dt - self.t0) / self.duration).powf(2.0)).exp();
-        self.ez[x][y] = self.ez[x][y] - self.befed * iz / (DX * DY);
-        println!("ez:{} iz:{} befed:{}", self.ez[x][y], iz, self.befed);
+        self.ez[x][y] -= self.befed * iz / self.node_area(x, y);
+        log_trace!("ez:{} iz:{} befed:{}", self.ez[x][y], iz, self.befed);
+    }
+
+    // 場の成分の参照
+    pub fn field(&self, c: Component) -> &Vec<Vec<f64>> {
+        match c {
+            Component::Ex => &self.ex,
+            Component::Ey => &self.ey,
+            Component::Ez => &self.ez,
+            Component::Hx => &self.hx,
+            Component::Hy => &self.hy,
+            Component::Hz => &self.hz,
+        }
+    }
+
+    // 場の成分の変更 (境界条件の適用等)
+    pub fn field_mut(&mut self, c: Component) -> &mut Vec<Vec<f64>> {
+        match c {
+            Component::Ex => &mut self.ex,
+            Component::Ey => &mut self.ey,
+            Component::Ez => &mut self.ez,
+            Component::Hx => &mut self.hx,
+            Component::Hy => &mut self.hy,
+            Component::Hz => &mut self.hz,
+        }
     }
 
-    pub fn out_p(&self, xo: usize, yo: usize) {
-        for y in LPML as usize..(self.ny - LPML) as usize {
-            for x in LPML as usize.. (self.nx - LPML) as usize {
-                println!("ez[{}][{}] = {}", x-LPML as usize, y-LPML as usize, self.ez[x][y]);
+    // 成分cの位置(x, y)での比誘電率(E成分)または比透磁率(H成分) (setupと同じ平均)
+    pub fn local_medium(&self, c: Component, x: usize, y: usize) -> f64 {
+        let (e, m) = (&self.epsd, &self.mud);
+        match c {
+            Component::Ex => 0.5 * (e[x+1][y+1] + e[x+1][y]),
+            Component::Ey => 0.5 * (e[x+1][y+1] + e[x][y+1]),
+            Component::Ez => 0.25 * (e[x+1][y+1] + e[x+1][y] + e[x][y+1] + e[x][y]),
+            Component::Hx => 0.5 * (m[x+1][y+1] + m[x][y+1]),
+            Component::Hy => 0.5 * (m[x+1][y+1] + m[x+1][y]),
+            Component::Hz => m[x+1][y+1],
+        }
+    }
+
+    // 成分cの位置(x, y)の更新係数 (前の値の係数, 差分の代わりに周回積分/面積に掛ける係数 dt / ε(1 + a) 等)
+    // 完全導体の電界は (0, 0)
+    pub fn coefficients(&self, c: Component, x: usize, y: usize) -> (f64, f64) {
+        match c {
+            Component::Ex => (self.aex[x][y], self.bexy[x][y] * dual(&self.yw, y)),
+            Component::Ey => (self.aey[x][y], self.beyx[x][y] * dual(&self.xw, x)),
+            Component::Ez => (self.aez[x][y], self.bezx[x][y] * dual(&self.xw, x)),
+            Component::Hx => (self.amx[x][y], self.bmxy[x][y] * self.yw[y]),
+            Component::Hy => (self.amy[x][y], self.bmyx[x][y] * self.xw[x]),
+            Component::Hz => (self.amz[x][y], self.bmzx[x][y] * self.xw[x]),
+        }
+    }
+
+    // 配列番号(x, y)を左下の角とするセルの媒質定数 (完全導体は1.0/0.0)
+    pub fn material(&self, m: Material, x: usize, y: usize) -> f64 {
+        match m {
+            Material::Eps => self.epsd[x+1][y+1],
+            Material::Mu => self.mud[x+1][y+1],
+            Material::SigmaE => self.sgmed[x+1][y+1],
+            Material::SigmaM => self.sgmmd[x+1][y+1],
+            Material::Pec => if self.pec[x][y] { 1.0 } else { 0.0 },
+        }
+    }
+
+    // 初期化済みの給電点
+    pub fn sources(&self) -> &[Source] {
+        &self.sources
+    }
+
+    // 解析条件 (名前, 値)
+    pub fn params(&self) -> Vec<(&'static str, f64)> {
+        vec![("nx", self.nx as f64), ("ny", self.ny as f64), ("dx", self.dx), ("dy", self.dy), ("dt", self.dt),
+             ("lpml", self.lpml as f64), ("order", ORDER as f64), ("rmax", RMAX),
+             ("epsbk", epsbk), ("mubk", mubk), ("sigebk", sigebk), ("sigmbk", sigmbk)]
+    }
+
+    // PMLを除いた解析領域のセル数
+    pub fn interior(&self) -> (usize, usize) {
+        ((self.nx - 2 * self.lpml) as usize, (self.ny - 2 * self.lpml) as usize)
+    }
+
+    // PML層数
+    pub fn lpml(&self) -> usize {
+        self.lpml as usize
+    }
+
+    // 配列のセル数 (PMLを含む)
+    pub fn dims(&self) -> (usize, usize) {
+        (self.nx as usize, self.ny as usize)
+    }
+
+    // セルサイズ (不均一メッシュでは最小のセル幅)
+    pub fn cell_size(&self) -> (f64, f64) {
+        (self.dx, self.dy)
+    }
+
+    // 列, 行毎のセル幅 (PMLを含む)
+    pub fn widths(&self) -> (&[f64], &[f64]) {
+        (&self.xw, &self.yw)
+    }
+
+    // 全てのセル幅が等しいか (不均一メッシュに対応していない機能の判定)
+    pub fn is_uniform(&self) -> bool {
+        self.xw.iter().all(|&w| w == self.dx) && self.yw.iter().all(|&w| w == self.dy)
+    }
+
+    // 均一メッシュを前提とする機能の入口で使う (不均一ならUnsupported)
+    pub fn require_uniform(&self, what: &'static str) -> error::Result<()> {
+        if self.is_uniform() { Ok(()) } else { Err(FdtdError::Unsupported{what}) }
+    }
+
+    // 節点(x, y)の双対セル幅 (Ezの周りの面積は dx * dy)
+    pub fn node_width(&self, x: usize, y: usize) -> (f64, f64) {
+        (dual(&self.xw, x), dual(&self.yw, y))
+    }
+
+    fn node_area(&self, x: usize, y: usize) -> f64 {
+        let (dx, dy) = self.node_width(x, y);
+        dx * dy
+    }
+
+    // 配列番号(x, y)の座標 (PMLを除いた解析領域の端を原点とする)
+    pub fn position(&self, x: usize, y: usize) -> (f64, f64) {
+        (self.xn[x], self.yn[y])
+    }
+
+    // 座標の原点をずらす (局所細分格子の座標を親の解析領域に合わせる等)
+    pub fn translate(&mut self, x: f64, y: f64) {
+        for v in &mut self.xn {
+            *v += x;
+        }
+        for v in &mut self.yn {
+            *v += y;
+        }
+    }
+
+    // 座標(x, y)に最も近い節点の配列番号 (配列の範囲に切り詰める)
+    pub fn index(&self, x: f64, y: f64) -> (usize, usize) {
+        (nearest(&self.xn[..self.nx as usize], x), nearest(&self.yn[..self.ny as usize], y))
+    }
+
+    // 座標で指定した矩形 [x_s, x_l] x [y_s, y_l] (m) の媒質設定
+    // 矩形の辺は最も近い節点に合わせる (不均一メッシュでも細かいセルの位置に正しく載る)
+    pub fn medium_at(&mut self, x_s: f64, x_l: f64, y_s: f64, y_l: f64, m: Medium) -> error::Result<()> {
+        let (i_s, j_s) = self.index(x_s, y_s);
+        let (i_l, j_l) = self.index(x_l, y_l);
+        // mediumの範囲 [x_s, x_l) はセル x_s..x_l-1 を設定する
+        self.medium(i_s, i_l + 1, j_s, j_l + 1, m)
+    }
+
+    // 座標で指定した矩形 [x_s, x_l] x [y_s, y_l] (m) の完全導体
+    pub fn pec_at(&mut self, x_s: f64, x_l: f64, y_s: f64, y_l: f64) -> error::Result<()> {
+        let (i_s, j_s) = self.index(x_s, y_s);
+        let (i_l, j_l) = self.index(x_l, y_l);
+        self.pec_rect(i_s, i_l + 1, j_s, j_l + 1)
+    }
+
+    // セル幅と節点座標を設定し, 最小のセル幅でdtを決め直す
+    fn set_widths(&mut self, xw: Vec<f64>, yw: Vec<f64>) {
+        let l = self.lpml as usize;
+        let origin = |w: &[f64]| w[..l].iter().sum::<f64>();
+        let (ox, oy) = (origin(&xw), origin(&yw));
+        self.xn = mesh::nodes(&xw).iter().map(|v| v - ox).collect();
+        self.yn = mesh::nodes(&yw).iter().map(|v| v - oy).collect();
+        self.dx = xw.iter().cloned().fold(f64::INFINITY, f64::min);
+        self.dy = yw.iter().cloned().fold(f64::INFINITY, f64::min);
+        self.xw = xw;
+        self.yw = yw;
+        self.dt = fdtdBuilder::time_step(self.dx, self.dy);
+    }
+
+    pub fn out_p(&self, xo: usize, yo: usize) -> error::Result<()> {
+        self.check_point("observation point", xo, yo, false)?;
+        for y in self.lpml as usize..(self.ny - self.lpml) as usize {
+            for x in self.lpml as usize.. (self.nx - self.lpml) as usize {
+                println!("ez[{}][{}] = {}", x-self.lpml as usize, y-self.lpml as usize, self.ez[x][y]);
             }
         }
         println!("Observation point: {}",self.ez[xo][yo]);
+        Ok(())
     }
 
-    #[allow(unused_must_use)]
-    pub fn out_file(&self, file: &mut File, xo: usize, yo: usize) {
-        for y in LPML as usize..(self.ny - LPML) as usize {
-            for x in LPML as usize.. (self.nx - LPML) as usize {
-                write!(*file, "ez[{}][{}] = {}\r\n", x-LPML as usize, y-LPML as usize, self.ez[x][y]);
+    pub fn out_file(&self, file: &mut File, xo: usize, yo: usize) -> error::Result<()> {
+        self.check_point("observation point", xo, yo, false)?;
+        for y in self.lpml as usize..(self.ny - self.lpml) as usize {
+            for x in self.lpml as usize.. (self.nx - self.lpml) as usize {
+                write!(*file, "ez[{}][{}] = {}\r\n", x-self.lpml as usize, y-self.lpml as usize, self.ez[x][y])?;
             }
         }
-        write!(*file,"Observation point: {}\r\n",self.ez[xo][yo]);
+        write!(*file,"Observation point: {}\r\n",self.ez[xo][yo])?;
+        Ok(())
     }
 
-    pub fn out_file_gnu(&self, file: &mut File) {
-        for y in LPML as usize..(self.ny - LPML) as usize {
-            for x in LPML as usize.. (self.nx - LPML) as usize {
-                if x % 50 == 0 && y % 50 == 0 {
-                    write!(*file, "{} {} {}\r\n", (x-LPML as usize) as f64 * DX, (y-LPML as usize) as f64 * DY, self.ez[x][y]);
-                }
+    // gnuplot形式の出力(50セル毎に間引き)
+    pub fn out_file_gnu(&self, file: &mut File) -> Result<()> {
+        let snap = SnapshotWriter::new("").stride(50, 50).capture(self, self.nstep, self.time());
+        GnuplotBackend.write_to(file, &snap, LineEnding::CrLf)
+    }
+
+    state_arrays!(ex, ey, ez, hx, hy, hz,
+                  aexpml, aeypml, aex, aey, aez, bexpml, beypml, bexy, beyx, bezx, bezy,
+                  amxpml, amypml, amx, amy, amz, bmxpml, bmypml, bmxy, bmyx, bmzx, bmzy,
+                  expml, eypml, ezx, ezy, hxpml, hypml, hzx, hzy,
+                  epsd, sgmed, mud, sgmmd);
+
+    // 計算状態の書き出し (Observerは含まない, 数値は全てリトルエンディアン)
+    pub fn write_state<W: Write>(&self, w: &mut W) -> Result<()> {
+        for v in [self.nx as u64, self.ny as u64, self.lpml as u64, self.nstep as u64, self.threads as u64].iter() {
+            put_u64(w, *v)?;
+        }
+        for v in [self.dx, self.dy, self.dt, self.befed, self.duration, self.t0].iter() {
+            put_f64(w, *v)?;
+        }
+        put_f64s(w, &self.xw)?;
+        put_f64s(w, &self.yw)?;
+
+        put_u64(w, self.sources.len() as u64)?;
+        for s in &self.sources {
+            put_u64(w, s.x as u64)?;
+            put_u64(w, s.y as u64)?;
+            for v in [s.duration, s.t0, s.freq, s.amplitude, s.resistance, s.length, s.coef].iter() {
+                put_f64(w, *v)?;
+            }
+        }
+        put_u64(w, self.pml_s.len() as u64)?;
+        for p in &self.pml_s {
+            for v in [p.x_s, p.x_l, p.y_s, p.y_l].iter() {
+                w.write_all(&v.to_le_bytes())?;
+            }
+        }
+
+        for a in self.state_arrays() {
+            put_u64(w, a.len() as u64)?;
+            put_u64(w, a.first().map_or(0, |c| c.len()) as u64)?;
+            for c in a {
+                put_f64s(w, c)?;
+            }
+        }
+        for c in &self.pec {
+            let b: Vec<u8> = c.iter().map(|&p| p as u8).collect();
+            w.write_all(&b)?;
+        }
+
+        put_u64(w, self.probes.len() as u64)?;
+        for p in &self.probes {
+            w.write_all(&[component_code(p.component)])?;
+            put_u64(w, p.x as u64)?;
+            put_u64(w, p.y as u64)?;
+            put_u64(w, p.times.len() as u64)?;
+            put_f64s(w, &p.times)?;
+            put_f64s(w, &p.values)?;
+        }
+        put_u64(w, self.monitors.len() as u64)?;
+        for m in &self.monitors {
+            w.write_all(&[component_code(m.component)])?;
+            let r = m.region;
+            for v in [r.x_s, r.x_l, r.y_s, r.y_l, m.freqs.len()].iter() {
+                put_u64(w, *v as u64)?;
+            }
+            put_f64s(w, &m.freqs)?;
+            put_f64s(w, &m.xs)?;
+            put_f64s(w, &m.ys)?;
+            for d in &m.data {
+                let flat: Vec<f64> = d.iter().flat_map(|c| vec![c.re, c.im]).collect();
+                put_f64s(w, &flat)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[allow(non_snake_case)]
+impl fdtdBuilder {
+    // write_stateで書き出した状態から復元
+    pub fn from_state<R: Read>(r: &mut R) -> Result<fdtd> {
+        let (nx, ny, lpml) = (get_u64(r)?, get_u64(r)?, get_u64(r)?);
+        let (nstep, threads) = (get_u64(r)? as usize, get_u64(r)? as usize);
+        let (dx, dy) = (get_f64(r)?, get_f64(r)?);
+        // 壊れたヘッダで巨大な配列を確保しないよう, 確保前にセル数を検査
+        if nx > 1 << 20 || ny > 1 << 20 || nx <= 2 * lpml || ny <= 2 * lpml || nx * ny > MAX_STATE_CELLS {
+            return Err(invalid("invalid grid size in state"));
+        }
+        let (nx, ny, lpml) = (nx as i32, ny as i32, lpml as i32);
+        let mut f = fdtdBuilder::newGrid(nx - 2 * lpml, ny - 2 * lpml, dx, dy, lpml);
+        f.nstep = nstep;
+        f.threads = threads.max(1);
+        f.dt = get_f64(r)?;
+        f.befed = get_f64(r)?;
+        f.duration = get_f64(r)?;
+        f.t0 = get_f64(r)?;
+        let (xw, yw) = (get_f64s(r, nx as usize)?, get_f64s(r, ny as usize)?);
+        if xw.iter().chain(yw.iter()).any(|&w| !(w > 0.0 && w.is_finite())) {
+            return Err(invalid("invalid cell width in state"));
+        }
+        if xw.iter().any(|&w| w != dx) || yw.iter().any(|&w| w != dy) {
+            let dt = f.dt;
+            f.set_widths(xw, yw);
+            f.dt = dt;
+        }
+
+        for _ in 0..get_u64(r)? {
+            let (x, y) = (get_u64(r)? as usize, get_u64(r)? as usize);
+            let (duration, t0) = (get_f64(r)?, get_f64(r)?);
+            let (freq, amplitude) = (get_f64(r)?, get_f64(r)?);
+            let (resistance, length, coef) = (get_f64(r)?, get_f64(r)?, get_f64(r)?);
+            if x >= nx as usize || y >= ny as usize {
+                return Err(invalid("source out of grid in state"));
+            }
+            f.sources.push(Source{x, y, duration, t0, freq, amplitude, resistance, length, coef});
+        }
+        for _ in 0..get_u64(r)? {
+            let mut v = [0i32; 4];
+            for e in v.iter_mut() {
+                let mut b = [0u8; 4];
+                r.read_exact(&mut b)?;
+                *e = i32::from_le_bytes(b);
+            }
+            if v[0] < 0 || v[0] > v[1] || v[1] > nx || v[2] < 0 || v[2] > v[3] || v[3] > ny {
+                return Err(invalid("invalid pml range in state"));
+            }
+            f.pml_s.push(pml{x_s: v[0], x_l: v[1], y_s: v[2], y_l: v[3]});
+        }
+
+        for a in f.state_arrays_mut() {
+            let (rows, cols) = (get_u64(r)? as usize, get_u64(r)? as usize);
+            if rows != a.len() || cols != a[0].len() {
+                return Err(invalid("array shape mismatch in state"));
+            }
+            for c in a.iter_mut() {
+                *c = get_f64s(r, cols)?;
+            }
+        }
+        for c in f.pec.iter_mut() {
+            let mut b = vec![0u8; c.len()];
+            r.read_exact(&mut b)?;
+            *c = b.iter().map(|&v| v != 0).collect();
+        }
+
+        for _ in 0..get_u64(r)? {
+            let c = get_component(r)?;
+            let mut p = Probe::new(c, get_u64(r)? as usize, get_u64(r)? as usize);
+            if p.x >= nx as usize || p.y >= ny as usize {
+                return Err(invalid("probe out of grid in state"));
+            }
+            let n = get_u64(r)? as usize;
+            p.times = get_f64s(r, n)?;
+            p.values = get_f64s(r, n)?;
+            f.probes.push(p);
+        }
+        for _ in 0..get_u64(r)? {
+            let component = get_component(r)?;
+            let region = Region{x_s: get_u64(r)? as usize, x_l: get_u64(r)? as usize, y_s: get_u64(r)? as usize, y_l: get_u64(r)? as usize};
+            if f.check_monitor(region).is_err() {
+                return Err(invalid("invalid monitor region in state"));
             }
-            if y % 50 == 0 {
-                write!(*file, "\r\n");
+            let nf = get_u64(r)? as usize;
+            let freqs = get_f64s(r, nf)?;
+            let xs = get_f64s(r, region.x_l - region.x_s)?;
+            let ys = get_f64s(r, region.y_l - region.y_s)?;
+            let n = (region.x_l - region.x_s) * (region.y_l - region.y_s);
+            let mut data = Vec::with_capacity(nf);
+            for _ in 0..nf {
+                data.push(get_f64s(r, 2 * n)?.chunks(2).map(|c| Complex::new(c[0], c[1])).collect());
             }
+            f.monitors.push(DftMonitor{component, region, freqs, data, xs, ys});
+        }
+        Ok(f)
+    }
+}
+
+// 節点 i の双対セル幅 (両隣のセル幅の平均, 配列の端は端のセル幅)
+fn dual(w: &[f64], i: usize) -> f64 {
+    if i == 0 || i >= w.len() {
+        w[i.min(w.len() - 1)]
+    } else {
+        0.5 * (w[i - 1] + w[i])
+    }
+}
+
+// 昇順の座標 xs のうち v に最も近いものの番号
+fn nearest(xs: &[f64], v: f64) -> usize {
+    match xs.iter().position(|&x| x >= v) {
+        Some(0) => 0,
+        Some(i) => if xs[i] - v < v - xs[i - 1] { i } else { i - 1 },
+        None => xs.len() - 1,
+    }
+}
+
+fn put_u64<W: Write>(w: &mut W, v: u64) -> Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+fn put_f64<W: Write>(w: &mut W, v: f64) -> Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+fn put_f64s<W: Write>(w: &mut W, v: &[f64]) -> Result<()> {
+    let b: Vec<u8> = v.iter().flat_map(|x| x.to_le_bytes()).collect();
+    w.write_all(&b)
+}
+
+fn get_u64<R: Read>(r: &mut R) -> Result<u64> {
+    let mut b = [0u8; 8];
+    r.read_exact(&mut b)?;
+    Ok(u64::from_le_bytes(b))
+}
+
+fn get_f64<R: Read>(r: &mut R) -> Result<f64> {
+    let mut b = [0u8; 8];
+    r.read_exact(&mut b)?;
+    Ok(f64::from_le_bytes(b))
+}
+
+// 個数はファイル由来なので先に確保せず, 実際に読めた分だけ伸ばす
+fn get_f64s<R: Read>(r: &mut R, n: usize) -> Result<Vec<f64>> {
+    let len = n.checked_mul(8).ok_or_else(|| invalid("invalid array length in state"))?;
+    let mut b = Vec::new();
+    r.take(len as u64).read_to_end(&mut b)?;
+    if b.len() != len {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "truncated state"));
+    }
+    Ok(b.chunks(8).map(|c| f64::from_le_bytes([c[0], c[1], c[2], c[3], c[4], c[5], c[6], c[7]])).collect())
+}
+
+fn component_code(c: Component) -> u8 {
+    Component::ALL.iter().position(|&a| a == c).unwrap_or(0) as u8
+}
+
+fn get_component<R: Read>(r: &mut R) -> Result<Component> {
+    let mut b = [0u8; 1];
+    r.read_exact(&mut b)?;
+    Component::ALL.get(b[0] as usize).cloned().ok_or_else(|| invalid("invalid component in state"))
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+// 配列の列 x in xs に f(x, 列) を適用 (threads > 1 の場合は列をまとめてスレッドに分配)
+fn columns<F>(threads: usize, v: &mut [Vec<f64>], xs: Range<usize>, f: F)
+    where F: Fn(usize, &mut Vec<f64>) + Sync
+{
+    let n = xs.len();
+    if threads <= 1 || n < 2 * threads {
+        for x in xs {
+            f(x, &mut v[x]);
         }
+        return;
     }
+    let chunk = n.div_ceil(threads);
+    let f = &f;
+    thread::scope(|s| {
+        for (k, c) in v[xs.clone()].chunks_mut(chunk).enumerate() {
+            let x0 = xs.start + k * chunk;
+            s.spawn(move || {
+                for (i, col) in c.iter_mut().enumerate() {
+                    f(x0 + i, col);
+                }
+            });
+        }
+    });
 }
@@ -0,0 +1,189 @@
+/*
+ * This software is released under the MIT License.
+ * http://opensource.org/licenses/mit-license.php
+ */
+
+// 観測点の時系列からの共振モード抽出 (Matrix Pencil法による調和振動の逆解析)
+//
+// 信号を減衰振動の和 y(t) = Σ A cos(2π f t + φ) exp(-γ t) とみなし,
+// 周波数, 減衰率, Q値, 振幅, 位相を求める. FFTの分解能より細かい周波数と高Qのモードが得られる
+// 時刻の基準(t=0)は解析に使った最初のサンプル. 誤差の推定にはペンシル幅を変えて再計算した結果との差を使う
+
+use std::f64::consts::PI;
+use std::io::{self, Write};
+
+use complex::Complex;
+use error::{Error, Result};
+use linalg;
+use monitor::Probe;
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Mode {
+    // 周波数 (Hz)
+    pub freq: f64,
+    // 振幅の減衰率 γ (1/s, 負の場合は増大)
+    pub decay: f64,
+    // Q = 2π f / (2γ)
+    pub q: f64,
+    pub amplitude: f64,
+    // 位相 (rad)
+    pub phase: f64,
+    // 複素周波数の相対誤差の推定値
+    pub error: f64,
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct Harminv {
+    // 探索する周波数範囲 (Hz)
+    pub fmin: f64,
+    pub fmax: f64,
+    // この時刻より前のサンプルは使わない (給電中の区間を除く)
+    pub t_start: f64,
+    // 間引き間隔 (0で fmax から自動)
+    pub decimate: usize,
+    // ペンシル幅 (0で min(N/3, 100))
+    pub pencil: usize,
+    // 最大特異値に対してこれ以下の特異値は雑音とみなす
+    pub threshold: f64,
+    // モード数の上限 (0で制限なし)
+    pub max_modes: usize,
+}
+
+impl Harminv {
+    pub fn new(fmin: f64, fmax: f64) -> Harminv {
+        Harminv{fmin, fmax, t_start: 0.0, decimate: 0, pencil: 0, threshold: 1e-5, max_modes: 0}
+    }
+
+    pub fn t_start(mut self, t: f64) -> Harminv {
+        self.t_start = t;
+        self
+    }
+
+    pub fn decimate(mut self, n: usize) -> Harminv {
+        self.decimate = n;
+        self
+    }
+
+    pub fn pencil(mut self, n: usize) -> Harminv {
+        self.pencil = n;
+        self
+    }
+
+    pub fn threshold(mut self, r: f64) -> Harminv {
+        self.threshold = r;
+        self
+    }
+
+    pub fn max_modes(mut self, n: usize) -> Harminv {
+        self.max_modes = n;
+        self
+    }
+
+    // 記録した観測点の解析 (t_startは観測点の時刻で判定)
+    pub fn probe(&self, p: &Probe) -> Result<Vec<Mode>> {
+        let t0 = p.times.first().cloned().unwrap_or(0.0);
+        let h = Harminv{t_start: self.t_start - t0, ..self.clone()};
+        h.analyze(&p.values, p.dt())
+    }
+
+    // 間隔dtで記録した時系列 values (先頭を t=0 とする) の解析. 周波数の昇順で返す
+    pub fn analyze(&self, values: &[f64], dt: f64) -> Result<Vec<Mode>> {
+        if !dt.is_finite() || dt <= 0.0 {
+            return Err(Error::InvalidValue{what: "sampling interval", value: dt});
+        }
+        if !self.fmax.is_finite() || self.fmax <= self.fmin || self.fmax * dt >= 0.5 {
+            return Err(Error::InvalidValue{what: "harminv fmax", value: self.fmax});
+        }
+        let d = if self.decimate > 0 {
+            self.decimate
+        } else {
+            // 間引き後のナイキスト周波数が fmax の2倍程度になるように
+            ((0.25 / (self.fmax * dt)).floor() as usize).max(1)
+        };
+        let first = (self.t_start / dt).ceil().max(0.0) as usize;
+        let y: Vec<f64> = values.iter().skip(first).step_by(d).cloned().collect();
+        let n = y.len();
+        if n < 8 {
+            return Err(Error::InvalidValue{what: "number of samples for harminv", value: n as f64});
+        }
+        let l = if self.pencil > 0 { self.pencil.min(n / 2) } else { (n / 3).min(100) };
+        let dt = dt * d as f64;
+
+        let poles = self.poles(&y, l);
+        // ペンシル幅を変えた結果と比較して誤差を見積もる
+        let check = self.poles(&y, (l * 2 / 3).max(2));
+
+        // 振幅 (Vandermonde行列の最小二乗解)
+        let a: Vec<Vec<Complex>> = (0..n).map(|k| poles.iter().map(|z| pow(*z, k)).collect()).collect();
+        let b: Vec<Complex> = y.iter().map(|&v| Complex::new(v, 0.0)).collect();
+        let amp = match linalg::least_squares(&a, &b) {
+            Some(x) => x,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut modes = Vec::new();
+        for (z, c) in poles.iter().zip(&amp) {
+            let s = z.ln().scale(1.0 / dt);
+            let freq = s.im / (2.0 * PI);
+            // 実信号なので負の周波数側は共役の片割れ
+            if freq < 0.0 || freq < self.fmin || freq > self.fmax {
+                continue;
+            }
+            let decay = -s.re;
+            let q = if decay > 0.0 { s.im / (2.0 * decay) } else { f64::INFINITY };
+            let real = z.im.abs() < 1e-12 * z.abs();
+            let amplitude = if real { c.abs() } else { 2.0 * c.abs() };
+            let scale = s.abs().max(2.0 * PI * self.fmin.max(0.0));
+            let error = check.iter()
+                .map(|w| (w.ln().scale(1.0 / dt) - s).abs() / scale)
+                .fold(f64::INFINITY, f64::min);
+            modes.push(Mode{freq, decay, q, amplitude, phase: c.arg(), error});
+        }
+        modes.sort_by(|a, b| a.freq.partial_cmp(&b.freq).unwrap_or(::std::cmp::Ordering::Equal));
+        Ok(modes)
+    }
+
+    // 信号の極 z = exp(s dt)
+    fn poles(&self, y: &[f64], l: usize) -> Vec<Complex> {
+        let n = y.len();
+        // Hankel行列 (N-L) x (L+1)
+        let hankel: Vec<Vec<f64>> = (0..n - l).map(|i| y[i..i + l + 1].to_vec()).collect();
+        let (sv, v) = linalg::svd(&hankel);
+        let top = sv.first().cloned().unwrap_or(0.0);
+        if top == 0.0 {
+            return Vec::new();
+        }
+        let mut m = sv.iter().take_while(|&&s| s > self.threshold * top).count().min(l);
+        if self.max_modes > 0 {
+            m = m.min(self.max_modes);
+        }
+        if m == 0 {
+            return Vec::new();
+        }
+        // V1 (先頭L行), V2 (末尾L行) について V1^+ V2 の固有値
+        let v1: Vec<Vec<f64>> = (0..l).map(|i| (0..m).map(|k| v[k][i]).collect()).collect();
+        let v2: Vec<Vec<f64>> = (0..l).map(|i| (0..m).map(|k| v[k][i + 1]).collect()).collect();
+        let gram: Vec<Vec<f64>> = (0..m).map(|i| (0..m).map(|j| (0..l).map(|r| v1[r][i] * v1[r][j]).sum()).collect()).collect();
+        let rhs: Vec<Vec<f64>> = (0..m).map(|i| (0..m).map(|j| (0..l).map(|r| v1[r][i] * v2[r][j]).sum()).collect()).collect();
+        let x = match linalg::solve(&gram, &rhs) {
+            Some(x) => x,
+            None => return Vec::new(),
+        };
+        let x: Vec<Vec<Complex>> = x.iter().map(|r| r.iter().map(|&v| Complex::new(v, 0.0)).collect()).collect();
+        linalg::eigenvalues(&x).into_iter().filter(|z| z.abs() > 0.0).collect()
+    }
+}
+
+fn pow(z: Complex, k: usize) -> Complex {
+    // 長い系列でも誤差が積み重ならないよう極形式で計算
+    Complex::from_polar(z.abs().powi(k as i32), z.arg() * k as f64)
+}
+
+// freq,decay,Q,amplitude,phase,error のCSV
+pub fn write_csv<W: Write>(w: &mut W, modes: &[Mode]) -> io::Result<()> {
+    writeln!(w, "freq,decay,Q,amplitude,phase,error")?;
+    for m in modes {
+        writeln!(w, "{:e},{:e},{:e},{:e},{:e},{:e}", m.freq, m.decay, m.q, m.amplitude, m.phase, m.error)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,308 @@
+/*
+ * This software is released under the MIT License.
+ * http://opensource.org/licenses/mit-license.php
+ */
+
+// シナリオファイル用の最小限のJSONパーサ
+
+use std::fmt;
+
+#[derive(Clone,Debug,PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Num(f64),
+    Str(String),
+    Array(Vec<Value>),
+    // キーの順序を保持
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match *self {
+            Value::Object(ref m) => m.iter().find(|e| e.0 == key).map(|e| &e.1),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Value::Num(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::Str(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Value>> {
+        match *self {
+            Value::Array(ref a) => Some(a),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&Vec<(String, Value)>> {
+        match *self {
+            Value::Object(ref m) => Some(m),
+            _ => None,
+        }
+    }
+
+    // "geometry[0].x[1]" 形式のパス (シナリオのエラー表示と同じ形式) の値
+    pub fn path_mut(&mut self, path: &str) -> Option<&mut Value> {
+        let mut v = self;
+        for seg in path.split('.') {
+            let (key, rest) = match seg.find('[') {
+                Some(i) => (&seg[..i], &seg[i..]),
+                None => (seg, ""),
+            };
+            if !key.is_empty() {
+                v = match *v {
+                    Value::Object(ref mut m) => &mut m.iter_mut().find(|e| e.0 == key)?.1,
+                    _ => return None,
+                };
+            }
+            for idx in rest.split('[').skip(1) {
+                let n = idx.strip_suffix(']')?.parse::<usize>().ok()?;
+                v = match *v {
+                    Value::Array(ref mut a) => a.get_mut(n)?,
+                    _ => return None,
+                };
+            }
+        }
+        Some(v)
+    }
+
+    // 型名 (エラーメッセージ用)
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Num(_) => "number",
+            Value::Str(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        }
+    }
+
+    // JSON文字列へ
+    pub fn to_json(&self) -> String {
+        match *self {
+            Value::Null => "null".to_string(),
+            Value::Bool(b) => b.to_string(),
+            Value::Num(v) => if v.is_finite() { format!("{}", v) } else { "null".to_string() },
+            Value::Str(ref s) => quote(s),
+            Value::Array(ref a) => format!("[{}]", a.iter().map(|v| v.to_json()).collect::<Vec<_>>().join(", ")),
+            Value::Object(ref m) => format!("{{{}}}", m.iter().map(|(k, v)| format!("{}: {}", quote(k), v.to_json())).collect::<Vec<_>>().join(", ")),
+        }
+    }
+}
+
+fn quote(s: &str) -> String {
+    let mut o = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => o.push_str("\\\""),
+            '\\' => o.push_str("\\\\"),
+            '\n' => o.push_str("\\n"),
+            '\t' => o.push_str("\\t"),
+            c if (c as u32) < 0x20 => o.push_str(&format!("\\u{:04x}", c as u32)),
+            c => o.push(c),
+        }
+    }
+    o.push('"');
+    o
+}
+
+// 構文エラー (行, 桁は1始まり)
+#[derive(Clone,Debug,PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub col: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.col, self.message)
+    }
+}
+
+pub fn parse(src: &str) -> Result<Value, ParseError> {
+    let mut p = Parser{s: src.as_bytes(), pos: 0};
+    p.ws();
+    let v = p.value()?;
+    p.ws();
+    if p.pos < p.s.len() {
+        return Err(p.error("trailing characters"));
+    }
+    Ok(v)
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, msg: &str) -> ParseError {
+        let done = &self.s[..self.pos.min(self.s.len())];
+        let line = done.iter().filter(|&&c| c == b'\n').count() + 1;
+        let col = done.iter().rev().take_while(|&&c| c != b'\n').count() + 1;
+        ParseError{line, col, message: msg.to_string()}
+    }
+
+    // 空白と "//" コメントを読み飛ばす
+    fn ws(&mut self) {
+        while self.pos < self.s.len() {
+            match self.s[self.pos] {
+                b' ' | b'\t' | b'\n' | b'\r' => self.pos += 1,
+                b'/' if self.s.get(self.pos + 1) == Some(&b'/') => {
+                    while self.pos < self.s.len() && self.s[self.pos] != b'\n' {
+                        self.pos += 1;
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, ParseError> {
+        match self.s.get(self.pos) {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => self.string().map(Value::Str),
+            Some(b't') => self.literal("true", Value::Bool(true)),
+            Some(b'f') => self.literal("false", Value::Bool(false)),
+            Some(b'n') => self.literal("null", Value::Null),
+            Some(&c) if c == b'-' || c.is_ascii_digit() => self.number(),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn literal(&mut self, word: &str, v: Value) -> Result<Value, ParseError> {
+        if self.s[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            Ok(v)
+        } else {
+            Err(self.error("invalid literal"))
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, ParseError> {
+        let start = self.pos;
+        while self.pos < self.s.len() && matches!(self.s[self.pos], b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') {
+            self.pos += 1;
+        }
+        let t = String::from_utf8_lossy(&self.s[start..self.pos]);
+        t.parse::<f64>().map(Value::Num).map_err(|_| {
+            let mut e = self.error("invalid number");
+            e.col -= self.pos - start;
+            e
+        })
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        self.pos += 1;
+        let mut b = Vec::new();
+        loop {
+            match self.s.get(self.pos) {
+                None => return Err(self.error("unterminated string")),
+                Some(b'"') => {
+                    self.pos += 1;
+                    break;
+                }
+                Some(b'\\') => {
+                    let c = match self.s.get(self.pos + 1) {
+                        Some(b'n') => '\n',
+                        Some(b't') => '\t',
+                        Some(b'r') => '\r',
+                        Some(b'u') => {
+                            let h = self.s.get(self.pos + 2..self.pos + 6).ok_or_else(|| self.error("invalid escape"))?;
+                            let n = u32::from_str_radix(&String::from_utf8_lossy(h), 16).map_err(|_| self.error("invalid escape"))?;
+                            self.pos += 4;
+                            ::std::char::from_u32(n).unwrap_or('?')
+                        }
+                        Some(&c) => c as char,
+                        None => return Err(self.error("unterminated string")),
+                    };
+                    let mut tmp = [0u8; 4];
+                    b.extend_from_slice(c.encode_utf8(&mut tmp).as_bytes());
+                    self.pos += 2;
+                }
+                Some(&c) => {
+                    b.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+        String::from_utf8(b).map_err(|_| self.error("invalid UTF-8"))
+    }
+
+    fn array(&mut self) -> Result<Value, ParseError> {
+        self.pos += 1;
+        let mut a = Vec::new();
+        self.ws();
+        if self.s.get(self.pos) == Some(&b']') {
+            self.pos += 1;
+            return Ok(Value::Array(a));
+        }
+        loop {
+            self.ws();
+            a.push(self.value()?);
+            self.ws();
+            match self.s.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Value::Array(a));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, ParseError> {
+        self.pos += 1;
+        let mut m: Vec<(String, Value)> = Vec::new();
+        self.ws();
+        if self.s.get(self.pos) == Some(&b'}') {
+            self.pos += 1;
+            return Ok(Value::Object(m));
+        }
+        loop {
+            self.ws();
+            if self.s.get(self.pos) != Some(&b'"') {
+                return Err(self.error("expected key string"));
+            }
+            let k = self.string()?;
+            if m.iter().any(|e| e.0 == k) {
+                return Err(self.error(&format!("duplicate key \"{}\"", k)));
+            }
+            self.ws();
+            if self.s.get(self.pos) != Some(&b':') {
+                return Err(self.error("expected ':'"));
+            }
+            self.pos += 1;
+            self.ws();
+            let v = self.value()?;
+            m.push((k, v));
+            self.ws();
+            match self.s.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Value::Object(m));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+}
@@ -1,3 +1,34 @@
-pub mod fdtd2d;
+#![allow(non_snake_case)]
 
+// マクロを他のモジュールで使うため先頭で宣言
+#[macro_use]
+pub mod log;
 
+pub mod adjoint;
+pub mod antenna;
+pub mod band;
+pub mod binary;
+pub mod checkpoint;
+pub mod complex;
+pub mod container;
+pub mod decay;
+pub mod eigenmode;
+pub mod error;
+pub mod fdtd2d;
+pub mod harminv;
+pub mod json;
+pub mod linalg;
+pub mod mesh;
+pub mod monitor;
+pub mod observer;
+pub mod port;
+pub mod progress;
+pub mod random;
+pub mod render;
+pub mod scenario;
+pub mod snapshot;
+pub mod spectrum;
+pub mod subgrid;
+pub mod sweep;
+pub mod topology;
+pub mod vtk;
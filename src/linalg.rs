@@ -0,0 +1,341 @@
+/*
+ * This software is released under the MIT License.
+ * http://opensource.org/licenses/mit-license.php
+ */
+
+// 解析用の小規模な線形代数 (行列は行のVec)
+// 式と対応させるため添字でループする
+#![allow(clippy::needless_range_loop)]
+
+use complex::Complex;
+
+// 特異値分解 a = U S V^T (片側Jacobi法)
+// 戻り値: (特異値 降順, V の列ベクトル) ※Uは求めない
+pub fn svd(a: &[Vec<f64>]) -> (Vec<f64>, Vec<Vec<f64>>) {
+    let m = a.len();
+    let n = if m > 0 { a[0].len() } else { 0 };
+    // 列毎に保持
+    let mut u: Vec<Vec<f64>> = (0..n).map(|j| (0..m).map(|i| a[i][j]).collect()).collect();
+    let mut v: Vec<Vec<f64>> = (0..n).map(|j| (0..n).map(|i| if i == j { 1.0 } else { 0.0 }).collect()).collect();
+
+    for _sweep in 0..60 {
+        let mut off = 0.0f64;
+        for p in 0..n {
+            for q in p+1..n {
+                let (mut alpha, mut beta, mut gamma) = (0.0, 0.0, 0.0);
+                for i in 0..m {
+                    alpha += u[p][i] * u[p][i];
+                    beta += u[q][i] * u[q][i];
+                    gamma += u[p][i] * u[q][i];
+                }
+                if gamma == 0.0 || gamma.abs() <= 1e-15 * (alpha * beta).sqrt() {
+                    continue;
+                }
+                off = off.max(gamma.abs() / (alpha * beta).sqrt());
+                let zeta = (beta - alpha) / (2.0 * gamma);
+                let t = zeta.signum() / (zeta.abs() + (1.0 + zeta * zeta).sqrt());
+                let t = if zeta == 0.0 { 1.0 } else { t };
+                let c = 1.0 / (1.0 + t * t).sqrt();
+                let s = c * t;
+                rotate(&mut u, p, q, c, s);
+                rotate(&mut v, p, q, c, s);
+            }
+        }
+        if off < 1e-15 {
+            break;
+        }
+    }
+
+    let mut order: Vec<(f64, usize)> = u.iter().enumerate().map(|(j, c)| (c.iter().map(|x| x * x).sum::<f64>().sqrt(), j)).collect();
+    order.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(::std::cmp::Ordering::Equal));
+    (order.iter().map(|o| o.0).collect(), order.iter().map(|o| v[o.1].clone()).collect())
+}
+
+fn rotate(cols: &mut [Vec<f64>], p: usize, q: usize, c: f64, s: f64) {
+    for i in 0..cols[p].len() {
+        let (x, y) = (cols[p][i], cols[q][i]);
+        cols[p][i] = c * x - s * y;
+        cols[q][i] = s * x + c * y;
+    }
+}
+
+// 実数の連立一次方程式 a x = b (部分ピボット選択のGauss消去, bは複数列)
+pub fn solve(a: &[Vec<f64>], b: &[Vec<f64>]) -> Option<Vec<Vec<f64>>> {
+    let n = a.len();
+    let mut m: Vec<Vec<f64>> = a.iter().zip(b).map(|(r, s)| r.iter().chain(s.iter()).cloned().collect()).collect();
+    let w = m.first().map_or(0, |r| r.len());
+    for k in 0..n {
+        let p = (k..n).max_by(|&i, &j| m[i][k].abs().partial_cmp(&m[j][k].abs()).unwrap_or(::std::cmp::Ordering::Equal))?;
+        if m[p][k] == 0.0 {
+            return None;
+        }
+        m.swap(k, p);
+        for i in k+1..n {
+            let f = m[i][k] / m[k][k];
+            for j in k..w {
+                m[i][j] -= f * m[k][j];
+            }
+        }
+    }
+    let mut x = vec![vec![0.0; w - n]; n];
+    for k in (0..n).rev() {
+        for c in 0..w-n {
+            let mut s = m[k][n + c];
+            for j in k+1..n {
+                s -= m[k][j] * x[j][c];
+            }
+            x[k][c] = s / m[k][k];
+        }
+    }
+    Some(x)
+}
+
+// 複素数の最小二乗解 min |a x - b| (修正Gram-Schmidt法)
+pub fn least_squares(a: &[Vec<Complex>], b: &[Complex]) -> Option<Vec<Complex>> {
+    let m = a.len();
+    let n = if m > 0 { a[0].len() } else { 0 };
+    let mut q: Vec<Vec<Complex>> = (0..n).map(|j| (0..m).map(|i| a[i][j]).collect()).collect();
+    let mut r = vec![vec![Complex::default(); n]; n];
+    for j in 0..n {
+        for k in 0..j {
+            let d = dot(&q[k], &q[j]);
+            r[k][j] = d;
+            for i in 0..m {
+                let t = q[k][i] * d;
+                q[j][i] -= t;
+            }
+        }
+        let norm = q[j].iter().map(|v| v.norm_sqr()).sum::<f64>().sqrt();
+        if norm == 0.0 {
+            return None;
+        }
+        r[j][j] = Complex::new(norm, 0.0);
+        for v in q[j].iter_mut() {
+            *v = v.scale(1.0 / norm);
+        }
+    }
+    let mut x = vec![Complex::default(); n];
+    for k in (0..n).rev() {
+        let mut s = dot(&q[k], b);
+        for j in k+1..n {
+            s -= r[k][j] * x[j];
+        }
+        x[k] = s / r[k][k];
+    }
+    Some(x)
+}
+
+// a^H b
+fn dot(a: &[Complex], b: &[Complex]) -> Complex {
+    a.iter().zip(b).fold(Complex::default(), |s, (x, y)| s + x.conj() * *y)
+}
+
+// 一般の複素正方行列の固有値 (Hessenberg化 + Wilkinsonシフト付きQR法)
+pub fn eigenvalues(a: &[Vec<Complex>]) -> Vec<Complex> {
+    let n = a.len();
+    let mut h: Vec<Vec<Complex>> = a.to_vec();
+
+    // Householder変換で上Hessenberg行列へ
+    for k in 0..n.saturating_sub(2) {
+        let alpha = (k+1..n).map(|i| h[i][k].norm_sqr()).sum::<f64>().sqrt();
+        if alpha == 0.0 {
+            continue;
+        }
+        let x0 = h[k+1][k];
+        let phase = if x0.abs() > 0.0 { x0.scale(1.0 / x0.abs()) } else { Complex::new(1.0, 0.0) };
+        let mut v: Vec<Complex> = (k+1..n).map(|i| h[i][k]).collect();
+        v[0] += phase.scale(alpha);
+        let vn = v.iter().map(|c| c.norm_sqr()).sum::<f64>();
+        if vn == 0.0 {
+            continue;
+        }
+        // H = (I - 2 v v^H / |v|^2) H (I - 2 v v^H / |v|^2)
+        for j in 0..n {
+            let s = (0..v.len()).fold(Complex::default(), |s, i| s + v[i].conj() * h[k+1+i][j]).scale(2.0 / vn);
+            for i in 0..v.len() {
+                let t = v[i] * s;
+                h[k+1+i][j] -= t;
+            }
+        }
+        for row in h.iter_mut() {
+            let s = (0..v.len()).fold(Complex::default(), |s, i| s + row[k+1+i] * v[i]).scale(2.0 / vn);
+            for i in 0..v.len() {
+                let t = s * v[i].conj();
+                row[k+1+i] -= t;
+            }
+        }
+    }
+
+    let mut ev = Vec::with_capacity(n);
+    let mut hi = n;
+    let mut iter = 0;
+    while hi > 0 {
+        let mut l = hi - 1;
+        while l > 0 {
+            let s = h[l][l].abs() + h[l-1][l-1].abs();
+            if h[l][l-1].abs() <= 1e-14 * if s > 0.0 { s } else { 1.0 } {
+                break;
+            }
+            l -= 1;
+        }
+        if l == hi - 1 || iter > 100 * n {
+            ev.push(h[hi-1][hi-1]);
+            hi -= 1;
+            iter = 0;
+            continue;
+        }
+        iter += 1;
+
+        // 右下2x2の固有値のうち右下要素に近い方をシフトに
+        let (a, b, c, d) = (h[hi-2][hi-2], h[hi-2][hi-1], h[hi-1][hi-2], h[hi-1][hi-1]);
+        let half = (a - d).scale(0.5);
+        let disc = (half * half + b * c).sqrt();
+        let (m1, m2) = ((a + d).scale(0.5) + disc, (a + d).scale(0.5) - disc);
+        let mut mu = if (m1 - d).abs() < (m2 - d).abs() { m1 } else { m2 };
+        if iter % 11 == 10 {
+            // 収束しない場合の例外シフト
+            mu += Complex::new(h[hi-1][hi-2].abs(), 0.0);
+        }
+
+        // 区間 l..hi でQRステップ
+        for i in l..hi {
+            h[i][i] -= mu;
+        }
+        let mut rots = Vec::with_capacity(hi - l);
+        for k in l..hi-1 {
+            let (x, y) = (h[k][k], h[k+1][k]);
+            let r = (x.norm_sqr() + y.norm_sqr()).sqrt();
+            let (c, s) = if r == 0.0 { (Complex::new(1.0, 0.0), Complex::default()) } else { (x.scale(1.0 / r), y.scale(1.0 / r)) };
+            for j in k..hi {
+                let (p, q) = (h[k][j], h[k+1][j]);
+                h[k][j] = c.conj() * p + s.conj() * q;
+                h[k+1][j] = c * q - s * p;
+            }
+            rots.push((c, s));
+        }
+        for (n, &(c, s)) in rots.iter().enumerate() {
+            let k = l + n;
+            for row in h.iter_mut().take((k + 2).min(hi)).skip(l) {
+                let (p, q) = (row[k], row[k+1]);
+                row[k] = p * c + q * s;
+                row[k+1] = q * c.conj() - p * s.conj();
+            }
+        }
+        for i in l..hi {
+            h[i][i] += mu;
+        }
+    }
+    ev
+}
+
+// 対称三重対角行列 (対角d, 副対角e) の大きい方からcount個の固有値と固有ベクトル
+// 固有値は二分法 (Sturm列), 固有ベクトルは逆反復法
+pub fn tridiagonal_eigen(d: &[f64], e: &[f64], count: usize) -> Vec<(f64, Vec<f64>)> {
+    let n = d.len();
+    if n == 0 {
+        return Vec::new();
+    }
+    // Gershgorinの円板による範囲
+    let (mut lo, mut hi) = (f64::INFINITY, f64::NEG_INFINITY);
+    for i in 0..n {
+        let r = if i > 0 { e[i-1].abs() } else { 0.0 } + if i + 1 < n { e[i].abs() } else { 0.0 };
+        lo = lo.min(d[i] - r);
+        hi = hi.max(d[i] + r);
+    }
+    let scale = lo.abs().max(hi.abs()).max(f64::MIN_POSITIVE);
+
+    let mut out: Vec<(f64, Vec<f64>)> = Vec::new();
+    for k in 0..count.min(n) {
+        // 小さい方から n-1-k 番目
+        let idx = n - 1 - k;
+        let (mut a, mut b) = (lo, hi);
+        for _ in 0..200 {
+            let m = 0.5 * (a + b);
+            if b - a <= 4.0 * f64::EPSILON * scale {
+                break;
+            }
+            if sturm_count(d, e, m) > idx { b = m; } else { a = m; }
+        }
+        let lambda = 0.5 * (a + b);
+
+        // 逆反復 (既に求めたベクトルと直交化して重根に対応)
+        let shift = lambda + 16.0 * f64::EPSILON * scale;
+        let mut v: Vec<f64> = (0..n).map(|i| 1.0 + 0.1 * ((i * 7 + k * 3) % 11) as f64).collect();
+        for _ in 0..4 {
+            v = tridiagonal_solve(d, e, shift, &v);
+            for (_, u) in &out {
+                let p: f64 = u.iter().zip(&v).map(|(a, b)| a * b).sum();
+                for (x, y) in v.iter_mut().zip(u) {
+                    *x -= p * y;
+                }
+            }
+            let norm = v.iter().map(|x| x * x).sum::<f64>().sqrt();
+            if norm == 0.0 || !norm.is_finite() {
+                break;
+            }
+            for x in v.iter_mut() {
+                *x /= norm;
+            }
+        }
+        out.push((lambda, v));
+    }
+    out
+}
+
+// xより小さい固有値の数
+fn sturm_count(d: &[f64], e: &[f64], x: f64) -> usize {
+    let mut count = 0;
+    let mut q = 1.0;
+    for i in 0..d.len() {
+        let off = if i > 0 { e[i-1] * e[i-1] / q } else { 0.0 };
+        q = d[i] - x - off;
+        if q == 0.0 {
+            q = -f64::EPSILON * (d[i].abs() + x.abs()).max(f64::MIN_POSITIVE);
+        }
+        if q < 0.0 {
+            count += 1;
+        }
+    }
+    count
+}
+
+// (T - shift I) x = b (部分ピボット選択のGauss消去)
+fn tridiagonal_solve(d: &[f64], e: &[f64], shift: f64, b: &[f64]) -> Vec<f64> {
+    let n = d.len();
+    // 行iの非零要素 (列 i, i+1, i+2)
+    let mut rows: Vec<[f64; 3]> = (0..n).map(|i| [d[i] - shift, if i + 1 < n { e[i] } else { 0.0 }, 0.0]).collect();
+    let mut sub: Vec<f64> = (0..n).map(|i| if i > 0 { e[i-1] } else { 0.0 }).collect();
+    let mut x = b.to_vec();
+    let tiny = f64::EPSILON * d.iter().fold(shift.abs(), |a, v| a.max(v.abs())).max(f64::MIN_POSITIVE);
+    for i in 0..n {
+        if i + 1 < n && sub[i+1].abs() > rows[i][0].abs() {
+            // 行iと行i+1を入れ替え
+            let next = [sub[i+1], rows[i+1][0], rows[i+1][1]];
+            let cur = rows[i];
+            rows[i] = next;
+            sub[i+1] = cur[0];
+            rows[i+1] = [cur[1], cur[2], 0.0];
+            x.swap(i, i + 1);
+        }
+        if rows[i][0].abs() < tiny {
+            rows[i][0] = tiny;
+        }
+        if i + 1 < n {
+            let f = sub[i+1] / rows[i][0];
+            rows[i+1][0] -= f * rows[i][1];
+            rows[i+1][1] -= f * rows[i][2];
+            x[i+1] -= f * x[i];
+        }
+    }
+    for i in (0..n).rev() {
+        let mut s = x[i];
+        if i + 1 < n {
+            s -= rows[i][1] * x[i+1];
+        }
+        if i + 2 < n {
+            s -= rows[i][2] * x[i+2];
+        }
+        x[i] = s / rows[i][0];
+    }
+    x
+}
@@ -0,0 +1,107 @@
+/*
+ * This software is released under the MIT License.
+ * http://opensource.org/licenses/mit-license.php
+ */
+
+// 診断メッセージの出力 (レベル毎に出力の有無を切り替える)
+//
+// log_error!, log_warn!, log_info!, log_debug!, log_trace! で出力する
+// 既定はInfo以上を標準エラー出力へ. 無効なレベルでは引数の整形も行わない
+
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq,PartialOrd,Ord)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+}
+
+// 出力する最も詳細なレベル (0で全て無効)
+static MAX_LEVEL: AtomicUsize = AtomicUsize::new(Level::Info as usize);
+
+// 出力先 (Noneで標準エラー出力)
+#[allow(clippy::type_complexity)]
+static SINK: Mutex<Option<Box<dyn Write + Send>>> = Mutex::new(None);
+
+// Noneで全て無効
+pub fn set_level(level: Option<Level>) {
+    MAX_LEVEL.store(level.map_or(0, |l| l as usize), Ordering::Relaxed);
+}
+
+pub fn enabled(level: Level) -> bool {
+    level as usize <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+// 出力先の変更 (ファイル等)
+pub fn set_sink(w: Box<dyn Write + Send>) {
+    if let Ok(mut s) = SINK.lock() {
+        *s = Some(w);
+    }
+}
+
+pub fn log(level: Level, args: fmt::Arguments) {
+    if !enabled(level) {
+        return;
+    }
+    let mut s = match SINK.lock() {
+        Ok(s) => s,
+        Err(e) => e.into_inner(),
+    };
+    // 出力の失敗で計算を止めない
+    let _ = match *s {
+        Some(ref mut w) => writeln!(w, "[{}] {}", level.name(), args),
+        None => writeln!(io::stderr(), "[{}] {}", level.name(), args),
+    };
+}
+
+#[macro_export]
+macro_rules! log_at {
+    ($level:expr, $($arg:tt)*) => {
+        if $crate::log::enabled($level) {
+            $crate::log::log($level, format_args!($($arg)*));
+        }
+    }
+}
+
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)*) => { log_at!($crate::log::Level::Error, $($arg)*) }
+}
+
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)*) => { log_at!($crate::log::Level::Warn, $($arg)*) }
+}
+
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)*) => { log_at!($crate::log::Level::Info, $($arg)*) }
+}
+
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)*) => { log_at!($crate::log::Level::Debug, $($arg)*) }
+}
+
+#[macro_export]
+macro_rules! log_trace {
+    ($($arg:tt)*) => { log_at!($crate::log::Level::Trace, $($arg)*) }
+}
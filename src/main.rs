@@ -1,66 +1,349 @@
+#![allow(non_snake_case)]
+
+#[macro_use]
 extern crate Rfdtd;
 
-use Rfdtd::fdtd2d::*;
+use Rfdtd::checkpoint::{load_checkpoint, Checkpointer};
+use Rfdtd::container::{Attr, ResultFile};
+use Rfdtd::fdtd2d::{Component, Material, StopReason};
+use Rfdtd::log::{self, Level};
+use Rfdtd::progress::Progress;
+use Rfdtd::render::{Colormap, GifBackend, Renderer, Scaling};
+use Rfdtd::scenario::ScenarioSpec;
+use Rfdtd::sweep::SweepSpec;
+use Rfdtd::snapshot::{LineEnding, Snapshot, SnapshotBackend};
+use Rfdtd::binary::ArrayData;
+use std::env;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process;
 use std::time::Instant;
-use std::fs::OpenOptions;
 
-use std::io::*;
+const USAGE: &str = "使い方: Rfdtd <command> [options]
+
+commands:
+  run <scenario.json>       解析を実行
+  validate <scenario.json>  シナリオファイルの検証
+  dry-run <scenario.json>   使用メモリ, dt, ステップ数, 波長あたりのセル数を表示
+  render <result.rfd>       結果ファイルのスナップショットを画像化
+  sweep <sweep.json>        パラメータスイープ (結果は <出力先>/summary.csv)
+
+options:
+  -o, --out-dir DIR      出力先 (run, sweep: out/<ファイル名>, render: 結果ファイルと同じ場所)
+  -j, --threads N        電磁界計算のスレッド数 (既定: 1)
+  -v, --verbose          詳細表示 (毎ステップの時刻等)
+  -q, --quiet            警告とエラー以外を表示しない
+      --log-level L      表示レベル (error, warn, info, debug, trace)
+      --no-progress      run: 進捗(残り時間, steps/s, Mcells/s)を表示しない
+      --non-interactive  終了時にキー入力を待たない
+      --checkpoint N     run: Nステップ毎に <出力先>/checkpoint.ckp へ計算状態を保存
+      --resume FILE      run: 保存した計算状態から再開
+      --parallel N       sweep: 同時に実行する計算の数 (既定: スイープファイルの parallel)
+      --freq HZ          dry-run: 波長あたりのセル数を評価する周波数
+      --format F         render: png, ppm, gif (既定: png)
+      --component C      render: 描画する成分 (ex, ey, ez, hx, hy, hz)
+      --colormap M       render: diverging, viridis, gray
+      --scaling S        render: symmetric, auto, MIN:MAX
+      --log-range DB     render: 対数表示のダイナミックレンジ
+  -h, --help             この表示";
+
+#[derive(Clone,Copy,PartialEq,PartialOrd)]
+enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+// コマンドライン引数
+struct Args {
+    command: String,
+    input: PathBuf,
+    out_dir: Option<PathBuf>,
+    threads: usize,
+    verbosity: Verbosity,
+    interactive: bool,
+    progress: bool,
+    log_level: Option<Level>,
+    checkpoint: usize,
+    parallel: Option<usize>,
+    resume: Option<PathBuf>,
+    freq: Option<f64>,
+    format: String,
+    renderer: Renderer,
+}
+
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut a = Args{command: String::new(), input: PathBuf::new(), out_dir: None, threads: 1,
+                     verbosity: Verbosity::Normal, interactive: true, progress: true, log_level: None, checkpoint: 0, parallel: None, resume: None, freq: None,
+                     format: "png".to_string(), renderer: Renderer::default()};
+    let mut free = Vec::new();
+    let mut it = args.iter();
+    while let Some(s) = it.next() {
+        let mut value = |name: &str| it.next().cloned().ok_or_else(|| format!("{} requires a value", name));
+        match s.as_str() {
+            "-h" | "--help" => return Err(String::new()),
+            "-o" | "--out-dir" => a.out_dir = Some(PathBuf::from(value(s)?)),
+            "-j" | "--threads" => {
+                let v = value(s)?;
+                a.threads = v.parse::<usize>().ok().filter(|&n| n > 0).ok_or_else(|| format!("{}: invalid thread count \"{}\"", s, v))?;
+            }
+            "-v" | "--verbose" => a.verbosity = Verbosity::Verbose,
+            "-q" | "--quiet" => a.verbosity = Verbosity::Quiet,
+            "--non-interactive" => a.interactive = false,
+            "--no-progress" => a.progress = false,
+            "--log-level" => a.log_level = Some(match value(s)?.as_str() {
+                "error" => Level::Error,
+                "warn" => Level::Warn,
+                "info" => Level::Info,
+                "debug" => Level::Debug,
+                "trace" => Level::Trace,
+                v => return Err(format!("unknown log level \"{}\"", v)),
+            }),
+            "--checkpoint" => {
+                let v = value(s)?;
+                a.checkpoint = v.parse::<usize>().ok().filter(|&n| n > 0).ok_or_else(|| format!("{}: invalid step count \"{}\"", s, v))?;
+            }
+            "--parallel" => {
+                let v = value(s)?;
+                a.parallel = Some(v.parse::<usize>().ok().filter(|&n| n > 0).ok_or_else(|| format!("{}: invalid run count \"{}\"", s, v))?);
+            }
+            "--resume" => a.resume = Some(PathBuf::from(value(s)?)),
+            "--freq" => a.freq = Some(number(s, &value(s)?)?),
+            "--format" => a.format = value(s)?,
+            "--component" => {
+                let v = value(s)?;
+                a.renderer.component = Some(Component::from_name(&v).ok_or_else(|| format!("unknown component \"{}\"", v))?);
+            }
+            "--colormap" => a.renderer.colormap = match value(s)?.as_str() {
+                "diverging" => Colormap::Diverging,
+                "viridis" => Colormap::Viridis,
+                "gray" => Colormap::Gray,
+                v => return Err(format!("unknown colormap \"{}\"", v)),
+            },
+            "--scaling" => a.renderer.scaling = match value(s)?.as_str() {
+                "symmetric" => Scaling::Symmetric,
+                "auto" => Scaling::Auto,
+                v => match v.split_once(':') {
+                    Some((lo, hi)) => Scaling::Fixed(number(s, lo)?, number(s, hi)?),
+                    None => return Err(format!("invalid scaling \"{}\"", v)),
+                },
+            },
+            "--log-range" => a.renderer.log_range = Some(number(s, &value(s)?)?),
+            _ if s.starts_with('-') => return Err(format!("unknown option {}", s)),
+            _ => free.push(s.clone()),
+        }
+    }
+    if free.len() != 2 {
+        return Err(String::new());
+    }
+    a.command = free[0].clone();
+    a.input = PathBuf::from(&free[1]);
+    Ok(a)
+}
+
+fn number(name: &str, v: &str) -> Result<f64, String> {
+    v.parse::<f64>().ok().filter(|x| x.is_finite())
+        .ok_or_else(|| format!("{}: invalid number \"{}\"", name, v))
+}
 
 // 待ち
 pub fn waitkey() {
     println!("続ける場合、何かキーを押してください");
     let mut __ret=String::new();
     std::io::stdin().read_line(&mut __ret).ok();
-    return;
 }
-    
-
-#[allow(unused_must_use)]
-fn main() {
 
-    let mut fmodule = fdtdBuilder::newSize(1000,1000);    // セル数を指定し設定して生成
-    //let mut fmodule = fdtdBuilder::new();                   // デフォルトの大きさで設定して生成
+fn load(a: &Args) -> Result<ScenarioSpec, String> {
+    ScenarioSpec::load(&a.input).map_err(|e| format!("{}: {}", a.input.display(), e))
+}
 
-    let mut t = fmodule.dt;
+fn dry_run(a: &Args, spec: &ScenarioSpec) {
+    let d = spec.dry_run(a.freq);
+    println!("セル数: {} x {} ({:.2} Mcells)", d.dims.0, d.dims.1, d.cells as f64 * 1e-6);
+    println!("使用メモリ: {:.1} MB", d.memory as f64 / (1024.0 * 1024.0));
+    println!("dt: {:e} s", d.dt);
+    println!("ステップ数: {} ({:e} s)", d.steps, d.time);
+    println!("波長あたりのセル数: {:.1} ({:e} Hz, 最大屈折率 {})", d.cells_per_wavelength, d.f_max, d.n_max);
+    if d.cells_per_wavelength < 10.0 {
+        println!("警告: 波長あたりのセル数が10未満です");
+    }
+}
 
-    fmodule.epsmu(300,700, 300,700,3.0);
+fn run(a: &Args, spec: &ScenarioSpec) -> Result<(), String> {
+    let out = a.out_dir.clone().unwrap_or_else(|| {
+        Path::new("out").join(a.input.file_stem().unwrap_or_default())
+    });
+    if a.verbosity >= Verbosity::Verbose {
+        dry_run(a, spec);
+    }
+    let mut sc = spec.build(&out).map_err(|e| format!("{}: {}", a.input.display(), e))?;
+    if let Some(ref path) = a.resume {
+        let f = load_checkpoint(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if f.dims() != sc.solver.dims() || f.probes().len() != sc.probes.len() || f.monitors().len() != sc.monitors.len() {
+            return Err(format!("{}: checkpoint does not match the scenario", path.display()));
+        }
+        log_info!("{} ステップから再開", f.step_count());
+        sc.solver = f;
+    }
+    sc.solver.set_threads(a.threads);
+    let checkpoint = out.join("checkpoint.ckp");
+    let mut saved = None;
+    if a.checkpoint > 0 {
+        std::fs::create_dir_all(&out).map_err(|e| format!("{}: {}", out.display(), e))?;
+        let c = Checkpointer::new(&checkpoint, a.checkpoint);
+        saved = Some(c.error());
+        sc.solver.add_observer(c);
+    }
 
-    //fmodule.init_source(68-40,68);
-    fmodule.init_source(500,0);
+    let (nx, ny) = sc.solver.dims();
+    let mut progress = Progress::new(spec.max_steps(), nx * ny);
+    let show = a.progress;
+    let start = Instant::now();
+    let summary = sc.run_with(|s, t| {
+        log_debug!("Time step:{} --- Time:{}s", s, t);
+        if show {
+            progress.update(s);
+        }
+    }).map_err(|e| format!("{}: {}", out.display(), e))?;
+    if let Some(e) = saved.and_then(|s| s.borrow_mut().take()) {
+        return Err(format!("{}: {}", checkpoint.display(), e));
+    }
 
-    fmodule.setup();
+    let end = start.elapsed();
+    let reason = match summary.stop {
+        StopReason::Steps => "指定ステップ数".to_string(),
+        StopReason::Time => "指定時刻".to_string(),
+        StopReason::Observer => "Observerによる終了".to_string(),
+        StopReason::Decayed(db) => format!("減衰 ({:.1} dB)", db),
+        StopReason::MaxSteps => "最大ステップ数 (減衰前)".to_string(),
+    };
+    log_info!("終了: {} ステップ, {:e} s, {}", summary.steps, summary.time, reason);
+    log_info!("結果: {}.{:03}秒 ({})", end.as_secs(), end.subsec_millis(), out.display());
+    Ok(())
+}
 
+fn sweep(a: &Args) -> Result<(), String> {
+    let mut spec = SweepSpec::load(&a.input).map_err(|e| format!("{}: {}", a.input.display(), e))?;
+    if let Some(n) = a.parallel {
+        spec.parallel = n;
+    }
+    let out = a.out_dir.clone().unwrap_or_else(|| {
+        Path::new("out").join(a.input.file_stem().unwrap_or_default())
+    });
     let start = Instant::now();
+    let summary = spec.run(&out, a.threads).map_err(|e| format!("{}: {}", out.display(), e))?;
+    let failed = summary.rows.iter().filter(|r| r.error.is_some()).count();
+    let end = start.elapsed();
+    log_info!("終了: {} 件 (失敗 {} 件), {}.{:03}秒", summary.rows.len(), failed, end.as_secs(), end.subsec_millis());
+    log_info!("結果: {}", out.join("summary.csv").display());
+    Ok(())
+}
 
-    for _s in 1..NSTEP as usize{
-        println!("Time step:{} --- Time:{}ms", _s, t);
-        fmodule.e_cal();
-        //fmodule.feed(68-40,68, t.clone());
-        fmodule.feed(500,0,t.clone());
-        fmodule.e_pml();
-
-        t = t + 0.5 * fmodule.dt;
-
-        fmodule.h_cal();
-        fmodule.h_pml();
-
-        t = t + 0.5 * fmodule.dt;
-        
-        if _s % 100 == 0 {
-            let mut filename = "EData".to_string();
-            let mut file = OpenOptions::new()
-                                .write(true)
-                                .append(true)
-                                .create(true)
-                                .open(filename + &((_s / 100) as i32).to_string() + ".txt").unwrap();
-            //write!(file, "Time step:{} --- Time:{}ms\r\n", _s, t);
-            //fmodule.out_file(&mut file,68-40, 68);
-            fmodule.out_file_gnu(&mut file);
+// 媒質が無いスナップショットに "/geometry" の誘電率と完全導体を補う (輪郭線用)
+fn outline(r: &mut ResultFile, snap: &mut Snapshot) {
+    if !snap.materials.is_empty() {
+        return;
+    }
+    for m in [Material::Eps, Material::Pec].iter() {
+        let path = format!("/geometry/{}", m.name());
+        // 節点の座標 (不均一メッシュでは等間隔でない)
+        let (xs, ys) = match (r.attr(&path, "x"), r.attr(&path, "y")) {
+            (Some(Attr::Nums(x)), Some(Attr::Nums(y))) if !x.is_empty() && !y.is_empty() => (x.clone(), y.clone()),
+            _ => return,
+        };
+        let a = match r.read(&path) {
+            Ok(a) => a,
+            Err(_) => return,
+        };
+        let v = match a.data {
+            ArrayData::Real(v) => v,
+            ArrayData::Complex(_) => return,
+        };
+        let nx = a.shape[1];
+        if a.shape[0] != ys.len() || nx != xs.len() {
+            return;
+        }
+        // 最も近い節点の番号
+        let nearest = |c: &[f64], p: f64| (0..c.len()).min_by(|&i, &j| (c[i] - p).abs().total_cmp(&(c[j] - p).abs())).unwrap_or(0);
+        let ix: Vec<usize> = snap.xs.iter().map(|&x| nearest(&xs, x)).collect();
+        let mut s = Vec::with_capacity(snap.xs.len() * snap.ys.len());
+        for &y in &snap.ys {
+            let j = nearest(&ys, y);
+            for &i in &ix {
+                s.push(v[j * nx + i]);
+            }
         }
+        snap.materials.push((*m, s));
     }
+}
 
-    let end = start.elapsed();
-    println!("結果: {}.{:03}秒",end.as_secs(), end.subsec_nanos() / 1_000_000);
+fn render(a: &Args) -> Result<(), String> {
+    let err = |e: std::io::Error| format!("{}: {}", a.input.display(), e);
+    let mut r = ResultFile::open(&a.input).map_err(err)?;
+    let names = r.snapshots();
+    if names.is_empty() {
+        return Err(format!("{}: no snapshots", a.input.display()));
+    }
+    let out = a.out_dir.clone().unwrap_or_else(|| a.input.parent().map(|p| p.to_path_buf()).unwrap_or_default());
+    std::fs::create_dir_all(&out).map_err(|e| format!("{}: {}", out.display(), e))?;
+    let stem = a.input.file_stem().unwrap_or_default().to_string_lossy().to_string();
 
-    waitkey()
+    let mut gif = GifBackend::new(&stem, a.renderer.clone());
+    for n in &names {
+        let mut snap = r.read_snapshot(n).map_err(err)?;
+        if a.renderer.outline {
+            outline(&mut r, &mut snap);
+        }
+        let path = out.join(format!("{}_{}.{}", stem, n, a.format));
+        let res = match a.format.as_str() {
+            "png" => a.renderer.render(&snap).and_then(|img| img.save_png(&path)),
+            "ppm" => a.renderer.render(&snap).and_then(|img| img.save_ppm(&path)),
+            "gif" => gif.write(&path, &snap, LineEnding::Lf),
+            f => return Err(format!("unknown format \"{}\" (png, ppm, gif)", f)),
+        };
+        res.map_err(|e| format!("{}: {}", path.display(), e))?;
+        log_debug!("{}", path.display());
+    }
+    gif.finish().map_err(|e| format!("{}: {}", out.display(), e))?;
+    log_info!("{}枚のスナップショットを出力しました ({})", names.len(), out.display());
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let a = match parse_args(&args) {
+        Ok(a) => a,
+        Err(e) => {
+            if e.is_empty() {
+                println!("{}", USAGE);
+                process::exit(if args.iter().any(|s| s == "-h" || s == "--help") { 0 } else { 2 });
+            }
+            eprintln!("error: {}", e);
+            process::exit(2);
+        }
+    };
+
+    log::set_level(Some(a.log_level.unwrap_or(match a.verbosity {
+        Verbosity::Quiet => Level::Warn,
+        Verbosity::Normal => Level::Info,
+        Verbosity::Verbose => Level::Debug,
+    })));
+
+    let res = match a.command.as_str() {
+        "validate" => load(&a).map(|_| if a.verbosity >= Verbosity::Normal { println!("{}: OK", a.input.display()) }),
+        "dry-run" => load(&a).map(|spec| dry_run(&a, &spec)),
+        "run" => load(&a).and_then(|spec| run(&a, &spec)),
+        "render" => render(&a),
+        "sweep" => sweep(&a),
+        c => Err(format!("unknown command \"{}\"\n\n{}", c, USAGE)),
+    };
+    if let Err(e) = res {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+
+    // 端末から対話的に実行した場合のみ待つ
+    if a.command == "run" && a.interactive && std::io::stdin().is_terminal() {
+        waitkey()
+    }
 }
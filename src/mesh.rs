@@ -0,0 +1,172 @@
+/*
+ * This software is released under the MIT License.
+ * http://opensource.org/licenses/mit-license.php
+ */
+
+// 不均一メッシュのセル幅の生成
+//
+// 長さ length の軸を既定のセル幅 size で分割し, refine で指定した区間 [start, end] を細かいセル幅にする.
+// 細分区間は等分して両端を節点に載せ, 区間の外ではセル幅を隣のセルの ratio 倍以下に抑えて
+// 滑らかに既定のセル幅へ戻す. 比の上限を守れない狭い隙間は両側の細分区間ごとまとめる.
+// 座標はPMLを除いた解析領域の端を原点とする.
+
+use error::{Error, Result};
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct Grading {
+    pub length: f64,
+    pub size: f64,
+    // 隣り合うセル幅の比の上限
+    pub ratio: f64,
+    // 細分区間 (開始, 終端, セル幅)
+    pub refinements: Vec<(f64, f64, f64)>,
+}
+
+impl Grading {
+    pub fn new(length: f64, size: f64) -> Grading {
+        Grading{length, size, ratio: 1.2, refinements: Vec::new()}
+    }
+
+    pub fn refine(mut self, start: f64, end: f64, size: f64) -> Grading {
+        self.refinements.push((start, end, size));
+        self
+    }
+
+    pub fn ratio(mut self, r: f64) -> Grading {
+        self.ratio = r;
+        self
+    }
+
+    // 各セルの幅 (原点側から順に)
+    pub fn widths(&self) -> Result<Vec<f64>> {
+        for &(what, v) in [("mesh length", self.length), ("cell width", self.size)].iter() {
+            if !(v > 0.0 && v.is_finite()) {
+                return Err(Error::InvalidValue{what, value: v});
+            }
+        }
+        if !(self.ratio > 1.0 && self.ratio.is_finite()) {
+            return Err(Error::InvalidValue{what: "grading ratio", value: self.ratio});
+        }
+        for &(s, e, size) in &self.refinements {
+            if !(size > 0.0 && size.is_finite()) {
+                return Err(Error::InvalidValue{what: "refined cell width", value: size});
+            }
+            if !s.is_finite() || !e.is_finite() || s > e {
+                return Err(Error::InvalidValue{what: "refinement end", value: e});
+            }
+        }
+
+        // 重なる細分区間はまとめて細かい方のセル幅にする (既定より粗い指定は既定のセル幅)
+        let mut fine: Vec<(f64, f64, f64)> = self.refinements.iter()
+            .map(|&(s, e, size)| (s.max(0.0), e.min(self.length), size.min(self.size)))
+            .filter(|r| r.0 < r.1).collect();
+        fine.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        let mut merged: Vec<(f64, f64, f64)> = Vec::new();
+        for r in fine {
+            match merged.last_mut() {
+                Some(m) if r.0 <= m.1 => {
+                    m.1 = m.1.max(r.1);
+                    m.2 = m.2.min(r.2);
+                }
+                _ => merged.push(r),
+            }
+        }
+
+        // 比の上限を守って埋められない間 (狭すぎる隙間等) は両側の細分区間ごとまとめる
+        // (解析領域の端との間なら細分区間を端まで延ばす)
+        while let Some(k) = (0..=merged.len()).find(|&k| self.gap(&merged, k).is_none()) {
+            if k == 0 {
+                merged[0].0 = 0.0;
+            } else if k == merged.len() {
+                merged[k - 1].1 = self.length;
+            } else {
+                let r = merged.remove(k);
+                merged[k - 1].1 = r.1;
+                merged[k - 1].2 = merged[k - 1].2.min(r.2);
+            }
+        }
+
+        // 細分区間は等分して端を節点に載せ, その間を徐々に粗くするセルで埋める
+        let mut w = Vec::new();
+        for k in 0..=merged.len() {
+            w.extend(self.gap(&merged, k).unwrap_or_default());
+            if let Some(&r) = merged.get(k) {
+                let (n, h) = cells(r);
+                w.extend(vec![h; n]);
+            }
+        }
+        Ok(w)
+    }
+
+    // k番目の細分区間の手前の間のセル幅 (k = 細分区間の数は最後の細分区間から領域の端まで)
+    fn gap(&self, fine: &[(f64, f64, f64)], k: usize) -> Option<Vec<f64>> {
+        let left = k.checked_sub(1).map(|i| fine[i]);
+        let right = fine.get(k).copied();
+        let a = left.map_or(0.0, |r| r.1);
+        let b = right.map_or(self.length, |r| r.0);
+        self.fill(b - a, left.map(|r| cells(r).1), right.map(|r| cells(r).1))
+    }
+
+    // 両側のセル幅 hl, hr (領域の端は None) の間の長さ len を, 隣り合うセル幅の比を ratio 以下,
+    // セル幅を size 以下にして埋める. セル数 m 毎に両側から比の上限で決まる幅の上限 u と下限 l を求め,
+    // 合計が len を挟む最小の m で l と u を内分する (l, u とも比の条件を満たすので内分も満たす).
+    // 埋められなければ None
+    fn fill(&self, len: f64, hl: Option<f64>, hr: Option<f64>) -> Option<Vec<f64>> {
+        if len <= 0.0 {
+            return Some(Vec::new());
+        }
+        let r = self.ratio;
+        let bounds = |m: usize| {
+            let mut l = vec![0.0; m];
+            let mut u = vec![self.size; m];
+            for i in 0..m {
+                for &(h, p) in [(hl, i + 1), (hr, m - i)].iter() {
+                    if let Some(h) = h {
+                        let p = r.powi(p.min(i32::MAX as usize) as i32);
+                        l[i] = f64::max(l[i], h / p);
+                        u[i] = f64::min(u[i], h * p);
+                    }
+                }
+            }
+            (l, u)
+        };
+        // 上限の合計はセル数に対して単調なので, len に届く最小のセル数を二分探索する
+        let min = [hl, hr].iter().flatten().fold(self.size, |a, &h| a.min(h));
+        let (mut lo, mut hi) = (1, (len / min).ceil() as usize + 1);
+        while lo < hi {
+            let m = (lo + hi) / 2;
+            if bounds(m).1.iter().sum::<f64>() >= len { hi = m; } else { lo = m + 1; }
+        }
+        for m in lo..lo + 64 {
+            let (l, u) = bounds(m);
+            if l.iter().zip(&u).any(|(a, b)| a > b) {
+                continue;
+            }
+            let (sl, su) = (l.iter().sum::<f64>(), u.iter().sum::<f64>());
+            if sl > len {
+                break;
+            }
+            if len <= su {
+                let t = if su > sl { (len - sl) / (su - sl) } else { 0.0 };
+                return Some(l.iter().zip(&u).map(|(a, b)| a + t * (b - a)).collect());
+            }
+        }
+        None
+    }
+}
+
+// 細分区間を等分するセル数と幅
+fn cells((s, e, size): (f64, f64, f64)) -> (usize, f64) {
+    let n = ((e - s) / size - 1e-9).ceil().max(1.0) as usize;
+    (n, (e - s) / n as f64)
+}
+
+// セル幅の並びの累積 (節点座標, 先頭は0)
+pub fn nodes(widths: &[f64]) -> Vec<f64> {
+    let mut x = vec![0.0];
+    for w in widths {
+        let last = x[x.len() - 1];
+        x.push(last + w);
+    }
+    x
+}
@@ -0,0 +1,214 @@
+/*
+ * This software is released under the MIT License.
+ * http://opensource.org/licenses/mit-license.php
+ */
+
+use std::f64::consts::PI;
+
+use complex::Complex;
+use eigenmode::{Eigenmode, ModeSolver};
+use error::{Error, Result};
+use fdtd2d::{fdtd, Component};
+use port::Axis;
+use snapshot::Region;
+
+// 観測点 (配列番号)
+#[derive(Clone,Debug)]
+pub struct Probe {
+    pub component: Component,
+    pub x: usize,
+    pub y: usize,
+    pub times: Vec<f64>,
+    pub values: Vec<f64>,
+}
+
+impl Probe {
+    pub fn new(component: Component, x: usize, y: usize) -> Probe {
+        Probe{component, x, y, times: Vec::new(), values: Vec::new()}
+    }
+
+    // 現在の値を記録
+    pub fn record(&mut self, f: &fdtd, t: f64) {
+        self.times.push(t);
+        self.values.push(f.field(self.component)[self.x][self.y]);
+    }
+
+    // 記録間隔 (等間隔を仮定)
+    pub fn dt(&self) -> f64 {
+        if self.times.len() < 2 {
+            return 0.0;
+        }
+        self.times[1] - self.times[0]
+    }
+}
+
+// 離散フーリエ変換モニタ (領域内の各点で指定周波数の成分を積算)
+#[derive(Clone,Debug)]
+pub struct DftMonitor {
+    pub component: Component,
+    pub region: Region,
+    pub freqs: Vec<f64>,
+    // 周波数毎のデータ [j * 幅 + i]
+    pub data: Vec<Vec<Complex>>,
+    // 領域の節点の座標 (不均一メッシュでは間隔が位置毎に異なる)
+    pub xs: Vec<f64>,
+    pub ys: Vec<f64>,
+}
+
+impl DftMonitor {
+    pub fn new(f: &fdtd, component: Component, region: Region, freqs: &[f64]) -> Result<DftMonitor> {
+        f.check_monitor(region)?;
+        let n = (region.x_l - region.x_s) * (region.y_l - region.y_s);
+        let xs = (region.x_s..region.x_l).map(|x| f.position(x, 0).0).collect();
+        let ys = (region.y_s..region.y_l).map(|y| f.position(0, y).1).collect();
+        Ok(DftMonitor{component, region, freqs: freqs.to_vec(), data: vec![vec![Complex::default(); n]; freqs.len()], xs, ys})
+    }
+
+    pub fn width(&self) -> usize {
+        self.region.x_l - self.region.x_s
+    }
+
+    pub fn height(&self) -> usize {
+        self.region.y_l - self.region.y_s
+    }
+
+    // 時刻tの場を積算 (E: exp(-jωt) dt の重み)
+    pub fn accumulate(&mut self, f: &fdtd, t: f64) {
+        let a = f.field(self.component);
+        let r = self.region;
+        let w = self.width();
+        for (n, freq) in self.freqs.iter().enumerate() {
+            let k = Complex::from_polar(f.dt, -2.0 * PI * freq * t);
+            let d = &mut self.data[n];
+            for y in r.y_s..r.y_l {
+                for x in r.x_s..r.x_l {
+                    d[(y - r.y_s) * w + (x - r.x_s)] += k * a[x][y];
+                }
+            }
+        }
+    }
+
+    pub fn value(&self, n: usize, x: usize, y: usize) -> Complex {
+        self.data[n][(y - self.region.y_s) * self.width() + (x - self.region.x_s)]
+    }
+}
+
+// モード分解モニタ
+//
+// 線 pos と pos+1 (法線の+側) のDFTを各周波数の固有モードへ射影し, 固有モードの β を使って
+//   V(u) = a exp(-jβu) + b exp(jβu)  (uは線 pos からの法線方向の距離)
+// の a (法線の+方向へ進む波), b (-方向へ進む波) を求める
+// 振幅は |a|^2 がモードの電力に比例するように規格化する (portと同じ)
+#[derive(Clone,Debug)]
+pub struct ModeMonitor {
+    pub freqs: Vec<f64>,
+    // [周波数][モード]
+    pub modes: Vec<Vec<Eigenmode>>,
+    // 登録したDFTモニタの番号 (線 pos, pos+1)
+    lines: (usize, usize),
+    du: f64,
+}
+
+// モードの複素振幅
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub struct ModalAmplitude {
+    pub forward: Complex,
+    pub backward: Complex,
+}
+
+impl ModeMonitor {
+    // solverの線で各周波数のモードを最大count個求め, 2本の線のDFTモニタを登録する
+    pub fn new(f: &mut fdtd, solver: &ModeSolver, freqs: &[f64], count: usize) -> Result<ModeMonitor> {
+        // 線の検査を固有値計算の前に行う
+        let c = solver.polarization.component();
+        let line = |u: usize| match solver.normal {
+            Axis::X => Region{x_s: u, x_l: u + 1, y_s: solver.start, y_l: solver.end},
+            Axis::Y => Region{x_s: solver.start, x_l: solver.end, y_s: u, y_l: u + 1},
+        };
+        let m0 = DftMonitor::new(f, c, line(solver.pos), freqs)?;
+        let m1 = DftMonitor::new(f, c, line(solver.pos + 1), freqs)?;
+        let modes = freqs.iter().map(|&freq| solver.solve(f, freq, count)).collect::<Result<Vec<_>>>()?;
+        let lines = (f.add_monitor(m0)?, f.add_monitor(m1)?);
+        let (dx, dy) = f.cell_size();
+        Ok(ModeMonitor{freqs: freqs.to_vec(), modes, lines, du: if solver.normal == Axis::X { dx } else { dy }})
+    }
+
+    // [周波数][モード] の振幅
+    pub fn amplitudes(&self, f: &fdtd) -> Vec<Vec<ModalAmplitude>> {
+        let (l0, l1) = (&f.monitors()[self.lines.0], &f.monitors()[self.lines.1]);
+        self.modes.iter().enumerate().map(|(n, modes)| modes.iter().map(|m| {
+            let (v0, v1) = (m.overlap(&l0.data[n]), m.overlap(&l1.data[n]));
+            let bd = m.beta * self.du;
+            let e = Complex::from_polar(1.0, bd);
+            let det = Complex::new(0.0, 2.0 * bd.sin());
+            let norm: f64 = m.profile.iter().zip(&m.weight).map(|(v, w)| w * v * v).sum();
+            let scale = (bd.sin().max(0.0) * norm).sqrt();
+            ModalAmplitude{forward: ((v0 * e - v1) / det).scale(scale), backward: ((v1 - v0 * e.conj()) / det).scale(scale)}
+        }).collect()).collect()
+    }
+}
+
+// 線を通過する電力のモニタ (Ez, Hx, Hy のポインティングベクトルの法線成分, 法線の+方向を正)
+//
+// 線 pos 上のEzと, 線の両側の接線方向のH (半ステップ前の値なので exp(jωΔt/2) で時刻を合わせる) の平均から
+//   Sx = -1/2 Re(Ez Hy*), Sy = 1/2 Re(Ez Hx*)
+// を線に沿って積分する. DFTの値を使うので給電波形のスペクトルを含む (同じ給電の計算同士で比を取る)
+#[derive(Clone,Debug)]
+pub struct FluxMonitor {
+    pub freqs: Vec<f64>,
+    pub normal: Axis,
+    pub pos: usize,
+    pub start: usize,
+    pub end: usize,
+    // 登録したDFTモニタの番号 (Ez, 接線方向のH)
+    ez: usize,
+    h: usize,
+}
+
+impl FluxMonitor {
+    // 法線normal, 法線方向の位置pos, 接線方向の範囲 [start, end) の線のDFTモニタを登録する
+    pub fn new(f: &mut fdtd, normal: Axis, pos: usize, start: usize, end: usize, freqs: &[f64]) -> Result<FluxMonitor> {
+        if pos == 0 {
+            return Err(Error::InvalidValue{what: "flux line position", value: 0.0});
+        }
+        // 法線方向は pos-1..pos+1 のHを使う
+        let (nx, ny) = f.dims();
+        let (nl, nt, axis) = if normal == Axis::X { (nx, ny, 'y') } else { (ny, nx, 'x') };
+        if start >= end {
+            return Err(Error::ReversedRange{what: "flux line", axis, start, end});
+        }
+        if pos + 1 >= nl || end > nt {
+            let (x, y) = if normal == Axis::X { (pos, end) } else { (end, pos) };
+            return Err(Error::OutOfBounds{what: "flux line", x, y, nx, ny});
+        }
+        let (line, strip, c) = match normal {
+            Axis::X => (Region{x_s: pos, x_l: pos + 1, y_s: start, y_l: end}, Region{x_s: pos - 1, x_l: pos + 1, y_s: start, y_l: end}, Component::Hy),
+            Axis::Y => (Region{x_s: start, x_l: end, y_s: pos, y_l: pos + 1}, Region{x_s: start, x_l: end, y_s: pos - 1, y_l: pos + 1}, Component::Hx),
+        };
+        let ez = DftMonitor::new(f, Component::Ez, line, freqs)?;
+        let h = DftMonitor::new(f, c, strip, freqs)?;
+        let (ez, h) = (f.add_monitor(ez)?, f.add_monitor(h)?);
+        Ok(FluxMonitor{freqs: freqs.to_vec(), normal, pos, start, end, ez, h})
+    }
+
+    // 周波数毎の電力 (z方向の単位長さ当たり)
+    pub fn flux(&self, f: &fdtd) -> Vec<f64> {
+        let (ez, h) = (&f.monitors()[self.ez], &f.monitors()[self.h]);
+        self.freqs.iter().enumerate().map(|(n, freq)| {
+            let shift = Complex::from_polar(1.0, PI * freq * f.dt);
+            (self.start..self.end).map(|s| {
+                let p = self.pos;
+                let (e, hm, hp) = match self.normal {
+                    Axis::X => (ez.value(n, p, s), h.value(n, p - 1, s), h.value(n, p, s)),
+                    Axis::Y => (ez.value(n, s, p), h.value(n, s, p - 1), h.value(n, s, p)),
+                };
+                let ht = (hm + hp).scale(0.5) * shift;
+                // 線素はEzの節点の双対セル幅 (不均一メッシュでは位置毎に異なる)
+                match self.normal {
+                    Axis::X => -0.5 * (e * ht.conj()).re * f.node_width(p, s).1,
+                    Axis::Y => 0.5 * (e * ht.conj()).re * f.node_width(s, p).0,
+                }
+            }).sum()
+        }).collect()
+    }
+}
@@ -0,0 +1,63 @@
+/*
+ * This software is released under the MIT License.
+ * http://opensource.org/licenses/mit-license.php
+ */
+
+// 時間ループへのフック
+//
+// fdtd::step() 内の呼び出し順
+//   E更新 → 給電 → PML(E) → 観測点/DFTモニタ → after_e
+//   H更新 → PML(H) → after_h
+//   interval() ステップ毎に every
+
+use fdtd2d::fdtd;
+
+// フックの戻り値
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Control {
+    Continue,
+    // run, run_until を現在のステップで終了する
+    Stop,
+}
+
+// 場と時刻(fdtd::time, step_count)は読み取りのみ
+pub trait Observer {
+    fn after_e(&mut self, _f: &fdtd) -> Control {
+        Control::Continue
+    }
+
+    fn after_h(&mut self, _f: &fdtd) -> Control {
+        Control::Continue
+    }
+
+    // everyを呼ぶ間隔 (0で呼ばない)
+    fn interval(&self) -> usize {
+        0
+    }
+
+    fn every(&mut self, _f: &fdtd) -> Control {
+        Control::Continue
+    }
+}
+
+// nステップ毎に関数を呼ぶ
+pub struct Interval<F> {
+    n: usize,
+    func: F,
+}
+
+impl<F: FnMut(&fdtd) -> Control> Interval<F> {
+    pub fn new(n: usize, func: F) -> Interval<F> {
+        Interval{n, func}
+    }
+}
+
+impl<F: FnMut(&fdtd) -> Control> Observer for Interval<F> {
+    fn interval(&self) -> usize {
+        self.n
+    }
+
+    fn every(&mut self, f: &fdtd) -> Control {
+        (self.func)(f)
+    }
+}
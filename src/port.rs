@@ -0,0 +1,275 @@
+/*
+ * This software is released under the MIT License.
+ * http://opensource.org/licenses/mit-license.php
+ */
+
+// 導波路ポートとSパラメータ
+//
+// ポートは導波路を横切る線で, 線上にモード分布で重み付けした給電点(Ez)を並べて励振する
+// 給電線から素子側に等間隔の3本の観測線を置き, 各線のEzのDFTをモード分布へ射影した値 V1, V2, V3 から
+//   伝搬定数: cos(β d) = (V1 + V3) / (2 V2)
+//   入射波a, 反射波b: V(u) = a exp(-jβu) + b exp(jβu)  (uは給電線から素子側への距離)
+// を求める (単一モードを仮定). 参照面は給電線の位置
+// ポート毎に励振した計算を繰り返し, S = B A^-1 とする
+// 波の振幅は Yee格子上の電力の流れ ∝ |a|^2 sin(βΔ) (Δは法線方向のセルサイズ) で規格化する
+
+use std::f64::consts::PI;
+use std::io::{self, Write};
+
+use complex::Complex;
+use error::{Error, Result};
+use fdtd2d::{fdtd, Component, Source};
+use linalg;
+use monitor::DftMonitor;
+use snapshot::Region;
+
+// ポートの線の法線方向
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Axis {
+    X,
+    Y,
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct Port {
+    pub name: String,
+    pub normal: Axis,
+    // 給電線の位置 (法線方向の配列番号)
+    pub pos: usize,
+    // 線の範囲 (接線方向の配列番号, 終端は含まない)
+    pub start: usize,
+    pub end: usize,
+    // 素子が法線の+側にある場合true
+    pub forward: bool,
+    // Ezのモード分布 (2乗和を1に規格化)
+    pub profile: Vec<f64>,
+    // 給電線から最初の観測線までのセル数, 観測線の間隔
+    pub offset: usize,
+    pub spacing: usize,
+}
+
+impl Port {
+    // 既定のモード分布は範囲の両外側を完全導体とした平行平板の最低次モード
+    pub fn new(name: &str, normal: Axis, pos: usize, start: usize, end: usize, forward: bool) -> Port {
+        let p = Port{name: name.to_string(), normal, pos, start, end, forward, profile: Vec::new(), offset: 3, spacing: 3};
+        p.mode(1)
+    }
+
+    // 平行平板のm次モード sin(mπ(k+1)/(n+1))
+    pub fn mode(self, m: usize) -> Port {
+        let n = self.end.saturating_sub(self.start);
+        let p = (0..n).map(|k| (m as f64 * PI * (k + 1) as f64 / (n + 1) as f64).sin()).collect();
+        self.profile(p)
+    }
+
+    pub fn profile(mut self, p: Vec<f64>) -> Port {
+        let norm = p.iter().map(|v| v * v).sum::<f64>().sqrt();
+        self.profile = if norm > 0.0 { p.iter().map(|v| v / norm).collect() } else { p };
+        self
+    }
+
+    pub fn offset(mut self, n: usize) -> Port {
+        self.offset = n;
+        self
+    }
+
+    pub fn spacing(mut self, n: usize) -> Port {
+        self.spacing = n.max(1);
+        self
+    }
+
+    fn len(&self) -> usize {
+        self.end.saturating_sub(self.start)
+    }
+
+    // 給電線から素子側へuセルの線の法線方向の位置
+    fn at(&self, u: usize) -> Result<usize> {
+        let c = if self.forward { Some(self.pos + u) } else { self.pos.checked_sub(u) };
+        c.ok_or(Error::InvalidValue{what: "port line position", value: self.pos as f64 - u as f64})
+    }
+
+    // 法線方向の位置cの線
+    fn line(&self, c: usize) -> Region {
+        match self.normal {
+            Axis::X => Region{x_s: c, x_l: c + 1, y_s: self.start, y_l: self.end},
+            Axis::Y => Region{x_s: self.start, x_l: self.end, y_s: c, y_l: c + 1},
+        }
+    }
+
+    // 観測線 (0, 1, 2) の給電線からの距離 (セル数)
+    fn distance(&self, k: usize) -> usize {
+        self.offset + k * self.spacing
+    }
+
+    // 波形pulseで励振する給電点 (位置は無視する)
+    pub fn sources(&self, pulse: &Source) -> Vec<Source> {
+        self.profile.iter().enumerate().filter(|&(_, &w)| w != 0.0).map(|(k, &w)| {
+            let (x, y) = match self.normal {
+                Axis::X => (self.pos, self.start + k),
+                Axis::Y => (self.start + k, self.pos),
+            };
+            let mut s = *pulse;
+            s.x = x;
+            s.y = y;
+            s.amplitude *= w;
+            s
+        }).collect()
+    }
+
+    // 範囲, モード分布, 給電線と観測線の位置の検査
+    fn check(&self, f: &fdtd) -> Result<()> {
+        if self.end <= self.start {
+            return Err(Error::ReversedRange{what: "port", axis: if self.normal == Axis::X { 'y' } else { 'x' }, start: self.start, end: self.end});
+        }
+        if self.profile.len() != self.len() {
+            return Err(Error::InvalidValue{what: "port profile length", value: self.profile.len() as f64});
+        }
+        let (nx, ny) = f.dims();
+        let (nl, nt) = if self.normal == Axis::X { (nx, ny) } else { (ny, nx) };
+        // 観測線は給電線から離れる向きに並ぶので, 最も遠い線と給電線の大きい方を調べる
+        let c = self.at(self.distance(2))?.max(self.pos);
+        if c >= nl || self.end > nt {
+            let (x, y) = if self.normal == Axis::X { (c, self.end) } else { (self.end, c) };
+            return Err(Error::OutOfBounds{what: "port line", x, y, nx, ny});
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct SParameters {
+    pub ports: Vec<String>,
+    pub freqs: Vec<f64>,
+    // [周波数][受信ポート][励振ポート]
+    pub s: Vec<Vec<Vec<Complex>>>,
+    // ポート毎の伝搬定数 [ポート][周波数] (rad/m)
+    pub beta: Vec<Vec<Complex>>,
+}
+
+// ポート毎に build で生成した解析モジュール(媒質, 完全導体を設定済み)を run で計算してSパラメータを求める
+// pulse はポートの給電波形 (搬送波周波数を帯域の中心にする)
+pub fn extract<B, R>(ports: &[Port], freqs: &[f64], pulse: &Source, mut build: B, mut run: R) -> Result<SParameters>
+    where B: FnMut() -> Result<fdtd>, R: FnMut(&mut fdtd)
+{
+    if ports.is_empty() || freqs.is_empty() {
+        return Err(Error::InvalidValue{what: "number of ports or frequencies", value: ports.len().min(freqs.len()) as f64});
+    }
+    let (np, nf) = (ports.len(), freqs.len());
+
+    // v[励振][ポート][観測線][周波数]
+    let mut v = Vec::with_capacity(np);
+    let mut cell = vec![0.0; np];
+    for (j, pj) in ports.iter().enumerate() {
+        let mut f = build()?;
+        f.require_uniform("S-parameters on a graded mesh")?;
+        for p in ports {
+            p.check(&f)?;
+        }
+        let (dx, dy) = f.cell_size();
+        let mut idx = Vec::new();
+        for (i, p) in ports.iter().enumerate() {
+            cell[i] = if p.normal == Axis::X { dx } else { dy };
+            for k in 0..3 {
+                let m = DftMonitor::new(&f, Component::Ez, p.line(p.at(p.distance(k))?), freqs)?;
+                idx.push(f.add_monitor(m)?);
+            }
+        }
+        for s in pj.sources(pulse) {
+            f.add_source(s)?;
+        }
+        log_info!("port {}/{} ({}): excited", j + 1, np, pj.name);
+        run(&mut f);
+
+        let m = f.monitors();
+        v.push(ports.iter().enumerate().map(|(i, p)| (0..3).map(|k| {
+            let d = &m[idx[i * 3 + k]];
+            (0..nf).map(|n| d.data[n].iter().zip(&p.profile).fold(Complex::default(), |s, (z, w)| s + z.scale(*w))).collect::<Vec<_>>()
+        }).collect::<Vec<_>>()).collect::<Vec<_>>());
+    }
+
+    // 伝搬定数 (全ての計算の観測値による最小二乗)
+    let mut beta = vec![vec![Complex::default(); nf]; np];
+    for (i, p) in ports.iter().enumerate() {
+        let d = p.spacing as f64 * cell[i];
+        for n in 0..nf {
+            let (mut num, mut den) = (Complex::default(), 0.0);
+            for vj in &v {
+                let (v1, v2, v3) = (vj[i][0][n], vj[i][1][n], vj[i][2][n]);
+                num += (v1 + v3) * v2.conj();
+                den += 2.0 * v2.norm_sqr();
+            }
+            let c = if den > 0.0 { num.scale(1.0 / den) } else { Complex::new(1.0, 0.0) };
+            beta[i][n] = acos(c).scale(1.0 / d);
+        }
+    }
+
+    let mut s = Vec::with_capacity(nf);
+    for n in 0..nf {
+        // a[ポート][励振], b[ポート][励振] (電力で規格化)
+        let mut a = vec![vec![Complex::default(); np]; np];
+        let mut b = vec![vec![Complex::default(); np]; np];
+        for (i, p) in ports.iter().enumerate() {
+            let be = beta[i][n];
+            let (u1, u2) = (p.distance(0) as f64 * cell[i], p.distance(1) as f64 * cell[i]);
+            let e = |u: f64| (Complex::new(0.0, 1.0) * be.scale(u)).exp();
+            let det = e(u2 - u1) - e(u1 - u2);
+            let w = (be.re * cell[i]).sin().max(0.0).sqrt();
+            for j in 0..np {
+                let (v1, v2) = (v[j][i][0][n], v[j][i][1][n]);
+                a[i][j] = ((v1 * e(u2) - v2 * e(u1)) / det).scale(w);
+                b[i][j] = ((v2 * e(-u1) - v1 * e(-u2)) / det).scale(w);
+            }
+        }
+        // S A = B → A^T S^T = B^T
+        let at: Vec<Vec<Complex>> = (0..np).map(|j| (0..np).map(|i| a[i][j]).collect()).collect();
+        let mut sn = vec![vec![Complex::default(); np]; np];
+        for i in 0..np {
+            let bt: Vec<Complex> = (0..np).map(|j| b[i][j]).collect();
+            // 入射波の行列が退化している (励振されないポートがある) 場合は0の行で代用しない
+            sn[i] = linalg::least_squares(&at, &bt).filter(|row| row.iter().all(|c| c.re.is_finite() && c.im.is_finite()))
+                .ok_or(Error::InvalidValue{what: "port incident wave matrix (singular) at frequency", value: freqs[n]})?;
+        }
+        s.push(sn);
+    }
+
+    Ok(SParameters{ports: ports.iter().map(|p| p.name.clone()).collect(), freqs: freqs.to_vec(), s, beta})
+}
+
+// 複素数の逆余弦 (実部が0以上, 実部が0の場合は虚部が0以下の枝)
+fn acos(z: Complex) -> Complex {
+    let j = Complex::new(0.0, 1.0);
+    let one = Complex::new(1.0, 0.0);
+    let w = -(j * (z + j * (one - z * z).sqrt()).ln());
+    let w = if w.re < 0.0 { -w } else { w };
+    if w.re.abs() < 1e-12 && w.im > 0.0 { -w } else { w }
+}
+
+impl SParameters {
+    // Touchstoneファイルの拡張子 ("s2p" 等)
+    pub fn extension(&self) -> String {
+        format!("s{}p", self.ports.len())
+    }
+
+    // Touchstone (v1, 実部虚部形式). 2ポートは S11 S21 S12 S22 の順, それ以外は行毎
+    pub fn write_touchstone<W: Write>(&self, w: &mut W, z0: f64) -> io::Result<()> {
+        writeln!(w, "! Rfdtd S-parameters")?;
+        writeln!(w, "! ports: {}", self.ports.join(" "))?;
+        writeln!(w, "# HZ S RI R {}", z0)?;
+        let np = self.ports.len();
+        for (f, s) in self.freqs.iter().zip(&self.s) {
+            let pair = |c: Complex| format!(" {:.9e} {:.9e}", c.re, c.im);
+            if np == 2 {
+                writeln!(w, "{:.9e}{}{}{}{}", f, pair(s[0][0]), pair(s[1][0]), pair(s[0][1]), pair(s[1][1]))?;
+                continue;
+            }
+            for (i, row) in s.iter().enumerate() {
+                // 1行に4組まで
+                for (k, chunk) in row.chunks(4).enumerate() {
+                    let head = if i == 0 && k == 0 { format!("{:.9e}", f) } else { " ".repeat(16) };
+                    writeln!(w, "{}{}", head, chunk.iter().map(|c| pair(*c)).collect::<String>())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
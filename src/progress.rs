@@ -0,0 +1,78 @@
+/*
+ * This software is released under the MIT License.
+ * http://opensource.org/licenses/mit-license.php
+ */
+
+// 進捗表示 (ステップ数, 残り時間, steps/s, Mcells/s)
+//
+// Observerとして登録するか, 計算ループからupdateを呼ぶ. 出力はlog (Infoレベル)
+
+use std::time::{Duration, Instant};
+
+use fdtd2d::fdtd;
+use observer::{Control, Observer};
+
+pub struct Progress {
+    // 総ステップ数 (0の場合は割合と残り時間を表示しない)
+    pub total: usize,
+    // 表示間隔
+    pub period: Duration,
+    cells: usize,
+    start: Instant,
+    first: Option<usize>,
+    last: Instant,
+}
+
+impl Progress {
+    // cells: 1ステップで更新するセル数
+    pub fn new(total: usize, cells: usize) -> Progress {
+        let now = Instant::now();
+        Progress{total, period: Duration::from_secs(1), cells, start: now, first: None, last: now}
+    }
+
+    pub fn period(mut self, d: Duration) -> Progress {
+        self.period = d;
+        self
+    }
+
+    // stepまで完了した (表示間隔毎と最終ステップで出力)
+    pub fn update(&mut self, step: usize) {
+        let now = Instant::now();
+        self.first.get_or_insert(step.saturating_sub(1));
+        if now.duration_since(self.last) < self.period && step != self.total {
+            return;
+        }
+        self.last = now;
+        log_info!("{}", self.line(step, now));
+    }
+
+    fn line(&self, step: usize, now: Instant) -> String {
+        let first = self.first.unwrap_or(0);
+        let secs = now.duration_since(self.start).as_secs_f64();
+        let rate = if secs > 0.0 { (step - first) as f64 / secs } else { 0.0 };
+        let mcells = rate * self.cells as f64 * 1e-6;
+        if self.total > 0 {
+            let eta = if rate > 0.0 { self.total.saturating_sub(step) as f64 / rate } else { 0.0 };
+            format!("step {}/{} ({:.1}%) {:.1} steps/s {:.1} Mcells/s ETA {}",
+                    step, self.total, 100.0 * step as f64 / self.total as f64, rate, mcells, hms(eta))
+        } else {
+            format!("step {} {:.1} steps/s {:.1} Mcells/s elapsed {}", step, rate, mcells, hms(secs))
+        }
+    }
+}
+
+fn hms(s: f64) -> String {
+    let s = s.round() as u64;
+    format!("{:02}:{:02}:{:02}", s / 3600, s / 60 % 60, s % 60)
+}
+
+impl Observer for Progress {
+    fn interval(&self) -> usize {
+        1
+    }
+
+    fn every(&mut self, f: &fdtd) -> Control {
+        self.update(f.step_count());
+        Control::Continue
+    }
+}
@@ -0,0 +1,432 @@
+/*
+ * This software is released under the MIT License.
+ * http://opensource.org/licenses/mit-license.php
+ */
+
+// スナップショットの画像化 (PPM/PNG, アニメーションGIF)
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind, Result, Write};
+use std::path::{Path, PathBuf};
+
+use binary::crc32;
+use fdtd2d::{Component, Material};
+use snapshot::{LineEnding, Snapshot, SnapshotBackend};
+
+// カラーマップの色数 (残りの1色は輪郭線)
+const NCOLOR: usize = 255;
+const OUTLINE: u8 = 255;
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Colormap {
+    // 青-白-赤
+    Diverging,
+    Viridis,
+    Gray,
+}
+
+impl Colormap {
+    // t: 0.0 ~ 1.0
+    pub fn rgb(&self, t: f64) -> [u8; 3] {
+        let t = if t.is_nan() { 0.5 } else { t.clamp(0.0, 1.0) };
+        match *self {
+            Colormap::Diverging => lerp(&[[59, 76, 192], [221, 221, 221], [180, 4, 38]], t),
+            Colormap::Viridis => lerp(&[[68, 1, 84], [72, 40, 120], [62, 74, 137], [49, 104, 142], [38, 130, 142],
+                                        [31, 158, 137], [53, 183, 121], [110, 206, 88], [181, 222, 43], [253, 231, 37]], t),
+            Colormap::Gray => lerp(&[[0, 0, 0], [255, 255, 255]], t),
+        }
+    }
+}
+
+// 等間隔の基準色を線形補間
+fn lerp(c: &[[u8; 3]], t: f64) -> [u8; 3] {
+    let s = t * (c.len() - 1) as f64;
+    let i = (s.floor() as usize).min(c.len() - 2);
+    let r = s - i as f64;
+    let mut o = [0u8; 3];
+    for k in 0..3 {
+        o[k] = (c[i][k] as f64 * (1.0 - r) + c[i + 1][k] as f64 * r).round() as u8;
+    }
+    o
+}
+
+// 色の割り当て範囲
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Scaling {
+    // 固定範囲 (gnuplotのzrange相当)
+    Fixed(f64, f64),
+    // ±最大絶対値
+    Symmetric,
+    // 最小値~最大値
+    Auto,
+}
+
+// 描画設定
+#[derive(Clone,Debug)]
+pub struct Renderer {
+    pub colormap: Colormap,
+    pub scaling: Scaling,
+    // 対数表示のダイナミックレンジ(dB), Noneで線形表示
+    pub log_range: Option<f64>,
+    // 描画する成分 (Noneで先頭の成分)
+    pub component: Option<Component>,
+    // 誘電体・完全導体の輪郭線
+    pub outline: bool,
+    pub outline_color: [u8; 3],
+    // 1セルあたりのピクセル数
+    pub zoom: usize,
+}
+
+impl Default for Renderer {
+    fn default() -> Renderer {
+        Renderer {
+            colormap: Colormap::Diverging,
+            scaling: Scaling::Symmetric,
+            log_range: None,
+            component: None,
+            outline: true,
+            outline_color: [0, 0, 0],
+            zoom: 1,
+        }
+    }
+}
+
+// パレット形式の画像 (上の行から)
+#[derive(Clone,Debug)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+    pub palette: Vec<[u8; 3]>,
+}
+
+impl Renderer {
+    pub fn palette(&self) -> Vec<[u8; 3]> {
+        let mut p: Vec<[u8; 3]> = (0..NCOLOR).map(|i| self.colormap.rgb(i as f64 / (NCOLOR - 1) as f64)).collect();
+        p.push(self.outline_color);
+        p
+    }
+
+    // 輪郭線に必要な媒質定数
+    pub fn materials(&self) -> Vec<Material> {
+        if self.outline { vec![Material::Eps, Material::Pec] } else { Vec::new() }
+    }
+
+    // 要求した成分がスナップショットに無ければエラー
+    pub fn render(&self, snap: &Snapshot) -> Result<Image> {
+        let (nx, ny) = (snap.xs.len(), snap.ys.len());
+        let k = match self.component {
+            Some(c) => snap.components.iter().position(|s| s.0 == c)
+                .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("snapshot has no {} component", c.name())))?,
+            None if snap.components.is_empty() => return Err(Error::new(ErrorKind::NotFound, "snapshot has no components")),
+            None => 0,
+        };
+        let data: Vec<f64> = match self.log_range {
+            Some(_) => snap.components[k].1.iter().map(|v| v.abs()).collect(),
+            None => snap.components[k].1.clone(),
+        };
+
+        let max = data.iter().fold(f64::MIN, |a, &b| a.max(b));
+        let min = data.iter().fold(f64::MAX, |a, &b| a.min(b));
+        let amax = data.iter().fold(0.0f64, |a, &b| a.max(b.abs()));
+        let (lo, hi) = match self.log_range {
+            Some(db) => (-db, 0.0),
+            None => match self.scaling {
+                Scaling::Fixed(a, b) => (a, b),
+                Scaling::Symmetric => (-amax, amax),
+                Scaling::Auto => (min, max),
+            },
+        };
+        let peak = match self.scaling {
+            Scaling::Fixed(_, b) => b.abs(),
+            _ => amax,
+        };
+
+        let index = |v: f64| -> u8 {
+            let v = match self.log_range {
+                Some(_) => 20.0 * (v / peak).log10(),
+                None => v,
+            };
+            let t = if hi > lo { (v - lo) / (hi - lo) } else { 0.5 };
+            let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+            (t * (NCOLOR - 1) as f64).round() as u8
+        };
+
+        // 媒質の境界 (右または上のセルと異なる点)
+        let edge = |i: usize, j: usize| -> bool {
+            snap.materials.iter().any(|(_, m)| {
+                let v = m[j * nx + i];
+                (i + 1 < nx && m[j * nx + i + 1] != v) || (j + 1 < ny && m[(j + 1) * nx + i] != v)
+            })
+        };
+
+        let z = self.zoom.max(1);
+        let (w, h) = (nx * z, ny * z);
+        let mut pixels = vec![0u8; w * h];
+        for j in 0..ny {
+            for i in 0..nx {
+                let c = if self.outline && edge(i, j) { OUTLINE } else { index(data[j * nx + i]) };
+                // yが上向きになるよう上下反転
+                for dy in 0..z {
+                    let row = h - 1 - (j * z + dy);
+                    for dx in 0..z {
+                        pixels[row * w + i * z + dx] = c;
+                    }
+                }
+            }
+        }
+        Ok(Image{width: w, height: h, pixels, palette: self.palette()})
+    }
+}
+
+impl Image {
+    pub fn rgb(&self) -> Vec<u8> {
+        self.pixels.iter().flat_map(|&p| self.palette[p as usize].to_vec()).collect()
+    }
+
+    pub fn save_ppm<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        write!(w, "P6\n{} {}\n255\n", self.width, self.height)?;
+        w.write_all(&self.rgb())?;
+        w.flush()
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        w.write_all(b"\x89PNG\r\n\x1a\n")?;
+
+        let mut ihdr = Vec::new();
+        ihdr.extend_from_slice(&(self.width as u32).to_be_bytes());
+        ihdr.extend_from_slice(&(self.height as u32).to_be_bytes());
+        ihdr.extend_from_slice(&[8, 3, 0, 0, 0]); // 8bit, パレット
+        png_chunk(&mut w, b"IHDR", &ihdr)?;
+        let plte: Vec<u8> = self.palette.iter().flat_map(|c| c.to_vec()).collect();
+        png_chunk(&mut w, b"PLTE", &plte)?;
+
+        let mut raw = Vec::with_capacity((self.width + 1) * self.height);
+        for row in self.pixels.chunks(self.width) {
+            raw.push(0); // フィルタ無し
+            raw.extend_from_slice(row);
+        }
+        png_chunk(&mut w, b"IDAT", &zlib_stored(&raw))?;
+        png_chunk(&mut w, b"IEND", &[])?;
+        w.flush()
+    }
+}
+
+fn png_chunk<W: Write>(w: &mut W, ty: &[u8; 4], data: &[u8]) -> Result<()> {
+    w.write_all(&(data.len() as u32).to_be_bytes())?;
+    let mut c = ty.to_vec();
+    c.extend_from_slice(data);
+    w.write_all(&c)?;
+    w.write_all(&crc32(&c).to_be_bytes())
+}
+
+// 無圧縮deflateブロックのzlibストリーム
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut o = vec![0x78, 0x01];
+    let mut blocks = data.chunks(65535).peekable();
+    if blocks.peek().is_none() {
+        o.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(b) = blocks.next() {
+        o.push(if blocks.peek().is_none() { 1 } else { 0 });
+        o.extend_from_slice(&(b.len() as u16).to_le_bytes());
+        o.extend_from_slice(&(!(b.len() as u16)).to_le_bytes());
+        o.extend_from_slice(b);
+    }
+    let (mut a, mut b) = (1u32, 0u32);
+    for &d in data {
+        a = (a + d as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    o.extend_from_slice(&((b << 16) | a).to_be_bytes());
+    o
+}
+
+// 画像形式
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum ImageFormat {
+    Ppm,
+    Png,
+}
+
+// 連番画像出力
+#[derive(Clone,Debug)]
+pub struct ImageBackend {
+    pub renderer: Renderer,
+    pub format: ImageFormat,
+}
+
+impl SnapshotBackend for ImageBackend {
+    fn extension(&self) -> &str {
+        match self.format {
+            ImageFormat::Ppm => "ppm",
+            ImageFormat::Png => "png",
+        }
+    }
+
+    fn write(&mut self, path: &Path, snap: &Snapshot, _eol: LineEnding) -> Result<()> {
+        let img = self.renderer.render(snap)?;
+        match self.format {
+            ImageFormat::Ppm => img.save_ppm(path),
+            ImageFormat::Png => img.save_png(path),
+        }
+    }
+
+    fn materials(&self) -> Vec<Material> {
+        self.renderer.materials()
+    }
+}
+
+// アニメーションGIF出力 (全フレームを "name.gif" 1ファイルへ)
+pub struct GifBackend {
+    pub renderer: Renderer,
+    // フレーム間隔(1/100秒)
+    pub delay: u16,
+    name: String,
+    out: Option<BufWriter<File>>,
+}
+
+impl GifBackend {
+    pub fn new(name: &str, renderer: Renderer) -> GifBackend {
+        GifBackend{renderer, delay: 10, name: name.to_string(), out: None}
+    }
+
+    fn open(&self, path: &Path, img: &Image) -> Result<BufWriter<File>> {
+        let dir = path.parent().map(|p| p.to_path_buf()).unwrap_or_default();
+        let mut w = BufWriter::new(File::create(dir.join(format!("{}.gif", self.name)))?);
+        w.write_all(b"GIF89a")?;
+        w.write_all(&(img.width as u16).to_le_bytes())?;
+        w.write_all(&(img.height as u16).to_le_bytes())?;
+        w.write_all(&[0xf7, 0, 0])?; // 256色のグローバルパレット
+        for i in 0..256 {
+            w.write_all(img.palette.get(i).unwrap_or(&[0, 0, 0]))?;
+        }
+        // 無限ループ
+        w.write_all(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00")?;
+        Ok(w)
+    }
+}
+
+impl SnapshotBackend for GifBackend {
+    fn extension(&self) -> &str {
+        "gif"
+    }
+
+    fn write(&mut self, path: &Path, snap: &Snapshot, _eol: LineEnding) -> Result<()> {
+        let img = self.renderer.render(snap)?;
+        if self.out.is_none() {
+            self.out = Some(self.open(path, &img)?);
+        }
+        let w = self.out.as_mut().unwrap();
+        w.write_all(&[0x21, 0xf9, 4, 0])?;
+        w.write_all(&self.delay.to_le_bytes())?;
+        w.write_all(&[0, 0])?;
+        w.write_all(&[0x2c, 0, 0, 0, 0])?;
+        w.write_all(&(img.width as u16).to_le_bytes())?;
+        w.write_all(&(img.height as u16).to_le_bytes())?;
+        w.write_all(&[0, 8])?;
+        for b in lzw(&img.pixels).chunks(255) {
+            w.write_all(&[b.len() as u8])?;
+            w.write_all(b)?;
+        }
+        w.write_all(&[0])
+    }
+
+    fn materials(&self) -> Vec<Material> {
+        self.renderer.materials()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        match self.out.take() {
+            Some(mut w) => {
+                w.write_all(&[0x3b])?;
+                w.flush()
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+// GIF用LZW圧縮 (8bit)
+fn lzw(data: &[u8]) -> Vec<u8> {
+    const CLEAR: u16 = 256;
+    const EOI: u16 = 257;
+    let mut out = BitWriter{out: Vec::new(), acc: 0, nbit: 0};
+    let mut dict: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next = EOI + 1;
+    let mut width = 9;
+    out.put(CLEAR, width);
+
+    let mut it = data.iter();
+    let mut cur = match it.next() {
+        Some(&b) => b as u16,
+        None => {
+            out.put(EOI, width);
+            return out.finish();
+        }
+    };
+    for &b in it {
+        match dict.get(&(cur, b)) {
+            Some(&c) => cur = c,
+            None => {
+                out.put(cur, width);
+                if next < 4096 {
+                    dict.insert((cur, b), next);
+                    next += 1;
+                    if next > (1 << width) && width < 12 {
+                        width += 1;
+                    }
+                } else {
+                    // 辞書が一杯になったら初期化
+                    out.put(CLEAR, width);
+                    dict.clear();
+                    next = EOI + 1;
+                    width = 9;
+                }
+                cur = b as u16;
+            }
+        }
+    }
+    out.put(cur, width);
+    out.put(EOI, width);
+    out.finish()
+}
+
+// LSBから詰めるビット列
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u32,
+    nbit: u32,
+}
+
+impl BitWriter {
+    fn put(&mut self, code: u16, width: u32) {
+        self.acc |= (code as u32) << self.nbit;
+        self.nbit += width;
+        while self.nbit >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.nbit -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.nbit > 0 {
+            self.out.push(self.acc as u8);
+        }
+        self.out
+    }
+}
+
+// 画像1枚を保存 (拡張子で形式を判定)
+pub fn save_image<P: AsRef<Path>>(path: P, snap: &Snapshot, renderer: &Renderer) -> Result<()> {
+    let path = PathBuf::from(path.as_ref());
+    let img = renderer.render(snap)?;
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("ppm") => img.save_ppm(&path),
+        _ => img.save_png(&path),
+    }
+}
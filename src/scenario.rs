@@ -0,0 +1,716 @@
+/*
+ * This software is released under the MIT License.
+ * http://opensource.org/licenses/mit-license.php
+ */
+
+// シナリオファイル(JSON)の読み込み
+//
+// 座標は全てPMLを含む配列番号 (epsmu, init_sourceと同じ)
+//
+// {
+//   "grid": {"nx": 1000, "ny": 1000, "dx": 0.005, "dy": 0.005},
+//   "boundary": {"pml_layers": 8},
+//   "steps": 2000,
+//   "termination": {"type": "decay", "signal": "probes", "probes": ["p0"], "threshold_db": -40, "window": 200, "interval": 10, "max_steps": 20000},
+//   "materials": {"dielectric": {"eps": 3.0, "mu": 1.0, "sigma_e": 0.0, "sigma_m": 0.0}},
+//   "geometry": [{"type": "rect", "material": "dielectric", "x": [300, 700], "y": [300, 700]},
+//                {"type": "pec", "x": [100, 110], "y": [100, 200]}],
+//   "sources": [{"x": 500, "y": 20}],
+//   "probes": [{"name": "p0", "component": "ez", "x": 500, "y": 500}],
+//   "monitors": [{"name": "d0", "type": "dft", "component": "ez", "x": [300, 700], "y": [300, 700], "freqs": [1e9]}],
+//   "outputs": [{"type": "snapshot", "format": "gnuplot", "prefix": "EData", "every": 100, "stride": [50, 50]},
+//               {"type": "container", "path": "result.rfd", "every": 500},
+//               {"type": "monitors", "format": "npz"}]
+// }
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use binary::{export_npz, export_raw, Dtype, NpyBackend, NpzBackend, RawBackend};
+use container::ResultWriter;
+use decay::{Decay, Signal};
+use error;
+use fdtd2d::{fdtd, fdtdBuilder, Component, Medium, RunSummary, StopReason, C, DURATION};
+use json::{self, ParseError, Value};
+use monitor::{DftMonitor, Probe};
+use render::{Colormap, GifBackend, ImageBackend, ImageFormat, Renderer, Scaling};
+use snapshot::{GnuplotBackend, LineEnding, Region, SnapshotWriter};
+use vtk::VtkBackend;
+
+// 読み込みエラー
+#[derive(Clone,Debug,PartialEq)]
+pub enum ScenarioError {
+    Io(String),
+    Parse(ParseError),
+    // path: 問題のある項目 ("geometry[2].x" 等)
+    Invalid{path: String, message: String},
+}
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ScenarioError::Io(ref m) => write!(f, "{}", m),
+            ScenarioError::Parse(ref e) => write!(f, "syntax error at {}", e),
+            ScenarioError::Invalid{ref path, ref message} => write!(f, "{}: {}", path, message),
+        }
+    }
+}
+
+pub fn invalid(path: &str, message: String) -> ScenarioError {
+    ScenarioError::Invalid{path: path.to_string(), message}
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct GridSpec {
+    // PMLを除いたセル数
+    pub nx: usize,
+    pub ny: usize,
+    pub dx: f64,
+    pub dy: f64,
+    pub pml_layers: usize,
+}
+
+impl GridSpec {
+    // PMLを含む配列のセル数
+    pub fn dims(&self) -> (usize, usize) {
+        (self.nx + 2 * self.pml_layers, self.ny + 2 * self.pml_layers)
+    }
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub enum GeometrySpec {
+    Rect{region: Region, medium: Medium},
+    Pec{region: Region},
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct ProbeSpec {
+    pub name: String,
+    pub component: Component,
+    pub x: usize,
+    pub y: usize,
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct MonitorSpec {
+    pub name: String,
+    pub component: Component,
+    pub region: Region,
+    pub freqs: Vec<f64>,
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub enum OutputSpec {
+    Snapshot{format: String, prefix: String, every: usize, stride: (usize, usize), region: Option<Region>,
+             components: Vec<Component>, line_ending: LineEnding, dtype: Dtype,
+             colormap: Colormap, scaling: Scaling, log_range: Option<f64>},
+    Container{path: String, every: usize, dtype: Dtype},
+    Monitors{format: String, dtype: Dtype},
+}
+
+// 検証済みのシナリオ
+#[derive(Clone,Debug,PartialEq)]
+pub struct ScenarioSpec {
+    pub grid: GridSpec,
+    pub steps: usize,
+    // 減衰による終了 (指定時はstepsの代わりにmax_stepsが上限)
+    pub termination: Option<Decay>,
+    pub geometry: Vec<GeometrySpec>,
+    pub sources: Vec<(usize, usize)>,
+    pub probes: Vec<ProbeSpec>,
+    pub monitors: Vec<MonitorSpec>,
+    pub outputs: Vec<OutputSpec>,
+}
+
+// 項目の読み取り (pathはエラー表示用, スイープファイルでも使う)
+pub struct Obj<'a> {
+    path: String,
+    m: &'a [(String, Value)],
+}
+
+impl<'a> Obj<'a> {
+    pub fn new(v: &'a Value, path: &str, keys: &[&str]) -> Result<Obj<'a>, ScenarioError> {
+        let m = v.as_object().ok_or_else(|| invalid(path, format!("expected object, found {}", v.kind())))?;
+        for (k, _) in m {
+            if !keys.contains(&k.as_str()) {
+                return Err(invalid(&join(path, k), format!("unknown key (expected one of: {})", keys.join(", "))));
+            }
+        }
+        Ok(Obj{path: path.to_string(), m})
+    }
+
+    pub fn at(&self, key: &str) -> String {
+        join(&self.path, key)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&'a Value> {
+        self.m.iter().find(|e| e.0 == key).map(|e| &e.1)
+    }
+
+    pub fn req(&self, key: &str) -> Result<&'a Value, ScenarioError> {
+        self.get(key).ok_or_else(|| invalid(&self.at(key), "missing required key".to_string()))
+    }
+
+    pub fn num(&self, key: &str, default: Option<f64>) -> Result<f64, ScenarioError> {
+        match (self.get(key), default) {
+            (None, Some(d)) => Ok(d),
+            _ => {
+                let v = self.req(key)?;
+                v.as_f64().ok_or_else(|| invalid(&self.at(key), format!("expected number, found {}", v.kind())))
+            }
+        }
+    }
+
+    pub fn positive(&self, key: &str, default: Option<f64>) -> Result<f64, ScenarioError> {
+        let v = self.num(key, default)?;
+        if v > 0.0 && v.is_finite() { Ok(v) } else { Err(invalid(&self.at(key), format!("must be positive, got {}", v))) }
+    }
+
+    pub fn uint(&self, key: &str, default: Option<usize>) -> Result<usize, ScenarioError> {
+        let v = self.num(key, default.map(|d| d as f64))?;
+        to_uint(v, &self.at(key))
+    }
+
+    pub fn text(&self, key: &str, default: Option<&str>) -> Result<String, ScenarioError> {
+        match (self.get(key), default) {
+            (None, Some(d)) => Ok(d.to_string()),
+            _ => {
+                let v = self.req(key)?;
+                v.as_str().map(|s| s.to_string()).ok_or_else(|| invalid(&self.at(key), format!("expected string, found {}", v.kind())))
+            }
+        }
+    }
+
+    // [開始, 終端] の組 (0 <= 開始 < 終端 <= max)
+    pub fn range(&self, key: &str, max: usize) -> Result<(usize, usize), ScenarioError> {
+        let p = self.at(key);
+        let v = self.req(key)?;
+        let a = match v.as_array() {
+            Some(a) if a.len() == 2 => a,
+            _ => return Err(invalid(&p, "expected [start, end]".to_string())),
+        };
+        let s = to_uint(a[0].as_f64().unwrap_or(-1.0), &format!("{}[0]", p))?;
+        let e = to_uint(a[1].as_f64().unwrap_or(-1.0), &format!("{}[1]", p))?;
+        if s >= e {
+            return Err(invalid(&p, format!("start {} must be less than end {}", s, e)));
+        }
+        if e > max {
+            return Err(invalid(&p, format!("end {} is outside the grid (0..{})", e, max)));
+        }
+        Ok((s, e))
+    }
+
+    pub fn pair(&self, key: &str, default: (usize, usize)) -> Result<(usize, usize), ScenarioError> {
+        let p = self.at(key);
+        match self.get(key) {
+            None => Ok(default),
+            Some(v) => match v.as_array() {
+                Some(a) if a.len() == 2 => Ok((to_uint(a[0].as_f64().unwrap_or(-1.0), &format!("{}[0]", p))?,
+                                               to_uint(a[1].as_f64().unwrap_or(-1.0), &format!("{}[1]", p))?)),
+                _ => Err(invalid(&p, "expected [x, y]".to_string())),
+            },
+        }
+    }
+
+    pub fn list(&self, key: &str) -> Result<&'a [Value], ScenarioError> {
+        match self.get(key) {
+            None => Ok(&[]),
+            Some(v) => v.as_array().map(|a| &a[..]).ok_or_else(|| invalid(&self.at(key), format!("expected array, found {}", v.kind()))),
+        }
+    }
+
+    fn region(&self, dims: (usize, usize)) -> Result<Region, ScenarioError> {
+        let (x_s, x_l) = self.range("x", dims.0)?;
+        let (y_s, y_l) = self.range("y", dims.1)?;
+        Ok(Region{x_s, x_l, y_s, y_l})
+    }
+
+    fn component(&self, key: &str) -> Result<Component, ScenarioError> {
+        let t = self.text(key, Some("ez"))?;
+        Component::from_name(&t).ok_or_else(|| invalid(&self.at(key), format!("unknown component \"{}\"", t)))
+    }
+
+    fn dtype(&self) -> Result<Dtype, ScenarioError> {
+        match self.text("dtype", Some("f32"))?.as_str() {
+            "f32" => Ok(Dtype::F32),
+            "f64" => Ok(Dtype::F64),
+            t => Err(invalid(&self.at("dtype"), format!("unknown dtype \"{}\" (f32, f64)", t))),
+        }
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() { key.to_string() } else { format!("{}.{}", path, key) }
+}
+
+fn to_uint(v: f64, path: &str) -> Result<usize, ScenarioError> {
+    if v >= 0.0 && v.fract() == 0.0 && v.is_finite() {
+        Ok(v as usize)
+    } else {
+        Err(invalid(path, format!("expected non-negative integer, got {}", v)))
+    }
+}
+
+const FORMATS: [&str; 8] = ["gnuplot", "npy", "npz", "raw", "vtk", "png", "ppm", "gif"];
+
+impl ScenarioSpec {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ScenarioSpec, ScenarioError> {
+        let src = fs::read_to_string(path.as_ref())
+            .map_err(|e| ScenarioError::Io(format!("{}: {}", path.as_ref().display(), e)))?;
+        ScenarioSpec::parse(&src)
+    }
+
+    pub fn parse(src: &str) -> Result<ScenarioSpec, ScenarioError> {
+        let v = json::parse(src).map_err(ScenarioError::Parse)?;
+        ScenarioSpec::from_value(&v)
+    }
+
+    pub fn from_value(v: &Value) -> Result<ScenarioSpec, ScenarioError> {
+        let root = Obj::new(v, "", &["grid", "boundary", "steps", "termination", "materials", "geometry", "sources", "probes", "monitors", "outputs"])?;
+
+        let g = Obj::new(root.req("grid")?, "grid", &["nx", "ny", "dx", "dy"])?;
+        let pml_layers = match root.get("boundary") {
+            Some(b) => Obj::new(b, "boundary", &["pml_layers"])?.uint("pml_layers", Some(8))?,
+            None => 8,
+        };
+        let grid = GridSpec {
+            nx: g.uint("nx", None)?,
+            ny: g.uint("ny", None)?,
+            dx: g.positive("dx", Some(0.005))?,
+            dy: g.positive("dy", Some(0.005))?,
+            pml_layers,
+        };
+        if grid.nx < 2 || grid.ny < 2 {
+            return Err(invalid("grid", "nx and ny must be at least 2".to_string()));
+        }
+        let dims = grid.dims();
+        let steps = root.uint("steps", Some(2000))?;
+
+        // 媒質
+        let mut materials: Vec<(String, Medium)> = Vec::new();
+        if let Some(mv) = root.get("materials") {
+            let mo = mv.as_object().ok_or_else(|| invalid("materials", format!("expected object, found {}", mv.kind())))?;
+            for (name, m) in mo {
+                let o = Obj::new(m, &join("materials", name), &["eps", "mu", "sigma_e", "sigma_m"])?;
+                let d = Medium::default();
+                let med = Medium {
+                    eps: o.positive("eps", Some(d.eps))?,
+                    mu: o.positive("mu", Some(d.mu))?,
+                    sigma_e: o.num("sigma_e", Some(d.sigma_e))?,
+                    sigma_m: o.num("sigma_m", Some(d.sigma_m))?,
+                };
+                if med.sigma_e < 0.0 || med.sigma_m < 0.0 {
+                    return Err(invalid(&join("materials", name), "conductivity must not be negative".to_string()));
+                }
+                materials.push((name.clone(), med));
+            }
+        }
+
+        let mut geometry = Vec::new();
+        for (n, e) in root.list("geometry")?.iter().enumerate() {
+            let p = format!("geometry[{}]", n);
+            let o = Obj::new(e, &p, &["type", "material", "x", "y"])?;
+            match o.text("type", None)?.as_str() {
+                "rect" => {
+                    let name = o.text("material", None)?;
+                    let medium = materials.iter().find(|m| m.0 == name).map(|m| m.1)
+                        .ok_or_else(|| invalid(&o.at("material"), format!("undefined material \"{}\"", name)))?;
+                    geometry.push(GeometrySpec::Rect{region: o.region(dims)?, medium});
+                }
+                "pec" => geometry.push(GeometrySpec::Pec{region: o.region(dims)?}),
+                t => return Err(invalid(&o.at("type"), format!("unknown geometry type \"{}\" (rect, pec)", t))),
+            }
+        }
+
+        let mut sources = Vec::new();
+        for (n, e) in root.list("sources")?.iter().enumerate() {
+            let o = Obj::new(e, &format!("sources[{}]", n), &["x", "y"])?;
+            let (x, y) = (o.uint("x", None)?, o.uint("y", None)?);
+            let l = grid.pml_layers;
+            if x < l || x >= dims.0 - l {
+                return Err(invalid(&o.at("x"), format!("{} is outside the grid or inside the PML ({}..{})", x, l, dims.0 - l)));
+            }
+            if y < l || y >= dims.1 - l {
+                return Err(invalid(&o.at("y"), format!("{} is outside the grid or inside the PML ({}..{})", y, l, dims.1 - l)));
+            }
+            sources.push((x, y));
+        }
+
+        let mut names: Vec<String> = Vec::new();
+        let mut unique = |name: String, p: String| -> Result<String, ScenarioError> {
+            if names.contains(&name) {
+                return Err(invalid(&p, format!("duplicate name \"{}\"", name)));
+            }
+            names.push(name.clone());
+            Ok(name)
+        };
+
+        let mut probes = Vec::new();
+        for (n, e) in root.list("probes")?.iter().enumerate() {
+            let o = Obj::new(e, &format!("probes[{}]", n), &["name", "component", "x", "y"])?;
+            let name = unique(o.text("name", Some(&format!("probe{}", n)))?, o.at("name"))?;
+            let (x, y) = (o.uint("x", None)?, o.uint("y", None)?);
+            if x >= dims.0 {
+                return Err(invalid(&o.at("x"), format!("{} is outside the grid (0..{})", x, dims.0)));
+            }
+            if y >= dims.1 {
+                return Err(invalid(&o.at("y"), format!("{} is outside the grid (0..{})", y, dims.1)));
+            }
+            probes.push(ProbeSpec{name, component: o.component("component")?, x, y});
+        }
+
+        let mut monitors = Vec::new();
+        for (n, e) in root.list("monitors")?.iter().enumerate() {
+            let o = Obj::new(e, &format!("monitors[{}]", n), &["name", "type", "component", "x", "y", "freqs"])?;
+            let name = unique(o.text("name", Some(&format!("monitor{}", n)))?, o.at("name"))?;
+            let t = o.text("type", Some("dft"))?;
+            if t != "dft" {
+                return Err(invalid(&o.at("type"), format!("unknown monitor type \"{}\" (dft)", t)));
+            }
+            let mut freqs = Vec::new();
+            for (k, f) in o.list("freqs")?.iter().enumerate() {
+                match f.as_f64() {
+                    Some(v) if v > 0.0 => freqs.push(v),
+                    _ => return Err(invalid(&format!("{}[{}]", o.at("freqs"), k), "frequency must be a positive number".to_string())),
+                }
+            }
+            if freqs.is_empty() {
+                return Err(invalid(&o.at("freqs"), "at least one frequency is required".to_string()));
+            }
+            monitors.push(MonitorSpec{name, component: o.component("component")?, region: o.region(dims)?, freqs});
+        }
+
+        let termination = match root.get("termination") {
+            None => None,
+            Some(e) => {
+                let o = Obj::new(e, "termination", &["type", "signal", "probes", "threshold_db", "window", "interval", "max_steps"])?;
+                let t = o.text("type", Some("decay"))?;
+                if t != "decay" {
+                    return Err(invalid(&o.at("type"), format!("unknown termination type \"{}\" (decay)", t)));
+                }
+                let signal = match o.text("signal", Some("energy"))?.as_str() {
+                    "energy" => Signal::Energy,
+                    "probes" => {
+                        // 省略時は全プローブ, 空の配列は指定漏れとみなす
+                        let list = o.list("probes")?;
+                        if o.get("probes").is_some() && list.is_empty() {
+                            return Err(invalid(&o.at("probes"), "must not be empty (omit to use all probes)".to_string()));
+                        }
+                        let mut idx = Vec::new();
+                        for (k, v) in list.iter().enumerate() {
+                            let pp = format!("{}[{}]", o.at("probes"), k);
+                            let name = v.as_str().ok_or_else(|| invalid(&pp, format!("expected probe name, found {}", v.kind())))?;
+                            idx.push(probes.iter().position(|p| p.name == name)
+                                .ok_or_else(|| invalid(&pp, format!("undefined probe \"{}\"", name)))?);
+                        }
+                        if probes.is_empty() {
+                            return Err(invalid(&o.at("signal"), "no probes are defined".to_string()));
+                        }
+                        Signal::Probes(idx)
+                    }
+                    s => return Err(invalid(&o.at("signal"), format!("unknown signal \"{}\" (energy, probes)", s))),
+                };
+                let threshold_db = o.num("threshold_db", Some(-40.0))?;
+                if threshold_db >= 0.0 {
+                    return Err(invalid(&o.at("threshold_db"), format!("must be negative, got {}", threshold_db)));
+                }
+                Some(Decay::new(signal, threshold_db, o.uint("max_steps", Some(steps))?)
+                     .window(o.uint("window", Some(200))?)
+                     .interval(o.uint("interval", Some(10))?))
+            }
+        };
+
+        let mut outputs = Vec::new();
+        for (n, e) in root.list("outputs")?.iter().enumerate() {
+            let p = format!("outputs[{}]", n);
+            let t = Obj::new(e, &p, &["type", "format", "prefix", "every", "stride", "x", "y", "components", "line_ending",
+                                       "dtype", "colormap", "scaling", "log_range", "path"])?.text("type", None)?;
+            match t.as_str() {
+                "snapshot" => {
+                    let o = Obj::new(e, &p, &["type", "format", "prefix", "every", "stride", "x", "y", "components", "line_ending",
+                                               "dtype", "colormap", "scaling", "log_range"])?;
+                    let format = o.text("format", Some("gnuplot"))?;
+                    if !FORMATS.contains(&format.as_str()) {
+                        return Err(invalid(&o.at("format"), format!("unknown format \"{}\" ({})", format, FORMATS.join(", "))));
+                    }
+                    let region = if o.get("x").is_some() || o.get("y").is_some() { Some(o.region(dims)?) } else { None };
+                    let mut components = Vec::new();
+                    for (k, c) in o.list("components")?.iter().enumerate() {
+                        let cp = format!("{}[{}]", o.at("components"), k);
+                        components.push(c.as_str().and_then(Component::from_name).ok_or_else(|| invalid(&cp, "unknown component".to_string()))?);
+                    }
+                    if components.is_empty() {
+                        components.push(Component::Ez);
+                    }
+                    let line_ending = match o.text("line_ending", Some("lf"))?.as_str() {
+                        "lf" => LineEnding::Lf,
+                        "crlf" => LineEnding::CrLf,
+                        s => return Err(invalid(&o.at("line_ending"), format!("unknown line ending \"{}\" (lf, crlf)", s))),
+                    };
+                    let colormap = match o.text("colormap", Some("diverging"))?.as_str() {
+                        "diverging" => Colormap::Diverging,
+                        "viridis" => Colormap::Viridis,
+                        "gray" => Colormap::Gray,
+                        s => return Err(invalid(&o.at("colormap"), format!("unknown colormap \"{}\" (diverging, viridis, gray)", s))),
+                    };
+                    let scaling = match o.get("scaling") {
+                        None => Scaling::Symmetric,
+                        Some(Value::Str(s)) if s == "symmetric" => Scaling::Symmetric,
+                        Some(Value::Str(s)) if s == "auto" => Scaling::Auto,
+                        Some(Value::Array(a)) if a.len() == 2 && a[0].as_f64().is_some() && a[1].as_f64().is_some() =>
+                            Scaling::Fixed(a[0].as_f64().unwrap_or(0.0), a[1].as_f64().unwrap_or(0.0)),
+                        Some(_) => return Err(invalid(&o.at("scaling"), "expected \"symmetric\", \"auto\" or [min, max]".to_string())),
+                    };
+                    let log_range = match o.get("log_range") {
+                        None => None,
+                        Some(_) => Some(o.positive("log_range", None)?),
+                    };
+                    outputs.push(OutputSpec::Snapshot {
+                        format,
+                        prefix: o.text("prefix", Some("EData"))?,
+                        every: o.uint("every", Some(100))?.max(1),
+                        stride: o.pair("stride", (1, 1))?,
+                        region, components, line_ending,
+                        dtype: o.dtype()?,
+                        colormap, scaling, log_range,
+                    });
+                }
+                "container" => {
+                    let o = Obj::new(e, &p, &["type", "path", "every", "dtype"])?;
+                    outputs.push(OutputSpec::Container{path: o.text("path", Some("result.rfd"))?, every: o.uint("every", Some(0))?, dtype: o.dtype()?});
+                }
+                "monitors" => {
+                    let o = Obj::new(e, &p, &["type", "format", "dtype"])?;
+                    let format = o.text("format", Some("npz"))?;
+                    if format != "npz" && format != "raw" {
+                        return Err(invalid(&o.at("format"), format!("unknown format \"{}\" (npz, raw)", format)));
+                    }
+                    outputs.push(OutputSpec::Monitors{format, dtype: o.dtype()?});
+                }
+                s => return Err(invalid(&join(&p, "type"), format!("unknown output type \"{}\" (snapshot, container, monitors)", s))),
+            }
+        }
+
+        Ok(ScenarioSpec{grid, steps, termination, geometry, sources, probes, monitors, outputs})
+    }
+
+    // ステップ数の上限
+    pub fn max_steps(&self) -> usize {
+        self.termination.as_ref().map_or(self.steps, |d| d.max_steps)
+    }
+
+    // 実行前の見積もり
+    // freq: 評価する周波数 (Noneの場合はDFTモニタの最高周波数, 無ければ給電パルスの-20dB帯域)
+    pub fn dry_run(&self, freq: Option<f64>) -> DryRun {
+        let g = &self.grid;
+        let dims = g.dims();
+        let dt = fdtdBuilder::time_step(g.dx, g.dy);
+        let mut memory = fdtdBuilder::memory_estimate(dims.0, dims.1);
+        let steps = self.max_steps();
+        memory += self.probes.len() * steps * 16;
+        for m in &self.monitors {
+            memory += m.freqs.len() * (m.region.x_l - m.region.x_s) * (m.region.y_l - m.region.y_s) * 16;
+        }
+        let f_mon = self.monitors.iter().flat_map(|m| m.freqs.iter().cloned()).fold(0.0, f64::max);
+        let f_max = match freq {
+            Some(f) => f,
+            None if f_mon > 0.0 => f_mon,
+            None => 10f64.ln().sqrt() / (::std::f64::consts::PI * DURATION),
+        };
+        let n_max = self.geometry.iter().fold(1.0f64, |n, e| match *e {
+            GeometrySpec::Rect{medium: m, ..} => n.max((m.eps * m.mu).sqrt()),
+            GeometrySpec::Pec{..} => n,
+        });
+        DryRun {
+            dims,
+            cells: dims.0 * dims.1,
+            memory,
+            dt,
+            steps,
+            time: dt * steps as f64,
+            f_max,
+            n_max,
+            cells_per_wavelength: C / (f_max * n_max) / g.dx.max(g.dy),
+        }
+    }
+
+    // 解析モジュールを生成 (出力はout_dir以下)
+    pub fn build<P: AsRef<Path>>(&self, out_dir: P) -> error::Result<Scenario> {
+        let g = &self.grid;
+        let mut f = fdtdBuilder::newGrid(g.nx as i32, g.ny as i32, g.dx, g.dy, g.pml_layers as i32);
+
+        // 媒質 → 係数計算 → 完全導体 の順
+        for e in &self.geometry {
+            if let GeometrySpec::Rect{region: r, medium} = *e {
+                f.medium(r.x_s, r.x_l, r.y_s, r.y_l, medium)?;
+            }
+        }
+        for &(x, y) in &self.sources {
+            f.init_source(x, y)?;
+        }
+        f.setup();
+        for e in &self.geometry {
+            if let GeometrySpec::Pec{region: r} = *e {
+                f.pec_rect(r.x_s, r.x_l, r.y_s, r.y_l)?;
+            }
+        }
+
+        let dir = out_dir.as_ref().to_path_buf();
+        for p in &self.probes {
+            f.add_probe(Probe::new(p.component, p.x, p.y))?;
+        }
+        for m in &self.monitors {
+            let d = DftMonitor::new(&f, m.component, m.region, &m.freqs)?;
+            f.add_monitor(d)?;
+        }
+        let probes = self.probes.iter().map(|p| p.name.clone()).collect();
+        let monitors = self.monitors.iter().map(|m| m.name.clone()).collect();
+
+        let mut writers = Vec::new();
+        for o in &self.outputs {
+            if let OutputSpec::Snapshot{ref format, ref prefix, every, stride, region, ref components, line_ending, dtype,
+                                        colormap, scaling, log_range} = *o {
+                let mut w = SnapshotWriter::new(prefix).dir(&dir).every(every).stride(stride.0, stride.1)
+                    .components(components).line_ending(line_ending);
+                if let Some(r) = region {
+                    w = w.array_region(r);
+                }
+                let renderer = Renderer{colormap, scaling, log_range, ..Renderer::default()};
+                w = match format.as_str() {
+                    "npy" => w.backend(NpyBackend{dtype}),
+                    "npz" => w.backend(NpzBackend{dtype}),
+                    "raw" => w.backend(RawBackend{dtype}),
+                    "vtk" => w.backend(VtkBackend::new(prefix).dtype(dtype)),
+                    "png" => w.backend(ImageBackend{renderer, format: ImageFormat::Png}),
+                    "ppm" => w.backend(ImageBackend{renderer, format: ImageFormat::Ppm}),
+                    "gif" => w.backend(GifBackend::new(prefix, renderer)),
+                    _ => w.backend(GnuplotBackend),
+                };
+                writers.push(w);
+            }
+        }
+
+        Ok(Scenario{solver: f, steps: self.steps, termination: self.termination.clone(), probes, monitors, writers, outputs: self.outputs.clone(), dir})
+    }
+}
+
+// 実行前の見積もり結果
+#[derive(Clone,Debug)]
+pub struct DryRun {
+    // 配列のセル数 (PMLを含む)
+    pub dims: (usize, usize),
+    pub cells: usize,
+    // 使用メモリ (bytes)
+    pub memory: usize,
+    pub dt: f64,
+    pub steps: usize,
+    // 解析時間 (s)
+    pub time: f64,
+    // 評価周波数と最大屈折率
+    pub f_max: f64,
+    pub n_max: f64,
+    // 最短波長あたりのセル数 (10以上が目安)
+    pub cells_per_wavelength: f64,
+}
+
+// 実行可能なシナリオ
+pub struct Scenario {
+    pub solver: fdtd,
+    pub steps: usize,
+    pub termination: Option<Decay>,
+    // 観測点, DFTモニタの名前 (並びはsolverに登録した順)
+    pub probes: Vec<String>,
+    pub monitors: Vec<String>,
+    pub writers: Vec<SnapshotWriter>,
+    pub outputs: Vec<OutputSpec>,
+    pub dir: PathBuf,
+}
+
+impl Scenario {
+    pub fn probe(&self, name: &str) -> Option<&Probe> {
+        self.probes.iter().position(|n| n == name).map(|i| &self.solver.probes()[i])
+    }
+
+    pub fn monitor(&self, name: &str) -> Option<&DftMonitor> {
+        self.monitors.iter().position(|n| n == name).map(|i| &self.solver.monitors()[i])
+    }
+
+    // 全ステップを計算し, 出力を書き出す
+    pub fn run(&mut self) -> io::Result<RunSummary> {
+        self.run_with(|_, _| {})
+    }
+
+    // progress(ステップ, 時刻) を毎ステップ呼ぶ
+    pub fn run_with<F: FnMut(usize, f64)>(&mut self, mut progress: F) -> io::Result<RunSummary> {
+        fs::create_dir_all(&self.dir)?;
+        let mut containers = Vec::new();
+        for o in &self.outputs {
+            if let OutputSpec::Container{ref path, every, dtype} = *o {
+                let mut c = ResultWriter::create(self.dir.join(path), dtype)?;
+                c.write_params(&self.solver)?;
+                c.write_geometry(&self.solver)?;
+                c.write_sources(&self.solver)?;
+                containers.push((c, SnapshotWriter::new("").every(if every == 0 { usize::MAX } else { every })));
+            }
+        }
+
+        let f = &mut self.solver;
+        let mut decay = self.termination.clone();
+        let max = decay.as_ref().map_or(self.steps, |d| d.max_steps);
+        let mut stop = if decay.is_some() { StopReason::MaxSteps } else { StopReason::Steps };
+        while f.step_count() < max {
+            f.step();
+            let (s, t) = (f.step_count(), f.time());
+            for w in &mut self.writers {
+                w.write(f, s, t)?;
+            }
+            for (c, w) in &mut containers {
+                if let Some(snap) = w.capture_due(f, s, t) {
+                    c.write_snapshot(&snap)?;
+                }
+            }
+            progress(s, t);
+            if f.stop_requested() {
+                stop = StopReason::Observer;
+                break;
+            }
+            if let Some(r) = decay.as_mut().and_then(|d| d.update(f)) {
+                stop = r;
+                break;
+            }
+        }
+
+        for w in &mut self.writers {
+            w.finish()?;
+        }
+        let f = &self.solver;
+        for (mut c, _) in containers {
+            for (name, p) in self.probes.iter().zip(f.probes()) {
+                c.write_export(&format!("/probes/{}", name), p)?;
+            }
+            for (name, m) in self.monitors.iter().zip(f.monitors()) {
+                c.write_export(&format!("/dft/{}", name), m)?;
+            }
+            c.finish()?;
+        }
+        for o in &self.outputs {
+            if let OutputSpec::Monitors{ref format, dtype} = *o {
+                for (name, p) in self.probes.iter().zip(f.probes()) {
+                    match format.as_str() {
+                        "raw" => export_raw(&self.dir, name, p, dtype)?,
+                        _ => export_npz(self.dir.join(format!("{}.npz", name)), p, dtype)?,
+                    }
+                }
+                for (name, m) in self.monitors.iter().zip(f.monitors()) {
+                    match format.as_str() {
+                        "raw" => export_raw(&self.dir, name, m, dtype)?,
+                        _ => export_npz(self.dir.join(format!("{}.npz", name)), m, dtype)?,
+                    }
+                }
+            }
+        }
+        Ok(RunSummary{steps: f.step_count(), time: f.time(), stop})
+    }
+}
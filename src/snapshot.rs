@@ -0,0 +1,264 @@
+/*
+ * This software is released under the MIT License.
+ * http://opensource.org/licenses/mit-license.php
+ */
+
+use std::fs::File;
+use std::io::{BufWriter, Result, Write};
+use std::path::{Path, PathBuf};
+
+use fdtd2d::{fdtd, Component, Material};
+
+// 改行コード
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+}
+
+// 領域 (終端は含まない. SnapshotWriter::region のみPMLを除いた解析領域のセル番号, それ以外は配列番号)
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Region {
+    pub x_s: usize,
+    pub x_l: usize,
+    pub y_s: usize,
+    pub y_l: usize,
+}
+
+// 間引き後の場のデータ
+#[derive(Clone,Debug)]
+pub struct Snapshot {
+    pub step: usize,
+    pub time: f64,
+    // 出力点の座標
+    pub xs: Vec<f64>,
+    pub ys: Vec<f64>,
+    // 成分毎のデータ [j * xs.len() + i]
+    pub components: Vec<(Component, Vec<f64>)>,
+    // 出力形式が要求する媒質定数 (並びは成分と同じ)
+    pub materials: Vec<(Material, Vec<f64>)>,
+}
+
+impl Snapshot {
+    pub fn value(&self, k: usize, i: usize, j: usize) -> f64 {
+        self.components[k].1[j * self.xs.len() + i]
+    }
+}
+
+// 出力形式
+pub trait SnapshotBackend {
+    // ファイルの拡張子
+    fn extension(&self) -> &str;
+
+    // 1スナップショットをファイルへ出力
+    fn write(&mut self, path: &Path, snap: &Snapshot, eol: LineEnding) -> Result<()>;
+
+    // 併せて出力する媒質定数
+    fn materials(&self) -> Vec<Material> {
+        Vec::new()
+    }
+
+    // 時間ループ終了時の処理
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+// gnuplot(splot)用テキスト形式 "x y 値..." 行毎に空行
+#[derive(Clone,Copy,Debug,Default)]
+pub struct GnuplotBackend;
+
+impl GnuplotBackend {
+    pub fn write_to<W: Write>(&self, w: &mut W, snap: &Snapshot, eol: LineEnding) -> Result<()> {
+        let eol = eol.as_str();
+        for (j, y) in snap.ys.iter().enumerate() {
+            for (i, x) in snap.xs.iter().enumerate() {
+                write!(w, "{} {}", x, y)?;
+                for k in 0..snap.components.len() {
+                    write!(w, " {}", snap.value(k, i, j))?;
+                }
+                write!(w, "{}", eol)?;
+            }
+            write!(w, "{}", eol)?;
+        }
+        Ok(())
+    }
+}
+
+impl SnapshotBackend for GnuplotBackend {
+    fn extension(&self) -> &str {
+        "txt"
+    }
+
+    fn write(&mut self, path: &Path, snap: &Snapshot, eol: LineEnding) -> Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write_to(&mut w, snap, eol)?;
+        w.flush()
+    }
+}
+
+// 出力領域の指定方法
+#[derive(Clone,Copy,Debug)]
+enum Area {
+    // PMLを除いた解析領域のセル番号
+    Interior(Region),
+    // PMLを含む配列番号
+    Array(Region),
+}
+
+// スナップショット出力器
+pub struct SnapshotWriter {
+    dir: PathBuf,
+    prefix: String,
+    stride_x: usize,
+    stride_y: usize,
+    every: usize,
+    // 出力領域 (Noneは解析領域全体)
+    region: Option<Area>,
+    components: Vec<Component>,
+    line_ending: LineEnding,
+    backend: Box<dyn SnapshotBackend>,
+}
+
+impl SnapshotWriter {
+    // 標準はEzのみ, 間引き無し, 毎ステップ, gnuplot形式
+    pub fn new(prefix: &str) -> SnapshotWriter {
+        SnapshotWriter {
+            dir: PathBuf::from("."),
+            prefix: prefix.to_string(),
+            stride_x: 1,
+            stride_y: 1,
+            every: 1,
+            region: None,
+            components: vec![Component::Ez],
+            line_ending: LineEnding::Lf,
+            backend: Box::new(GnuplotBackend),
+        }
+    }
+
+    // 出力先ディレクトリ
+    pub fn dir<P: AsRef<Path>>(mut self, dir: P) -> SnapshotWriter {
+        self.dir = dir.as_ref().to_path_buf();
+        self
+    }
+
+    // 空間の間引き間隔(セル数)
+    pub fn stride(mut self, sx: usize, sy: usize) -> SnapshotWriter {
+        self.stride_x = sx.max(1);
+        self.stride_y = sy.max(1);
+        self
+    }
+
+    // 時間の間引き間隔(ステップ数)
+    pub fn every(mut self, n: usize) -> SnapshotWriter {
+        self.every = n.max(1);
+        self
+    }
+
+    // 出力領域 (PMLを除いた解析領域のセル番号)
+    pub fn region(mut self, r: Region) -> SnapshotWriter {
+        self.region = Some(Area::Interior(r));
+        self
+    }
+
+    // 出力領域 (PMLを含む配列番号. シナリオファイル, DftMonitor等と同じ番号)
+    pub fn array_region(mut self, r: Region) -> SnapshotWriter {
+        self.region = Some(Area::Array(r));
+        self
+    }
+
+    pub fn components(mut self, c: &[Component]) -> SnapshotWriter {
+        self.components = c.to_vec();
+        self
+    }
+
+    pub fn line_ending(mut self, eol: LineEnding) -> SnapshotWriter {
+        self.line_ending = eol;
+        self
+    }
+
+    pub fn backend<B: SnapshotBackend + 'static>(mut self, b: B) -> SnapshotWriter {
+        self.backend = Box::new(b);
+        self
+    }
+
+    // 出力対象のステップか
+    pub fn is_due(&self, step: usize) -> bool {
+        step.is_multiple_of(self.every)
+    }
+
+    // 出力ファイル名 (prefix + 出力番号 + 拡張子)
+    pub fn path(&self, step: usize) -> PathBuf {
+        self.dir.join(format!("{}{}.{}", self.prefix, step / self.every, self.backend.extension()))
+    }
+
+    // 場を間引いて取り出す
+    pub fn capture(&self, f: &fdtd, step: usize, time: f64) -> Snapshot {
+        // 標準はPMLを除いた解析領域. 配列番号に直して範囲に切り詰める
+        let (nx, ny) = f.dims();
+        let l = f.lpml();
+        let r = match self.region {
+            Some(Area::Array(r)) => Region{x_l: r.x_l.min(nx), y_l: r.y_l.min(ny), ..r},
+            Some(Area::Interior(r)) => Region{x_s: r.x_s + l, x_l: (r.x_l + l).min(nx - l), y_s: r.y_s + l, y_l: (r.y_l + l).min(ny - l)},
+            None => Region{x_s: l, x_l: nx - l, y_s: l, y_l: ny - l},
+        };
+
+        let is: Vec<usize> = (r.x_s..r.x_l).step_by(self.stride_x).collect();
+        let js: Vec<usize> = (r.y_s..r.y_l).step_by(self.stride_y).collect();
+
+        let sample = |g: &dyn Fn(usize, usize) -> f64| {
+            let mut v = Vec::with_capacity(is.len() * js.len());
+            for &j in &js {
+                for &i in &is {
+                    v.push(g(i, j));
+                }
+            }
+            v
+        };
+        let components = self.components.iter().map(|&c| {
+            let a = f.field(c);
+            (c, sample(&|i, j| a[i][j]))
+        }).collect();
+        let materials = self.backend.materials().into_iter().map(|m| {
+            (m, sample(&|i, j| f.material(m, i, j)))
+        }).collect();
+
+        Snapshot {
+            step,
+            time,
+            xs: is.iter().map(|&i| f.position(i, 0).0).collect(),
+            ys: js.iter().map(|&j| f.position(0, j).1).collect(),
+            components,
+            materials,
+        }
+    }
+
+    // 出力対象のステップであれば場を間引いて取り出す
+    pub fn capture_due(&self, f: &fdtd, step: usize, time: f64) -> Option<Snapshot> {
+        if self.is_due(step) { Some(self.capture(f, step, time)) } else { None }
+    }
+
+    // 出力対象のステップであればファイルへ出力
+    pub fn write(&mut self, f: &fdtd, step: usize, time: f64) -> Result<bool> {
+        if !self.is_due(step) {
+            return Ok(false);
+        }
+        let snap = self.capture(f, step, time);
+        let path = self.path(step);
+        self.backend.write(&path, &snap, self.line_ending)?;
+        Ok(true)
+    }
+
+    pub fn finish(&mut self) -> Result<()> {
+        self.backend.finish()
+    }
+}
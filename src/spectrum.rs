@@ -0,0 +1,228 @@
+/*
+ * This software is released under the MIT License.
+ * http://opensource.org/licenses/mit-license.php
+ */
+
+// 観測点の時系列のスペクトル解析 (FFT, 窓関数, ゼロ詰め, 伝達関数, 群遅延)
+//
+// スペクトルは X(f) = Δt Σ x(t_k) exp(-j 2π f t_k) で, 連続のフーリエ変換の近似になる
+// t_k は記録した時刻なので, 位相と群遅延は計算開始(t=0)が基準
+
+use std::f64::consts::PI;
+use std::io::{self, Write};
+
+use complex::Complex;
+use error::{Error, Result};
+use fdtd2d::Source;
+use monitor::Probe;
+
+// 基数2のFFT (長さは2のべき乗, それ以外はエラー). inverseで逆変換 (1/Nは掛けない)
+pub fn fft(data: &mut [Complex], inverse: bool) -> Result<()> {
+    if !data.len().is_power_of_two() {
+        return Err(Error::InvalidValue{what: "fft length (not a power of two)", value: data.len() as f64});
+    }
+    radix2(data, inverse);
+    Ok(())
+}
+
+fn radix2(data: &mut [Complex], inverse: bool) {
+    let n = data.len();
+    // ビット反転の並べ替え
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        // 回転因子は誤差が積み重ならないよう毎回計算
+        let w: Vec<Complex> = (0..len / 2).map(|k| Complex::from_polar(1.0, sign * 2.0 * PI * k as f64 / len as f64)).collect();
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let a = data[start + k];
+                let b = data[start + k + len / 2] * w[k];
+                data[start + k] = a + b;
+                data[start + k + len / 2] = a - b;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Window {
+    Rect,
+    Hann,
+    Hamming,
+    Blackman,
+}
+
+impl Window {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Window::Rect => "rect",
+            Window::Hann => "hann",
+            Window::Hamming => "hamming",
+            Window::Blackman => "blackman",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Window> {
+        [Window::Rect, Window::Hann, Window::Hamming, Window::Blackman].iter().cloned().find(|w| w.name() == name)
+    }
+
+    // 長さnの窓のk番目の係数
+    pub fn coefficient(&self, k: usize, n: usize) -> f64 {
+        if n < 2 {
+            return 1.0;
+        }
+        let x = 2.0 * PI * k as f64 / (n - 1) as f64;
+        match *self {
+            Window::Rect => 1.0,
+            Window::Hann => 0.5 - 0.5 * x.cos(),
+            Window::Hamming => 0.54 - 0.46 * x.cos(),
+            Window::Blackman => 0.42 - 0.5 * x.cos() + 0.08 * (2.0 * x).cos(),
+        }
+    }
+}
+
+// 解析条件
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Spectral {
+    pub window: Window,
+    // FFTの最小点数 (記録長以上の2のべき乗にゼロ詰めする)
+    pub n_fft: usize,
+}
+
+impl Spectral {
+    pub fn new(window: Window) -> Spectral {
+        Spectral{window, n_fft: 0}
+    }
+
+    // ゼロ詰めで周波数の刻みを細かくする
+    pub fn pad(mut self, n: usize) -> Spectral {
+        self.n_fft = n;
+        self
+    }
+
+    // 等間隔の時系列 (時刻 t0 + k dt) のスペクトル. 0からナイキスト周波数まで
+    pub fn analyze(&self, values: &[f64], t0: f64, dt: f64) -> Spectrum {
+        let n = values.len().max(self.n_fft).max(1).next_power_of_two();
+        let mut data = vec![Complex::default(); n];
+        for (k, v) in values.iter().enumerate() {
+            data[k] = Complex::new(v * self.window.coefficient(k, values.len()), 0.0);
+        }
+        radix2(&mut data, false);
+        let df = 1.0 / (n as f64 * dt);
+        let freqs: Vec<f64> = (0..n / 2 + 1).map(|k| k as f64 * df).collect();
+        let values = freqs.iter().zip(&data)
+            .map(|(&f, &x)| x.scale(dt) * Complex::from_polar(1.0, -2.0 * PI * f * t0))
+            .collect();
+        Spectrum{freqs, values}
+    }
+
+    // 観測点の時系列のスペクトル (記録が2点未満では時間刻みが決まらないのでエラー)
+    pub fn probe(&self, p: &Probe) -> Result<Spectrum> {
+        let dt = sampling(p)?;
+        Ok(self.analyze(&p.values, p.times[0], dt))
+    }
+
+    // 観測点と同じ時刻で標本化した給電波形のスペクトル
+    pub fn source(&self, s: &Source, p: &Probe) -> Result<Spectrum> {
+        let dt = sampling(p)?;
+        let w: Vec<f64> = p.times.iter().map(|&t| s.waveform(t, dt)).collect();
+        Ok(self.analyze(&w, p.times[0], dt))
+    }
+
+    // 給電波形で規格化した伝達関数 (観測点のスペクトル / 給電波形のスペクトル)
+    // 給電波形のスペクトルが最大値の floor 倍未満の周波数は除く
+    pub fn transfer(&self, p: &Probe, s: &Source, floor: f64) -> Result<Spectrum> {
+        Ok(self.probe(p)?.divide(&self.source(s, p)?, floor))
+    }
+}
+
+// 観測点の記録の時間刻み
+fn sampling(p: &Probe) -> Result<f64> {
+    let dt = p.dt();
+    if p.values.len() < 2 || p.times.len() != p.values.len() || dt <= 0.0 {
+        return Err(Error::InvalidValue{what: "probe samples (need at least 2)", value: p.values.len() as f64});
+    }
+    Ok(dt)
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct Spectrum {
+    pub freqs: Vec<f64>,
+    pub values: Vec<Complex>,
+}
+
+impl Spectrum {
+    pub fn magnitude(&self) -> Vec<f64> {
+        self.values.iter().map(|v| v.abs()).collect()
+    }
+
+    // 最大値を0dBとした振幅 (全て0なら全点 -inf)
+    pub fn db(&self) -> Vec<f64> {
+        let m = self.values.iter().fold(0.0f64, |a, v| a.max(v.abs()));
+        if m == 0.0 {
+            return vec![f64::NEG_INFINITY; self.values.len()];
+        }
+        self.values.iter().map(|v| 20.0 * (v.abs() / m).log10()).collect()
+    }
+
+    // 位相 (rad, 隣の周波数との差がπを超えないように接続)
+    pub fn phase(&self) -> Vec<f64> {
+        let mut out: Vec<f64> = Vec::with_capacity(self.values.len());
+        for v in &self.values {
+            let mut p = v.arg();
+            if let Some(&prev) = out.last() {
+                p += 2.0 * PI * ((prev - p) / (2.0 * PI)).round();
+            }
+            out.push(p);
+        }
+        out
+    }
+
+    // 群遅延 -dφ/dω (s, 中心差分)
+    pub fn group_delay(&self) -> Vec<f64> {
+        let (p, f) = (self.phase(), &self.freqs);
+        let n = p.len();
+        (0..n).map(|k| {
+            if n < 2 {
+                return 0.0;
+            }
+            let (a, b) = (k.saturating_sub(1), (k + 1).min(n - 1));
+            -(p[b] - p[a]) / (2.0 * PI * (f[b] - f[a]))
+        }).collect()
+    }
+
+    // 同じ周波数の点で割る (基準が最大値の floor 倍未満の点は除く)
+    pub fn divide(&self, reference: &Spectrum, floor: f64) -> Spectrum {
+        let m = reference.values.iter().fold(0.0f64, |a, v| a.max(v.abs()));
+        let (mut freqs, mut values) = (Vec::new(), Vec::new());
+        for ((&f, &x), &r) in self.freqs.iter().zip(&self.values).zip(&reference.values) {
+            if r.abs() > floor * m {
+                freqs.push(f);
+                values.push(x / r);
+            }
+        }
+        Spectrum{freqs, values}
+    }
+
+    // freq,re,im,magnitude,phase,group_delay のCSV
+    pub fn write_csv<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "freq,re,im,magnitude,phase,group_delay")?;
+        for (((f, v), p), g) in self.freqs.iter().zip(&self.values).zip(self.phase()).zip(self.group_delay()) {
+            writeln!(w, "{:e},{:e},{:e},{:e},{:e},{:e}", f, v.re, v.im, v.abs(), p, g)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,461 @@
+/*
+ * This software is released under the MIT License.
+ * http://opensource.org/licenses/mit-license.php
+ */
+
+// 局所細分格子 (サブグリッド)
+//
+// 親の解析モジュールの節点範囲 region を ratio:1 (奇数) に細分した解析モジュール fine (PMLなし) を重ねる.
+// 時間の境界と空間の境界を分けるため, region を親の BUFFER セルだけ広げた範囲に
+// 親と同じセル幅で dt / ratio で進める緩衝格子 buffer を置き, 親の1ステップ毎に
+//   1. 親の電界を更新 (緩衝格子の範囲を含む親の格子全体)
+//   2. buffer と fine を ratio ステップ進める. 各ステップで
+//      - buffer の外周の接線方向の電界 (Ez, 上下辺のEx, 左右辺のEy) を親の直前の2ステップの電界の
+//        時間の線形補間で与える
+//      - region の外周の接線方向の電界は buffer の値を主とし, fine の外周の値は辺に沿った線形補間 P で与える.
+//        主の値は外側の buffer の磁界と, 内側の fine の磁界を P の重みで集めた周回積分で更新する
+//        (双対セルの面積も外側と内側の和). 外周上の磁界は buffer と fine が半分ずつ持つ
+//   3. fine の電界を buffer の region の内部へ ratio x ratio セルの平均で戻し,
+//      親の内部 (緩衝格子の外周を除く) の電界を buffer の電界で置き換える
+//   4. 親の磁界を更新
+// とする. 空間の境界は P とその転置で結ぶのでエネルギーを保存し, 媒質が境界を跨いでも増幅しない.
+// 時間と空間の補間を同じ境界で行うと, 細分格子の中の親の格子で表せない周波数の成分が
+// 親の時間ステップで折り返して境界で増幅される.
+// 外周と親の媒質が一致するように, 細かい構造は region の外周から親の1セル以上離して置く.
+// 給電点, 観測点は fine に設定する (親の region 内の電界は毎ステップ置き換わる).
+
+use std::collections::HashMap;
+
+use error::{Error, Result};
+use fdtd2d::{fdtd, fdtdBuilder, Component, Material, Medium};
+use snapshot::Region;
+
+// 親と細分格子の間の緩衝格子の幅 (親のセル数)
+pub const BUFFER: usize = 2;
+
+// 外周で結ぶ電界
+const E: [Component; 3] = [Component::Ex, Component::Ey, Component::Ez];
+
+// 周回積分に掛かる磁界 (成分, 半セル単位のずれ x, y, 符号, 長さがx方向のセル幅か)
+type Neighbor = (Component, i64, i64, f64, bool);
+
+// 電界 E[k] の周回積分に掛かる磁界
+const CURL: [&[Neighbor]; 3] = [
+    &[(Component::Hz, 0, 1, 1.0, true), (Component::Hz, 0, -1, -1.0, true)],
+    &[(Component::Hz, 1, 0, -1.0, false), (Component::Hz, -1, 0, 1.0, false)],
+    &[(Component::Hy, 1, 0, 1.0, false), (Component::Hy, -1, 0, -1.0, false),
+      (Component::Hx, 0, 1, -1.0, true), (Component::Hx, 0, -1, 1.0, true)],
+];
+
+// 周回積分の項 (磁界の成分, 配列番号 x, y, 符号付きの長さ)
+type Term = (Component, usize, usize, f64);
+
+// region の外周の電界 (緩衝格子)
+struct Master {
+    k: usize,
+    x: usize,
+    y: usize,
+    // 外側の磁界の項, 外側と内側の双対セルの面積
+    terms: Vec<Term>,
+    area: f64,
+    // 重なる fine の外周の電界 (番号, P の重み)
+    slaves: Vec<(usize, f64)>,
+    // 更新係数 (setupで設定)
+    a: f64,
+    b: f64,
+}
+
+// fine の外周の電界
+struct Slave {
+    k: usize,
+    i: usize,
+    j: usize,
+    // 内側の磁界の項
+    terms: Vec<Term>,
+    // 補間する主 (番号, P の重み)
+    masters: Vec<(usize, f64)>,
+}
+
+pub struct Subgrid {
+    // 細分する親の節点範囲 [x_s, x_l) x [y_s, y_l) (両端の節点が外周)
+    pub region: Region,
+    pub ratio: usize,
+    // 細分格子 (媒質, 完全導体, 給電点, 観測点はこちらに設定する. 座標は親と同じ)
+    pub fine: fdtd,
+    // 緩衝格子 (親の節点範囲 outer, 親と同じセル幅で dt / ratio)
+    buffer: fdtd,
+    outer: Region,
+    // 親から写した完全導体 (緩衝格子, 細分格子の配列番号の矩形)
+    pec: Vec<(bool, Region)>,
+    masters: Vec<Master>,
+    slaves: Vec<Slave>,
+    // 緩衝格子の外周の電界 (成分の番号, 配列番号)
+    ring: Vec<(usize, usize, usize)>,
+    // 親の1つ前のステップの outer の範囲の電界 (外周の時間補間用)
+    old: Vec<Vec<Vec<f64>>>,
+}
+
+impl Subgrid {
+    // 親の媒質と完全導体をセル毎に写した緩衝格子と細分格子を作り, setupまで行う
+    // (fine の媒質を変えた場合は setup を呼び直してから fine に完全導体を設定する)
+    pub fn new(parent: &fdtd, region: Region, ratio: usize) -> Result<Subgrid> {
+        if ratio < 3 || ratio.is_multiple_of(2) {
+            return Err(Error::InvalidValue{what: "subgrid ratio", value: ratio as f64});
+        }
+        parent.require_uniform("subgrid on a graded mesh")?;
+        let r = region;
+        if r.x_l < r.x_s + 3 {
+            return Err(Error::ReversedRange{what: "subgrid", axis: 'x', start: r.x_s, end: r.x_l});
+        }
+        if r.y_l < r.y_s + 3 {
+            return Err(Error::ReversedRange{what: "subgrid", axis: 'y', start: r.y_s, end: r.y_l});
+        }
+        let (nx, ny) = parent.dims();
+        if r.x_l > nx || r.y_l > ny {
+            return Err(Error::OutOfBounds{what: "subgrid", x: r.x_l, y: r.y_l, nx, ny});
+        }
+        // 緩衝格子の外周は親の通常の更新 (PMLの外) で求める
+        let l = parent.lpml() + BUFFER;
+        if r.x_s <= l || r.y_s <= l || r.x_l + l >= nx || r.y_l + l >= ny {
+            return Err(Error::InPml{what: "subgrid", x: r.x_s, y: r.y_s, lpml: parent.lpml()});
+        }
+        let outer = Region{x_s: r.x_s - BUFFER, x_l: r.x_l + BUFFER, y_s: r.y_s - BUFFER, y_l: r.y_l + BUFFER};
+
+        let buffer = refined(parent, outer, 1, ratio)?;
+        let fine = refined(parent, region, ratio, ratio)?;
+        let mut pec = pec_cells(parent, outer, 1).into_iter().map(|p| (false, p)).collect::<Vec<_>>();
+        pec.extend(pec_cells(parent, region, ratio).into_iter().map(|p| (true, p)));
+        let (bx, by) = buffer.dims();
+        let ring = (0..3).flat_map(|k| boundary(k, bx, by).into_iter().map(move |(x, y)| (k, x, y))).collect();
+        let mut s = Subgrid{region, ratio, fine, buffer, outer, pec, masters: Vec::new(), slaves: Vec::new(), ring, old: Vec::new()};
+        s.couple();
+        s.setup()?;
+        Ok(s)
+    }
+
+    // 緩衝格子と細分格子の係数を作り直し, 親から写した完全導体を設定する
+    pub fn setup(&mut self) -> Result<()> {
+        self.buffer.setup();
+        self.fine.setup();
+        for &(fine, p) in &self.pec {
+            let f = if fine { &mut self.fine } else { &mut self.buffer };
+            f.pec_rect(p.x_s, p.x_l, p.y_s, p.y_l)?;
+        }
+        for m in &mut self.masters {
+            (m.a, m.b) = self.buffer.coefficients(E[m.k], m.x, m.y);
+        }
+        Ok(())
+    }
+
+    // 親の節点(x, y)に重なる細分格子の節点
+    pub fn fine_index(&self, x: usize, y: usize) -> (usize, usize) {
+        ((x - self.region.x_s) * self.ratio, (y - self.region.y_s) * self.ratio)
+    }
+
+    // 緩衝格子の範囲 (親の節点範囲)
+    pub fn outer(&self) -> Region {
+        self.outer
+    }
+
+    // region の外周の主と従属の電界, 周回積分の項, 補間の重みを作る
+    // (位置は region の左下を原点とする fine の半セル単位. 外周上の磁界, 双対セルは内側と外側で半分ずつ)
+    fn couple(&mut self) {
+        let n = self.ratio as i64;
+        let (cx, cy) = (self.region.x_l - 1 - self.region.x_s, self.region.y_l - 1 - self.region.y_s);
+        let hi = (2 * n * cx as i64, 2 * n * cy as i64);
+        let inside = |p: (i64, i64)| part(p.0, hi.0) * part(p.1, hi.1);
+        let ((dx, dy), (fx, fy)) = (self.buffer.cell_size(), self.fine.cell_size());
+        let b = BUFFER as i64;
+
+        let mut index = HashMap::new();
+        for k in 0..3 {
+            let (sx, sy) = shift(E[k]);
+            for (x, y) in boundary(k, cx + 1, cy + 1) {
+                let q = (2 * x as i64 + sx, 2 * y as i64 + sy);
+                let terms = CURL[k].iter().filter_map(|&(c, ox, oy, s, along_x)| {
+                    let h = (q.0 + ox, q.1 + oy);
+                    let w = 1.0 - inside((h.0 * n, h.1 * n));
+                    let (i, j) = ((h.0.div_euclid(2) + b) as usize, (h.1.div_euclid(2) + b) as usize);
+                    (w > 0.0).then_some((c, i, j, s * w * if along_x { dx } else { dy }))
+                }).collect();
+                index.insert((k, x, y), self.masters.len());
+                self.masters.push(Master{k, x: x + BUFFER, y: y + BUFFER, terms, slaves: Vec::new(),
+                                         area: (1.0 - inside((q.0 * n, q.1 * n))) * dx * dy, a: 0.0, b: 0.0});
+            }
+        }
+
+        let (nx, ny) = self.fine.dims();
+        for k in 0..3 {
+            let (sx, sy) = shift(E[k]);
+            for (i, j) in boundary(k, nx, ny) {
+                let p = (2 * i as i64 + sx, 2 * j as i64 + sy);
+                let terms = CURL[k].iter().filter_map(|&(c, ox, oy, s, along_x)| {
+                    let h = (p.0 + ox, p.1 + oy);
+                    let w = inside(h);
+                    (w > 0.0).then_some((c, (h.0 / 2) as usize, (h.1 / 2) as usize, s * w * if along_x { fx } else { fy }))
+                }).collect();
+                // 辺に沿った位置 u, 辺上の主の数 - 1 (節点 c, 辺 c - 1)
+                let horizontal = sy == 0 && (p.1 == 0 || p.1 == hi.1);
+                let (u, s, last) = if horizontal { (p.0, sx, cx as i64) } else { (p.1, sy, cy as i64) };
+                let u = u - s * n;
+                let last = last - s;
+                let weights = if u <= 0 {
+                    vec![(0, 1.0)]
+                } else if u >= 2 * n * last {
+                    vec![(last, 1.0)]
+                } else {
+                    let t = (u % (2 * n)) as f64 / (2 * n) as f64;
+                    vec![(u / (2 * n), 1.0 - t), (u / (2 * n) + 1, t)]
+                };
+                let id = self.slaves.len();
+                let mut masters = Vec::new();
+                for (m, w) in weights.into_iter().filter(|&(_, w)| w > 0.0) {
+                    let (x, y) = if horizontal { (m as usize, (p.1 / (2 * n)) as usize) } else { ((p.0 / (2 * n)) as usize, m as usize) };
+                    let m = index[&(k, x, y)];
+                    self.masters[m].slaves.push((id, w));
+                    self.masters[m].area += w * inside(p) * fx * fy;
+                    masters.push((m, w));
+                }
+                self.slaves.push(Slave{k, i, j, terms, masters});
+            }
+        }
+    }
+
+    // 親の電界更新の前に outer の範囲の電界を保存する
+    fn save(&mut self, parent: &fdtd) {
+        let o = self.outer;
+        self.old = E.iter().map(|&c| {
+            parent.field(c)[o.x_s..o.x_l].iter().map(|col| col[o.y_s..o.y_l].to_vec()).collect()
+        }).collect();
+    }
+
+    // 主の電界の次の値 (緩衝格子と細分格子の電界の更新の前に求める)
+    fn next_masters(&self) -> Vec<f64> {
+        let circulation = |f: &fdtd, terms: &[Term]| terms.iter().map(|&(c, i, j, w)| w * f.field(c)[i][j]).sum::<f64>();
+        let inner: Vec<f64> = self.slaves.iter().map(|s| circulation(&self.fine, &s.terms)).collect();
+        self.masters.iter().map(|m| {
+            let c = circulation(&self.buffer, &m.terms) + m.slaves.iter().map(|&(s, w)| w * inner[s]).sum::<f64>();
+            m.a * self.buffer.field(E[m.k])[m.x][m.y] + m.b * c / m.area
+        }).collect()
+    }
+
+    // 親の電界を更新した後に緩衝格子と細分格子を ratio ステップ進め, 親の内部へ戻す
+    fn advance(&mut self, parent: &mut fdtd) {
+        let n = self.ratio;
+        for k in 1..=n {
+            let theta = k as f64 / n as f64;
+            let next = self.next_masters();
+            self.buffer.step_e();
+            for (m, v) in self.masters.iter().zip(next) {
+                self.buffer.field_mut(E[m.k])[m.x][m.y] = v;
+            }
+            let o = self.outer;
+            for &(c, x, y) in &self.ring {
+                let now = parent.field(E[c])[o.x_s + x][o.y_s + y];
+                self.buffer.field_mut(E[c])[x][y] = (1.0 - theta) * self.old[c][x][y] + theta * now;
+            }
+
+            self.fine.step_e();
+            for s in &self.slaves {
+                let v = s.masters.iter().map(|&(m, w)| {
+                    let m = &self.masters[m];
+                    w * self.buffer.field(E[m.k])[m.x][m.y]
+                }).sum();
+                self.fine.field_mut(E[s.k])[s.i][s.j] = v;
+            }
+            self.buffer.step_h();
+            self.fine.step_h();
+            boundary_h(&mut self.fine);
+        }
+        restrict(&self.fine, &mut self.buffer, (BUFFER, BUFFER), n);
+        restrict(&self.buffer, parent, (self.outer.x_s, self.outer.y_s), 1);
+    }
+}
+
+// 成分の半セルのずれ
+fn shift(c: Component) -> (i64, i64) {
+    match c {
+        Component::Ex | Component::Hy => (1, 0),
+        Component::Ey | Component::Hx => (0, 1),
+        Component::Ez => (0, 0),
+        Component::Hz => (1, 1),
+    }
+}
+
+// 範囲 [0, hi] の内側にある割合 (内部 1, 端 0.5, 外 0)
+fn part(p: i64, hi: i64) -> f64 {
+    if 0 < p && p < hi {
+        1.0
+    } else if p == 0 || p == hi {
+        0.5
+    } else {
+        0.0
+    }
+}
+
+// nx x ny 節点の格子の外周にある接線方向の電界 E[k] の配列番号
+fn boundary(k: usize, nx: usize, ny: usize) -> Vec<(usize, usize)> {
+    let mut v = Vec::new();
+    for x in 0..nx {
+        for y in 0..ny {
+            let (ex, ey) = (x == 0 || x == nx - 1, y == 0 || y == ny - 1);
+            let on = match k {
+                0 => x < nx - 1 && ey,
+                1 => y < ny - 1 && ex,
+                _ => ex || ey,
+            };
+            if on {
+                v.push((x, y));
+            }
+        }
+    }
+    v
+}
+
+// 外周上の磁界 (左右辺のHx, 上下辺のHy. h_calでは更新しない) を外周の電界から更新する
+fn boundary_h(f: &mut fdtd) {
+    let (nx, ny) = f.dims();
+    let (dx, dy) = f.cell_size();
+    for x in [0, nx - 1] {
+        for y in 0..ny - 1 {
+            let (a, b) = f.coefficients(Component::Hx, x, y);
+            f.hx[x][y] = a * f.hx[x][y] - b / dy * (f.ez[x][y + 1] - f.ez[x][y]);
+        }
+    }
+    for y in [0, ny - 1] {
+        for x in 0..nx - 1 {
+            let (a, b) = f.coefficients(Component::Hy, x, y);
+            f.hy[x][y] = a * f.hy[x][y] + b / dx * (f.ez[x + 1][y] - f.ez[x][y]);
+        }
+    }
+}
+
+// 親の範囲 region を ratio:1 に細分し, 親の媒質をセル毎に写した dt = 親の dt / steps の格子 (setup前)
+fn refined(parent: &fdtd, region: Region, ratio: usize, steps: usize) -> Result<fdtd> {
+    let (dx, dy) = parent.cell_size();
+    let (cx, cy) = (region.x_l - 1 - region.x_s, region.y_l - 1 - region.y_s);
+    let mut f = fdtdBuilder::newGrid((ratio * cx + 1) as i32, (ratio * cy + 1) as i32,
+                                     dx / ratio as f64, dy / ratio as f64, 0);
+    f.dt = parent.dt / steps as f64;
+    let (x0, y0) = parent.position(region.x_s, region.y_s);
+    f.translate(x0, y0);
+    for i in 0..ratio * cx {
+        for j in 0..ratio * cy {
+            let (px, py) = (region.x_s + i / ratio, region.y_s + j / ratio);
+            let m = Medium{eps: parent.material(Material::Eps, px, py), mu: parent.material(Material::Mu, px, py),
+                           sigma_e: parent.material(Material::SigmaE, px, py), sigma_m: parent.material(Material::SigmaM, px, py)};
+            if m != Medium::default() {
+                // medium(i, i + 2, ..) はセル i の1つを設定する
+                f.medium(i, i + 2, j, j + 2, m)?;
+            }
+        }
+    }
+    Ok(f)
+}
+
+// 親の範囲 region 内の完全導体を ratio:1 に細分した格子の配列番号の矩形にする
+// (完全導体の節点, 両端が完全導体の辺, 4隅が完全導体のセル)
+fn pec_cells(parent: &fdtd, region: Region, ratio: usize) -> Vec<Region> {
+    let pec = |x: usize, y: usize| parent.material(Material::Pec, x, y) > 0.0;
+    let rect = |x: usize, y: usize, w: usize, h: usize| {
+        let (i, j) = ((x - region.x_s) * ratio, (y - region.y_s) * ratio);
+        Region{x_s: i, x_l: i + w * ratio + 1, y_s: j, y_l: j + h * ratio + 1}
+    };
+    let mut v = Vec::new();
+    for x in region.x_s..region.x_l {
+        for y in region.y_s..region.y_l {
+            if !pec(x, y) {
+                continue;
+            }
+            v.push(rect(x, y, 0, 0));
+            let (px, py) = (x + 1 < region.x_l && pec(x + 1, y), y + 1 < region.y_l && pec(x, y + 1));
+            if px {
+                v.push(rect(x, y, 1, 0));
+            }
+            if py {
+                v.push(rect(x, y, 0, 1));
+            }
+            if px && py && pec(x + 1, y + 1) {
+                v.push(rect(x, y, 1, 1));
+            }
+        }
+    }
+    v
+}
+
+// 内側の格子の電界を外側の格子の内部 (外周を除く) の同じ位置へ ratio x ratio の平均で戻す
+// (origin: 内側の格子の節点0に重なる外側の節点. ratioが奇数なので外側のEx, Eyは内側のEx, Eyに重なる)
+fn restrict(inner: &fdtd, outer: &mut fdtd, origin: (usize, usize), ratio: usize) {
+    let (nx, ny) = inner.dims();
+    let (n, h) = (ratio, ratio / 2);
+    let (x0, y0) = origin;
+    let (xl, yl) = (x0 + (nx - 1) / n, y0 + (ny - 1) / n);
+    for (k, &c) in E.iter().enumerate() {
+        let f = inner.field(c);
+        let (xr, yr, si, sj) = match k {
+            0 => (x0..xl, y0 + 1..yl, h, 0),
+            1 => (x0 + 1..xl, y0..yl, 0, h),
+            _ => (x0 + 1..xl, y0 + 1..yl, 0, 0),
+        };
+        let a = outer.field_mut(c);
+        for x in xr {
+            for y in yr.clone() {
+                let (i, j) = ((x - x0) * n + si, (y - y0) * n + sj);
+                let sum: f64 = (i - h..=i + h).map(|i| f[i][j - h..=j + h].iter().sum::<f64>()).sum();
+                a[x][y] = sum / (n * n) as f64;
+            }
+        }
+    }
+}
+
+// 親の解析モジュールと細分格子の組
+pub struct SubgridSolver {
+    pub parent: fdtd,
+    pub patches: Vec<Subgrid>,
+}
+
+impl SubgridSolver {
+    pub fn new(parent: fdtd) -> SubgridSolver {
+        SubgridSolver{parent, patches: Vec::new()}
+    }
+
+    // 細分格子の追加 (戻り値は番号). 緩衝格子の外周の1つ外側まで他の細分格子と重ならないこと
+    pub fn add(&mut self, s: Subgrid) -> Result<usize> {
+        let grow = |r: Region| (r.x_s - 1, r.x_l + 1, r.y_s - 1, r.y_l + 1);
+        let a = grow(s.outer);
+        for p in &self.patches {
+            let b = grow(p.outer);
+            if a.0 < b.1 && b.0 < a.1 && a.2 < b.3 && b.2 < a.3 {
+                return Err(Error::InvalidValue{what: "overlapping subgrid at x", value: s.region.x_s as f64});
+            }
+        }
+        if (s.fine.dt * s.ratio as f64 - self.parent.dt).abs() > 1e-9 * self.parent.dt {
+            return Err(Error::InvalidValue{what: "subgrid time step", value: s.fine.dt});
+        }
+        self.patches.push(s);
+        Ok(self.patches.len() - 1)
+    }
+
+    // 親の1ステップ (細分格子は ratio ステップ)
+    pub fn step(&mut self) {
+        for p in &mut self.patches {
+            p.save(&self.parent);
+        }
+        self.parent.step_e();
+        for p in &mut self.patches {
+            p.advance(&mut self.parent);
+        }
+        self.parent.step_h();
+    }
+
+    // 親のステップ数nだけ計算 (親のObserverが停止を求めたら終える. 戻り値は計算したステップ数)
+    pub fn run(&mut self, n: usize) -> usize {
+        for i in 0..n {
+            self.step();
+            if self.parent.stop_requested() {
+                return i + 1;
+            }
+        }
+        n
+    }
+}
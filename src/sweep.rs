@@ -0,0 +1,449 @@
+/*
+ * This software is released under the MIT License.
+ * http://opensource.org/licenses/mit-license.php
+ */
+
+// パラメータスイープ (基準のシナリオの項目を書き換えた計算をまとめて実行)
+//
+// {
+//   "base": "default.json",                // スイープファイルからの相対パス, またはシナリオのオブジェクト
+//   "mode": "grid",                         // grid: 全組み合わせ, list: i番目の値同士, lhs: ラテン超方格
+//   "samples": 20, "seed": 1,               // lhsの標本数と乱数の種
+//   "parallel": 0,                          // 同時に実行する計算の数 (0でコア数)
+//   "parameters": [{"name": "eps", "path": "materials.dielectric.eps", "values": [2, 3, 4]},
+//                  {"name": "w", "path": "geometry[0].x[1]", "range": [600, 800], "count": 5, "integer": true}],
+//   "results": [{"name": "peak", "type": "probe_peak", "probe": "p0"},
+//               {"name": "t", "type": "flux", "normal": "y", "position": 900, "range": [300, 700], "freq": 1e9},
+//               {"name": "s21", "type": "sparam", "freq": 1e10, "element": [2, 1], "part": "db",
+//                "ports": [{"name": "p1", "normal": "x", "position": 30, "range": [21, 41], "forward": true}, ...]}]
+// }
+//
+// pathはシナリオのエラー表示と同じ形式で, 書き換える項目は数値. lhsは "range" が必要
+// 計算毎に <出力先>/run_0000 等へシナリオの出力と params.json を書き, 結果を <出力先>/summary.csv にまとめる
+// sparamは計算毎にポート数だけ追加の計算をする (シナリオの給電点, 観測点, 出力は使わない)
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use decay::Decay;
+use fdtd2d::{fdtd, Source};
+use json::{self, Value};
+use monitor::FluxMonitor;
+use port::{self, Axis, Port};
+use random::Random;
+use scenario::{invalid, Obj, ScenarioError, ScenarioSpec};
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum SweepMode {
+    Grid,
+    List,
+    Lhs,
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct Parameter {
+    pub name: String,
+    pub path: String,
+    // 値の列 (valuesまたはrangeとcountから)
+    pub values: Vec<f64>,
+    // lhsの範囲
+    pub range: Option<(f64, f64)>,
+    // 整数に丸める
+    pub integer: bool,
+}
+
+// Sパラメータの値の取り出し方
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Part {
+    Abs,
+    Db,
+    Phase,
+    Re,
+    Im,
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub enum Quantity {
+    // 観測点の値の絶対値の最大
+    ProbePeak{probe: String},
+    // 線を通過する電力
+    Flux{normal: Axis, pos: usize, start: usize, end: usize, freq: f64},
+    // S[i][j] (i: 受信ポート, j: 励振ポート, 0始まり)
+    SParam{ports: Vec<Port>, pulse: Source, freq: f64, i: usize, j: usize, part: Part},
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct ResultSpec {
+    pub name: String,
+    pub quantity: Quantity,
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct SweepSpec {
+    pub base: Value,
+    pub mode: SweepMode,
+    pub samples: usize,
+    pub seed: u64,
+    pub parallel: usize,
+    pub parameters: Vec<Parameter>,
+    pub results: Vec<ResultSpec>,
+}
+
+fn axis(o: &Obj, key: &str) -> Result<Axis, ScenarioError> {
+    match o.text(key, None)?.as_str() {
+        "x" => Ok(Axis::X),
+        "y" => Ok(Axis::Y),
+        s => Err(invalid(&o.at(key), format!("unknown axis \"{}\" (x, y)", s))),
+    }
+}
+
+fn boolean(o: &Obj, key: &str, default: bool) -> Result<bool, ScenarioError> {
+    match o.get(key) {
+        None => Ok(default),
+        Some(&Value::Bool(b)) => Ok(b),
+        Some(v) => Err(invalid(&o.at(key), format!("expected boolean, found {}", v.kind()))),
+    }
+}
+
+fn numbers(o: &Obj, key: &str) -> Result<Vec<f64>, ScenarioError> {
+    o.list(key)?.iter().enumerate().map(|(k, v)| {
+        v.as_f64().ok_or_else(|| invalid(&format!("{}[{}]", o.at(key), k), format!("expected number, found {}", v.kind())))
+    }).collect()
+}
+
+impl SweepSpec {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<SweepSpec, ScenarioError> {
+        let path = path.as_ref();
+        let src = fs::read_to_string(path).map_err(|e| ScenarioError::Io(format!("{}: {}", path.display(), e)))?;
+        let v = json::parse(&src).map_err(ScenarioError::Parse)?;
+        SweepSpec::from_value(&v, path.parent().unwrap_or_else(|| Path::new("")))
+    }
+
+    // dir: baseのパスの基準
+    pub fn from_value(v: &Value, dir: &Path) -> Result<SweepSpec, ScenarioError> {
+        let root = Obj::new(v, "", &["base", "mode", "samples", "seed", "parallel", "parameters", "results"])?;
+        let base = match *root.req("base")? {
+            Value::Str(ref p) => {
+                let path = dir.join(p);
+                let src = fs::read_to_string(&path).map_err(|e| ScenarioError::Io(format!("{}: {}", path.display(), e)))?;
+                json::parse(&src).map_err(ScenarioError::Parse)?
+            }
+            ref b => b.clone(),
+        };
+        let spec = ScenarioSpec::from_value(&base).map_err(|e| match e {
+            ScenarioError::Invalid{path, message} => invalid(&format!("base.{}", path), message),
+            e => e,
+        })?;
+
+        let mode = match root.text("mode", Some("grid"))?.as_str() {
+            "grid" => SweepMode::Grid,
+            "list" => SweepMode::List,
+            "lhs" => SweepMode::Lhs,
+            s => return Err(invalid(&root.at("mode"), format!("unknown mode \"{}\" (grid, list, lhs)", s))),
+        };
+
+        let mut parameters = Vec::new();
+        for (n, e) in root.list("parameters")?.iter().enumerate() {
+            let o = Obj::new(e, &format!("parameters[{}]", n), &["name", "path", "values", "range", "count", "integer"])?;
+            let path = o.text("path", None)?;
+            match base.clone().path_mut(&path) {
+                Some(&mut Value::Num(_)) => {}
+                Some(v) => return Err(invalid(&o.at("path"), format!("\"{}\" is {}, not a number", path, v.kind()))),
+                None => return Err(invalid(&o.at("path"), format!("\"{}\" is not in the base scenario", path))),
+            }
+            let integer = boolean(&o, "integer", false)?;
+            let range = match o.get("range") {
+                None => None,
+                Some(_) => match numbers(&o, "range")?[..] {
+                    [lo, hi] if lo < hi => Some((lo, hi)),
+                    _ => return Err(invalid(&o.at("range"), "expected [min, max] with min < max".to_string())),
+                },
+            };
+            let round = |v: f64| if integer { v.round() } else { v };
+            let values = match (o.get("values"), range) {
+                (Some(_), _) => numbers(&o, "values")?.into_iter().map(round).collect(),
+                (None, Some((lo, hi))) => {
+                    let c = o.uint("count", Some(2))?.max(2);
+                    (0..c).map(|k| round(lo + (hi - lo) * k as f64 / (c - 1) as f64)).collect()
+                }
+                (None, None) => if mode == SweepMode::Lhs { Vec::new() } else {
+                    return Err(invalid(&o.at("values"), "either values or range is required".to_string()));
+                },
+            };
+            if mode == SweepMode::Lhs && range.is_none() {
+                return Err(invalid(&o.at("range"), "lhs requires a range".to_string()));
+            }
+            if mode != SweepMode::Lhs && values.is_empty() {
+                return Err(invalid(&o.at("values"), "at least one value is required".to_string()));
+            }
+            parameters.push(Parameter{name: o.text("name", Some(&path))?, path, values, range, integer});
+        }
+        if parameters.is_empty() {
+            return Err(invalid("parameters", "at least one parameter is required".to_string()));
+        }
+        if mode == SweepMode::List && parameters.iter().any(|p| p.values.len() != parameters[0].values.len()) {
+            return Err(invalid("parameters", "list mode requires the same number of values for every parameter".to_string()));
+        }
+
+        let mut results = Vec::new();
+        for (n, e) in root.list("results")?.iter().enumerate() {
+            let p = format!("results[{}]", n);
+            let o = Obj::new(e, &p, &["name", "type", "probe", "normal", "position", "range", "freq", "ports", "element", "part", "pulse"])?;
+            let name = o.text("name", Some(&format!("result{}", n)))?;
+            let quantity = match o.text("type", None)?.as_str() {
+                "probe_peak" => {
+                    let probe = o.text("probe", None)?;
+                    if !spec.probes.iter().any(|q| q.name == probe) {
+                        return Err(invalid(&o.at("probe"), format!("undefined probe \"{}\"", probe)));
+                    }
+                    Quantity::ProbePeak{probe}
+                }
+                "flux" => {
+                    let normal = axis(&o, "normal")?;
+                    let dims = spec.grid.dims();
+                    let (nl, nt) = if normal == Axis::X { (dims.0, dims.1) } else { (dims.1, dims.0) };
+                    let pos = o.uint("position", None)?;
+                    if pos == 0 || pos >= nl {
+                        return Err(invalid(&o.at("position"), format!("{} is outside the grid (1..{})", pos, nl)));
+                    }
+                    let (start, end) = o.range("range", nt)?;
+                    Quantity::Flux{normal, pos, start, end, freq: o.positive("freq", None)?}
+                }
+                "sparam" => {
+                    let freq = o.positive("freq", None)?;
+                    let mut ports = Vec::new();
+                    for (k, pv) in o.list("ports")?.iter().enumerate() {
+                        let po = Obj::new(pv, &format!("{}[{}]", o.at("ports"), k), &["name", "normal", "position", "range", "forward", "mode"])?;
+                        let normal = axis(&po, "normal")?;
+                        let dims = spec.grid.dims();
+                        let nt = if normal == Axis::X { dims.1 } else { dims.0 };
+                        let (start, end) = po.range("range", nt)?;
+                        ports.push(Port::new(&po.text("name", Some(&format!("p{}", k + 1)))?, normal, po.uint("position", None)?,
+                                             start, end, boolean(&po, "forward", true)?).mode(po.uint("mode", Some(1))?.max(1)));
+                    }
+                    if ports.is_empty() {
+                        return Err(invalid(&o.at("ports"), "at least one port is required".to_string()));
+                    }
+                    let (i, j) = o.pair("element", (1, 1))?;
+                    if i == 0 || j == 0 || i > ports.len() || j > ports.len() {
+                        return Err(invalid(&o.at("element"), format!("port numbers must be in 1..{}", ports.len())));
+                    }
+                    let pulse = match o.get("pulse") {
+                        Some(pv) => {
+                            let po = Obj::new(pv, &o.at("pulse"), &["duration", "t0"])?;
+                            let d = po.positive("duration", None)?;
+                            Source::new(0, 0).pulse(d, po.positive("t0", Some(4.0 * d))?)
+                        }
+                        None => Source::new(0, 0).pulse(2.0 / freq, 8.0 / freq),
+                    }.carrier(freq);
+                    let part = match o.text("part", Some("abs"))?.as_str() {
+                        "abs" => Part::Abs,
+                        "db" => Part::Db,
+                        "phase" => Part::Phase,
+                        "re" => Part::Re,
+                        "im" => Part::Im,
+                        s => return Err(invalid(&o.at("part"), format!("unknown part \"{}\" (abs, db, phase, re, im)", s))),
+                    };
+                    Quantity::SParam{ports, pulse, freq, i: i - 1, j: j - 1, part}
+                }
+                t => return Err(invalid(&o.at("type"), format!("unknown result type \"{}\" (probe_peak, flux, sparam)", t))),
+            };
+            results.push(ResultSpec{name, quantity});
+        }
+
+        let seed = root.uint("seed", Some(1))? as u64;
+        Ok(SweepSpec{base, mode, samples: root.uint("samples", Some(10))?.max(1), seed, parallel: root.uint("parallel", Some(0))?,
+                     parameters, results})
+    }
+
+    // 計算毎のパラメータの値
+    pub fn runs(&self) -> Vec<Vec<f64>> {
+        let p = &self.parameters;
+        match self.mode {
+            SweepMode::Grid => {
+                let mut runs = vec![Vec::new()];
+                for q in p {
+                    runs = runs.into_iter().flat_map(|r| q.values.iter().map(move |&v| {
+                        let mut r = r.clone();
+                        r.push(v);
+                        r
+                    })).collect();
+                }
+                runs
+            }
+            SweepMode::List => (0..p[0].values.len()).map(|k| p.iter().map(|q| q.values[k]).collect()).collect(),
+            SweepMode::Lhs => {
+                // パラメータ毎に範囲をsamples等分し, 各区間から1点ずつを無作為な組み合わせで取る
+                let n = self.samples;
+                let mut rng = Random::new(self.seed);
+                let mut runs = vec![Vec::with_capacity(p.len()); n];
+                for q in p {
+                    let (lo, hi) = q.range.unwrap_or((0.0, 1.0));
+                    let mut perm: Vec<usize> = (0..n).collect();
+                    for k in (1..n).rev() {
+                        perm.swap(k, rng.below(k + 1));
+                    }
+                    for (r, &k) in runs.iter_mut().zip(&perm) {
+                        let v = lo + (hi - lo) * (k as f64 + rng.uniform()) / n as f64;
+                        r.push(if q.integer { v.round() } else { v });
+                    }
+                }
+                runs
+            }
+        }
+    }
+
+    // パラメータの値valuesを代入したシナリオ
+    pub fn scenario(&self, values: &[f64]) -> Result<ScenarioSpec, ScenarioError> {
+        let mut v = self.base.clone();
+        for (p, &x) in self.parameters.iter().zip(values) {
+            match v.path_mut(&p.path) {
+                Some(e) => *e = Value::Num(x),
+                None => return Err(invalid(&p.path, "not in the base scenario".to_string())),
+            }
+        }
+        ScenarioSpec::from_value(&v)
+    }
+
+    // 全ての計算を実行し, out_dir/summary.csv を書く (threads: 各計算の電磁界計算のスレッド数)
+    pub fn run<P: AsRef<Path>>(&self, out_dir: P, threads: usize) -> io::Result<SweepSummary> {
+        let out = out_dir.as_ref().to_path_buf();
+        fs::create_dir_all(&out)?;
+        let runs = self.runs();
+        let parallel = match self.parallel {
+            0 => thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            n => n,
+        }.min(runs.len()).max(1);
+        log_info!("sweep: {} runs, {} in parallel", runs.len(), parallel);
+
+        let next = AtomicUsize::new(0);
+        let done = AtomicUsize::new(0);
+        let rows: Mutex<Vec<Option<SweepRow>>> = Mutex::new(vec![None; runs.len()]);
+        thread::scope(|s| {
+            for _ in 0..parallel {
+                s.spawn(|| loop {
+                    let k = next.fetch_add(1, Ordering::SeqCst);
+                    if k >= runs.len() {
+                        break;
+                    }
+                    let dir = out.join(format!("run_{:04}", k));
+                    let row = match self.run_one(&runs[k], &dir, threads) {
+                        Ok(results) => SweepRow{run: k, values: runs[k].clone(), results, error: None},
+                        Err(e) => {
+                            log_warn!("run {}: {}", k, e);
+                            SweepRow{run: k, values: runs[k].clone(), results: vec![f64::NAN; self.results.len()], error: Some(e)}
+                        }
+                    };
+                    let d = done.fetch_add(1, Ordering::SeqCst) + 1;
+                    log_info!("run {} finished ({}/{})", k, d, runs.len());
+                    if let Ok(mut r) = rows.lock() {
+                        r[k] = Some(row);
+                    }
+                });
+            }
+        });
+
+        let rows = rows.into_inner().unwrap_or_default().into_iter().flatten().collect();
+        let summary = SweepSummary{parameters: self.parameters.iter().map(|p| p.name.clone()).collect(),
+                                   results: self.results.iter().map(|r| r.name.clone()).collect(), rows};
+        summary.write_csv(&mut io::BufWriter::new(fs::File::create(out.join("summary.csv"))?))?;
+        Ok(summary)
+    }
+
+    fn run_one(&self, values: &[f64], dir: &Path, threads: usize) -> Result<Vec<f64>, String> {
+        let spec = self.scenario(values).map_err(|e| e.to_string())?;
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        let params = Value::Object(self.parameters.iter().zip(values).map(|(p, &v)| (p.name.clone(), Value::Num(v))).collect());
+        fs::write(dir.join("params.json"), params.to_json() + "\n").map_err(|e| e.to_string())?;
+
+        let mut sc = spec.build(dir).map_err(|e| e.to_string())?;
+        sc.solver.set_threads(threads);
+        let mut fluxes = Vec::new();
+        for r in &self.results {
+            if let Quantity::Flux{normal, pos, start, end, freq} = r.quantity {
+                fluxes.push(FluxMonitor::new(&mut sc.solver, normal, pos, start, end, &[freq]).map_err(|e| e.to_string())?);
+            }
+        }
+        sc.run().map_err(|e| e.to_string())?;
+
+        let mut fluxes = fluxes.into_iter();
+        let mut out = Vec::with_capacity(self.results.len());
+        for r in &self.results {
+            out.push(match r.quantity {
+                Quantity::ProbePeak{ref probe} => sc.probe(probe).map_or(f64::NAN, |p| p.values.iter().fold(0.0f64, |a, v| a.max(v.abs()))),
+                Quantity::Flux{..} => fluxes.next().map_or(f64::NAN, |m| m.flux(&sc.solver)[0]),
+                Quantity::SParam{ref ports, ref pulse, freq, i, j, part} => {
+                    let sp = sparam_solver(&spec, threads, ports, pulse, freq).map_err(|e| e.to_string())?;
+                    let s = sp.s[0][i][j];
+                    match part {
+                        Part::Abs => s.abs(),
+                        Part::Db => 20.0 * s.abs().log10(),
+                        Part::Phase => s.arg(),
+                        Part::Re => s.re,
+                        Part::Im => s.im,
+                    }
+                }
+            });
+        }
+        Ok(out)
+    }
+}
+
+// シナリオの媒質と完全導体だけを使ったSパラメータの計算
+fn sparam_solver(spec: &ScenarioSpec, threads: usize, ports: &[Port], pulse: &Source, freq: f64) -> ::error::Result<port::SParameters> {
+    let mut s = spec.clone();
+    s.sources.clear();
+    s.probes.clear();
+    s.monitors.clear();
+    s.outputs.clear();
+    // 観測点を使わないので減衰の判定はエネルギーで行う
+    let termination = s.termination.take().map(|d| Decay::energy(d.threshold_db, d.max_steps).window(d.window).interval(d.interval));
+    let steps = s.steps;
+    port::extract(ports, &[freq], pulse, || {
+        let mut f = s.build("")?.solver;
+        f.set_threads(threads);
+        Ok(f)
+    }, |f: &mut fdtd| {
+        match termination {
+            Some(ref d) => { f.run_decay(&mut d.clone()); }
+            None => { f.run(steps); }
+        }
+    })
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct SweepRow {
+    pub run: usize,
+    pub values: Vec<f64>,
+    pub results: Vec<f64>,
+    // 失敗した計算のエラー (結果はNaN)
+    pub error: Option<String>,
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct SweepSummary {
+    pub parameters: Vec<String>,
+    pub results: Vec<String>,
+    pub rows: Vec<SweepRow>,
+}
+
+impl SweepSummary {
+    // run,<パラメータ>,<結果>,status のCSV
+    pub fn write_csv<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let head: Vec<&str> = self.parameters.iter().chain(&self.results).map(|s| s.as_str()).collect();
+        writeln!(w, "run,{},status", head.join(","))?;
+        for r in &self.rows {
+            let v: Vec<String> = r.values.iter().chain(&r.results).map(|v| format!("{:e}", v)).collect();
+            let status = match r.error {
+                Some(ref e) => format!("\"{}\"", e.replace('"', "'")),
+                None => "ok".to_string(),
+            };
+            writeln!(w, "{},{},{}", r.run, v.join(","), status)?;
+        }
+        Ok(())
+    }
+}
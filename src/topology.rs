@@ -0,0 +1,339 @@
+/*
+ * This software is released under the MIT License.
+ * http://opensource.org/licenses/mit-license.php
+ */
+
+// 密度法によるトポロジー最適化 (設計領域の比誘電率, TE)
+//
+// 設計変数 ρ (セル毎, 0..1) → 円錐フィルタ (半径 radius セル) ρ~ → tanh射影 ρ^ → ε = ε_min + ρ^ (ε_max - ε_min)
+//   ρ^ = (tanh(βη) + tanh(β(ρ~ - η))) / (tanh(βη) + tanh(β(1 - η)))
+// 感度は adjoint の dF/dε を射影, フィルタの順に逆にたどって dF/dρ にする
+// 更新は上下限だけのMMA (Svanberg) で, 変数毎に分離した部分問題を解く. βは continuation 回毎に2倍にする (beta_maxまで)
+// 目的関数は最初の値で規格化して更新に使う
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use adjoint::AdjointSolver;
+use error::{Error, Result};
+use fdtd2d::{fdtd, Medium, Source};
+use snapshot::Region;
+
+// 比誘電率 eps[x - x_s][y - y_s] を設計領域のセル (material() と同じ番号) に設定する (setupの前に呼ぶ)
+pub fn apply(f: &mut fdtd, design: Region, eps: &[Vec<f64>]) -> Result<()> {
+    for (i, col) in eps.iter().enumerate() {
+        for (j, &e) in col.iter().enumerate() {
+            let (x, y) = (design.x_s + i, design.y_s + j);
+            // mediumは x_s+1..x_l の配列 (セル x_s..x_l-1) に設定する
+            f.medium(x, x + 2, y, y + 2, Medium{eps: e, ..Medium::default()})?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct TopologyOptimizer {
+    // 目的関数, 周波数, 設計領域
+    pub adjoint: AdjointSolver,
+    pub eps_min: f64,
+    pub eps_max: f64,
+    // フィルタの半径 (セル, 1以下でフィルタなし)
+    pub radius: f64,
+    // 射影の初期の急峻さと閾値
+    pub beta: f64,
+    pub eta: f64,
+    // βを2倍にする間隔 (0で変えない) と上限
+    pub continuation: usize,
+    pub beta_max: f64,
+    pub iterations: usize,
+    // 1回の更新で変える密度の上限
+    pub move_limit: f64,
+    // false で最小化
+    pub maximize: bool,
+}
+
+impl TopologyOptimizer {
+    pub fn new(adjoint: AdjointSolver, eps_min: f64, eps_max: f64) -> TopologyOptimizer {
+        TopologyOptimizer{adjoint, eps_min, eps_max, radius: 3.0, beta: 1.0, eta: 0.5, continuation: 10, beta_max: 64.0,
+                          iterations: 50, move_limit: 0.2, maximize: true}
+    }
+
+    pub fn filter(mut self, radius: f64) -> TopologyOptimizer {
+        self.radius = radius;
+        self
+    }
+
+    pub fn projection(mut self, beta: f64, eta: f64) -> TopologyOptimizer {
+        self.beta = beta;
+        self.eta = eta;
+        self
+    }
+
+    pub fn continuation(mut self, every: usize, beta_max: f64) -> TopologyOptimizer {
+        self.continuation = every;
+        self.beta_max = beta_max;
+        self
+    }
+
+    pub fn iterations(mut self, n: usize) -> TopologyOptimizer {
+        self.iterations = n;
+        self
+    }
+
+    pub fn move_limit(mut self, m: f64) -> TopologyOptimizer {
+        self.move_limit = m;
+        self
+    }
+
+    pub fn minimize(mut self) -> TopologyOptimizer {
+        self.maximize = false;
+        self
+    }
+
+    // 初期密度 rho から最適化する. build は設計領域の比誘電率を受け取って解析モジュール (給電点なし) を生成し,
+    // run で計算する (adjoint::AdjointSolver::solve と同じ). out_dir を指定すると反復毎に
+    // design_0000.csv (x,y,rho,filtered,projected,eps,gradient) と history.csv を書く
+    pub fn optimize<B, R>(&self, rho: &[Vec<f64>], sources: &[Source], mut build: B, mut run: R, out_dir: Option<&Path>) -> Result<Design>
+        where B: FnMut(&[Vec<f64>]) -> Result<fdtd>, R: FnMut(&mut fdtd)
+    {
+        let r = self.adjoint.design;
+        if r.x_s >= r.x_l {
+            return Err(Error::ReversedRange{what: "design region", axis: 'x', start: r.x_s, end: r.x_l});
+        }
+        if r.y_s >= r.y_l {
+            return Err(Error::ReversedRange{what: "design region", axis: 'y', start: r.y_s, end: r.y_l});
+        }
+        let (w, h) = (r.x_l - r.x_s, r.y_l - r.y_s);
+        // 範囲外は0.5で補う
+        let mut x = vec![0.5; w * h];
+        for (i, col) in rho.iter().enumerate().take(w) {
+            for (j, &v) in col.iter().enumerate().take(h) {
+                x[i * h + j] = v.clamp(0.0, 1.0);
+            }
+        }
+        let filter = Filter::new(w, h, self.radius);
+        let mut mma = Mma::new(x.len());
+        let mut history: Vec<Iteration> = Vec::with_capacity(self.iterations + 1);
+        let dir = out_dir.map(|d| d.to_path_buf());
+        if let Some(ref d) = dir {
+            fs::create_dir_all(d)?;
+        }
+
+        // 射影後の密度 → 比誘電率 [x - x_s][y - y_s]
+        let eps_of = |projected: &[f64]| -> Vec<Vec<f64>> {
+            (0..w).map(|i| (0..h).map(|j| self.eps_min + projected[i * h + j] * (self.eps_max - self.eps_min)).collect()).collect()
+        };
+        // 密度xの評価 (目的関数, 密度に対する感度, 射影後の密度)
+        let mut evaluate = |it: usize, x: &[f64], beta: f64, change: f64| -> Result<(f64, Vec<f64>, Vec<f64>)> {
+            let filtered = filter.apply(x);
+            let projected: Vec<f64> = filtered.iter().map(|&v| project(v, beta, self.eta)).collect();
+            let eps = eps_of(&projected);
+            let s = self.adjoint.solve(sources, || build(&eps), &mut run)?;
+            let grad = self.sensitivity(&filter, &filtered, beta, &s.gradient);
+
+            let grayness = projected.iter().map(|&p| 4.0 * p * (1.0 - p)).sum::<f64>() / projected.len() as f64;
+            log_info!("topology: iteration {} objective {:e} beta {} grayness {:.3} change {:.3}", it, s.value, beta, grayness, change);
+            history.push(Iteration{iteration: it, beta, value: s.value, grayness, change});
+            if let Some(ref d) = dir {
+                self.write_design(&d.join(format!("design_{:04}.csv", it)), x, &filtered, &projected, &grad)?;
+                let mut f = io::BufWriter::new(fs::File::create(d.join("history.csv"))?);
+                write_history(&mut f, &history)?;
+            }
+            Ok((s.value, grad, projected))
+        };
+        // continuation反復毎に射影を2倍に鋭くする
+        let continuation = |it: usize, beta: f64| {
+            if it > 0 && self.continuation > 0 && it.is_multiple_of(self.continuation) { (2.0 * beta).min(self.beta_max) } else { beta }
+        };
+
+        let mut beta = self.beta;
+        let mut scale = 0.0;
+        let mut change = 0.0;
+        for it in 0..self.iterations {
+            beta = continuation(it, beta);
+            let (value, grad, _) = evaluate(it, &x, beta, change)?;
+            // 最初の目的関数の値で規格化し, 最小化の問題にする
+            if scale == 0.0 {
+                scale = if value != 0.0 { 1.0 / value.abs() } else { 1.0 };
+            }
+            let sign = if self.maximize { -scale } else { scale };
+            let df: Vec<f64> = grad.iter().map(|g| sign * g).collect();
+            let last = x.clone();
+            mma.update(&mut x, &df, self.move_limit);
+            change = last.iter().zip(&x).fold(0.0f64, |a, (p, q)| a.max((p - q).abs()));
+        }
+        // 最後の密度の評価
+        beta = continuation(self.iterations, beta);
+        let (_, _, projected) = evaluate(self.iterations, &x, beta, change)?;
+        Ok(Design{region: r, rho: to_columns(&x, h), projected: to_columns(&projected, h), eps: eps_of(&projected), history})
+    }
+
+    // dF/dε [x - x_s][y - y_s] → dF/dρ^ → dF/dρ~ → dF/dρ (filtered: フィルタ後の密度)
+    pub fn sensitivity(&self, filter: &Filter, filtered: &[f64], beta: f64, gradient: &[Vec<f64>]) -> Vec<f64> {
+        let h = self.adjoint.design.y_l - self.adjoint.design.y_s;
+        let dproj: Vec<f64> = (0..filtered.len()).map(|k| {
+            gradient[k / h][k % h] * (self.eps_max - self.eps_min) * project_derivative(filtered[k], beta, self.eta)
+        }).collect();
+        filter.transpose(&dproj)
+    }
+
+    fn write_design(&self, path: &PathBuf, x: &[f64], filtered: &[f64], projected: &[f64], grad: &[f64]) -> io::Result<()> {
+        let r = self.adjoint.design;
+        let h = r.y_l - r.y_s;
+        let mut w = io::BufWriter::new(fs::File::create(path)?);
+        writeln!(w, "x,y,rho,filtered,projected,eps,gradient")?;
+        for k in 0..x.len() {
+            let eps = self.eps_min + projected[k] * (self.eps_max - self.eps_min);
+            writeln!(w, "{},{},{:e},{:e},{:e},{:e},{:e}", r.x_s + k / h, r.y_s + k % h, x[k], filtered[k], projected[k], eps, grad[k])?;
+        }
+        Ok(())
+    }
+}
+
+fn to_columns(v: &[f64], h: usize) -> Vec<Vec<f64>> {
+    v.chunks(h).map(|c| c.to_vec()).collect()
+}
+
+// tanh射影
+pub fn project(v: f64, beta: f64, eta: f64) -> f64 {
+    let d = (beta * eta).tanh() + (beta * (1.0 - eta)).tanh();
+    ((beta * eta).tanh() + (beta * (v - eta)).tanh()) / d
+}
+
+pub fn project_derivative(v: f64, beta: f64, eta: f64) -> f64 {
+    let d = (beta * eta).tanh() + (beta * (1.0 - eta)).tanh();
+    let t = (beta * (v - eta)).tanh();
+    beta * (1.0 - t * t) / d
+}
+
+// 円錐フィルタ ρ~_i = Σ w_ij ρ_j / Σ w_ij, w_ij = max(0, radius - |i - j|) (領域外は除く)
+// 密度は w x h セルの x[i * h + j]
+pub struct Filter {
+    // セル毎の (近傍のセル, 規格化した重み)
+    weights: Vec<Vec<(usize, f64)>>,
+}
+
+impl Filter {
+    pub fn new(w: usize, h: usize, radius: f64) -> Filter {
+        let n = radius.ceil().max(0.0) as isize;
+        let weights = (0..w * h).map(|k| {
+            let (i, j) = ((k / h) as isize, (k % h) as isize);
+            let mut row = Vec::new();
+            for a in (i - n).max(0)..(i + n + 1).min(w as isize) {
+                for b in (j - n).max(0)..(j + n + 1).min(h as isize) {
+                    let d = (((a - i) * (a - i) + (b - j) * (b - j)) as f64).sqrt();
+                    let c = a as usize * h + b as usize;
+                    if d == 0.0 {
+                        row.push((c, radius.max(1.0)));
+                    } else if d < radius {
+                        row.push((c, radius - d));
+                    }
+                }
+            }
+            let sum: f64 = row.iter().map(|p| p.1).sum();
+            row.into_iter().map(|(c, v)| (c, v / sum)).collect()
+        }).collect();
+        Filter{weights}
+    }
+
+    pub fn apply(&self, x: &[f64]) -> Vec<f64> {
+        self.weights.iter().map(|row| row.iter().map(|&(c, v)| v * x[c]).sum()).collect()
+    }
+
+    // 感度の逆伝播 (転置)
+    pub fn transpose(&self, g: &[f64]) -> Vec<f64> {
+        let mut out = vec![0.0; g.len()];
+        for (row, &gi) in self.weights.iter().zip(g) {
+            for &(c, v) in row {
+                out[c] += v * gi;
+            }
+        }
+        out
+    }
+}
+
+// 上下限 [0, 1] だけのMMA (制約なしなので部分問題は変数毎に解ける)
+pub struct Mma {
+    iter: usize,
+    low: Vec<f64>,
+    upp: Vec<f64>,
+    old1: Vec<f64>,
+    old2: Vec<f64>,
+}
+
+impl Mma {
+    pub fn new(n: usize) -> Mma {
+        Mma{iter: 0, low: vec![0.0; n], upp: vec![1.0; n], old1: vec![0.0; n], old2: vec![0.0; n]}
+    }
+
+    // 最小化の1ステップ (df: 目的関数の勾配)
+    pub fn update(&mut self, x: &mut [f64], df: &[f64], move_limit: f64) {
+        const ASYINIT: f64 = 0.5;
+        const RAA0: f64 = 1e-5;
+        self.iter += 1;
+        for k in 0..x.len() {
+            let xk = x[k];
+            // 漸近線 (振動したら狭め, 同じ向きに進んだら広げる)
+            if self.iter <= 2 {
+                self.low[k] = xk - ASYINIT;
+                self.upp[k] = xk + ASYINIT;
+            } else {
+                let z = (xk - self.old1[k]) * (self.old1[k] - self.old2[k]);
+                let g = if z < 0.0 { 0.7 } else if z > 0.0 { 1.2 } else { 1.0 };
+                self.low[k] = (xk - g * (self.old1[k] - self.low[k])).max(xk - 10.0).min(xk - 0.01);
+                self.upp[k] = (xk + g * (self.upp[k] - self.old1[k])).max(xk + 0.01).min(xk + 10.0);
+            }
+            let (l, u) = (self.low[k], self.upp[k]);
+            let alpha = 0.0f64.max(l + 0.1 * (xk - l)).max(xk - move_limit);
+            let beta = 1.0f64.min(u - 0.1 * (u - xk)).min(xk + move_limit);
+
+            // f ≈ p / (U - x) + q / (x - L) の最小
+            let d = df[k];
+            let p = (u - xk).powi(2) * (d.max(0.0) + 0.001 * d.abs() + RAA0);
+            let q = (xk - l).powi(2) * ((-d).max(0.0) + 0.001 * d.abs() + RAA0);
+            let (sp, sq) = (p.sqrt(), q.sqrt());
+            let xn = (sp * l + sq * u) / (sp + sq);
+
+            self.old2[k] = self.old1[k];
+            self.old1[k] = xk;
+            x[k] = xn.max(alpha).min(beta);
+        }
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Iteration {
+    pub iteration: usize,
+    pub beta: f64,
+    pub value: f64,
+    // 中間の密度の割合 (4ρ^(1-ρ^) の平均, 0で0/1のみ)
+    pub grayness: f64,
+    // 前の反復からの密度の変化の最大
+    pub change: f64,
+}
+
+fn write_history<W: Write>(w: &mut W, history: &[Iteration]) -> io::Result<()> {
+    writeln!(w, "iteration,beta,objective,grayness,change")?;
+    for h in history {
+        writeln!(w, "{},{},{:e},{:e},{:e}", h.iteration, h.beta, h.value, h.grayness, h.change)?;
+    }
+    Ok(())
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct Design {
+    pub region: Region,
+    // 設計変数, 射影後の密度, 比誘電率 [x - x_s][y - y_s]
+    pub rho: Vec<Vec<f64>>,
+    pub projected: Vec<Vec<f64>>,
+    pub eps: Vec<Vec<f64>>,
+    pub history: Vec<Iteration>,
+}
+
+impl Design {
+    // iteration,beta,objective,grayness,change のCSV
+    pub fn write_history<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_history(w, &self.history)
+    }
+}
@@ -0,0 +1,205 @@
+/*
+ * This software is released under the MIT License.
+ * http://opensource.org/licenses/mit-license.php
+ */
+
+// VTK XML RectilinearGrid (.vtr) と時系列コレクション (.pvd) の出力
+// 不均一メッシュに対応するため節点の座標を軸毎に格納する
+// ParaViewで.pvdを開くとアニメーションとして表示できる
+//
+// 場の成分と完全導体マスク(節点で定義)は点データ, epsd, mud, sgmed, sgmmd はセル毎の値なのでセルデータとする.
+// 出力セルの値は左下の出力点が属するYeeセルのもの (間引き無しなら各Yeeセルそのもの)
+
+use std::fs::File;
+use std::io::{BufWriter, Result, Write};
+use std::path::{Path, PathBuf};
+
+use binary::Dtype;
+use fdtd2d::{fdtd, Material};
+use snapshot::{LineEnding, Snapshot, SnapshotBackend};
+
+// 全媒質定数
+pub const ALL_MATERIALS: [Material; 5] = [Material::Eps, Material::Mu, Material::SigmaE, Material::SigmaM, Material::Pec];
+
+// .vtr出力 (場の成分と完全導体マスクを点データ, それ以外の媒質定数をセルデータとして格納)
+pub struct VtkBackend {
+    pub dtype: Dtype,
+    // 併せて出力する媒質定数
+    pub materials: Vec<Material>,
+    // .pvdのファイル名 (拡張子なし)
+    name: String,
+    dir: Option<PathBuf>,
+    frames: Vec<(f64, String)>,
+}
+
+impl VtkBackend {
+    // .pvdは "name.pvd" として各フレームと同じディレクトリに出力
+    pub fn new(name: &str) -> VtkBackend {
+        VtkBackend{dtype: Dtype::F32, materials: ALL_MATERIALS.to_vec(), name: name.to_string(), dir: None, frames: Vec::new()}
+    }
+
+    pub fn dtype(mut self, dtype: Dtype) -> VtkBackend {
+        self.dtype = dtype;
+        self
+    }
+
+    pub fn materials(mut self, m: &[Material]) -> VtkBackend {
+        self.materials = m.to_vec();
+        self
+    }
+}
+
+impl SnapshotBackend for VtkBackend {
+    fn extension(&self) -> &str {
+        "vtr"
+    }
+
+    fn write(&mut self, path: &Path, snap: &Snapshot, _eol: LineEnding) -> Result<()> {
+        let (nx, ny) = (snap.xs.len(), snap.ys.len());
+        let mut points: Vec<(&str, &[f64])> = Vec::new();
+        for (c, v) in &snap.components {
+            points.push((c.name(), v));
+        }
+        let mut sampled = Vec::new();
+        for (m, v) in &snap.materials {
+            if is_cell_data(*m) {
+                sampled.push((m.name(), lower_left(v, nx, ny)));
+            } else {
+                points.push((m.name(), v));
+            }
+        }
+        let cells: Vec<(&str, &[f64])> = sampled.iter().map(|(n, v)| (*n, &v[..])).collect();
+        write_vtr(path, &snap.xs, &snap.ys, &points, &cells, self.dtype)?;
+
+        if self.dir.is_none() {
+            self.dir = Some(path.parent().map(|p| p.to_path_buf()).unwrap_or_default());
+        }
+        let file = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        self.frames.push((snap.time, file));
+        Ok(())
+    }
+
+    fn materials(&self) -> Vec<Material> {
+        self.materials.clone()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        match self.dir {
+            Some(ref d) => write_pvd(d.join(format!("{}.pvd", self.name)), &self.frames),
+            None => Ok(()),
+        }
+    }
+}
+
+// 解析領域全体の媒質定数を出力 (PMLを含む全セル)
+pub fn write_geometry<P: AsRef<Path>>(path: P, f: &fdtd, dtype: Dtype) -> Result<()> {
+    let (nx, ny) = f.dims();
+    let maps: Vec<(Material, Vec<f64>)> = ALL_MATERIALS.iter().map(|&m| {
+        // セルデータはセル(x, y)の値, 点データは節点(x, y)の値
+        let (mx, my) = if is_cell_data(m) { (nx - 1, ny - 1) } else { (nx, ny) };
+        let mut v = Vec::with_capacity(mx * my);
+        for y in 0..my {
+            for x in 0..mx {
+                v.push(f.material(m, x, y));
+            }
+        }
+        (m, v)
+    }).collect();
+    let points: Vec<(&str, &[f64])> = maps.iter().filter(|(m, _)| !is_cell_data(*m)).map(|(m, v)| (m.name(), &v[..])).collect();
+    let cells: Vec<(&str, &[f64])> = maps.iter().filter(|(m, _)| is_cell_data(*m)).map(|(m, v)| (m.name(), &v[..])).collect();
+    let xs: Vec<f64> = (0..nx).map(|x| f.position(x, 0).0).collect();
+    let ys: Vec<f64> = (0..ny).map(|y| f.position(0, y).1).collect();
+    write_vtr(path, &xs, &ys, &points, &cells, dtype)
+}
+
+// RectilinearGrid 1枚 (xs, ys: 節点の座標, データはx方向が最も速く変化する並び)
+// points は xs.len() x ys.len() 個, cells は (xs.len()-1) x (ys.len()-1) 個
+pub fn write_vtr<P: AsRef<Path>>(path: P, xs: &[f64], ys: &[f64], points: &[(&str, &[f64])], cells: &[(&str, &[f64])],
+                                 dtype: Dtype) -> Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    let extent = format!("0 {} 0 {} 0 0", xs.len().saturating_sub(1), ys.len().saturating_sub(1));
+
+    writeln!(w, "<?xml version=\"1.0\"?>")?;
+    writeln!(w, "<VTKFile type=\"RectilinearGrid\" version=\"1.0\" byte_order=\"LittleEndian\" header_type=\"UInt64\">")?;
+    writeln!(w, "  <RectilinearGrid WholeExtent=\"{}\">", extent)?;
+    writeln!(w, "    <Piece Extent=\"{}\">", extent)?;
+    writeln!(w, "      <PointData>")?;
+    for &(name, v) in points {
+        data_array(&mut w, name, v, dtype)?;
+    }
+    writeln!(w, "      </PointData>")?;
+    writeln!(w, "      <CellData>")?;
+    for &(name, v) in cells {
+        data_array(&mut w, name, v, dtype)?;
+    }
+    writeln!(w, "      </CellData>")?;
+    writeln!(w, "      <Coordinates>")?;
+    data_array(&mut w, "x", xs, dtype)?;
+    data_array(&mut w, "y", ys, dtype)?;
+    data_array(&mut w, "z", &[0.0], dtype)?;
+    writeln!(w, "      </Coordinates>")?;
+    writeln!(w, "    </Piece>")?;
+    writeln!(w, "  </RectilinearGrid>")?;
+    writeln!(w, "</VTKFile>")?;
+    w.flush()
+}
+
+// セル毎に定義される媒質定数か (完全導体マスクは節点で定義)
+fn is_cell_data(m: Material) -> bool {
+    m != Material::Pec
+}
+
+// 点で標本化した nx x ny 個の値から, 各セルの左下の値を取り出す
+fn lower_left(v: &[f64], nx: usize, ny: usize) -> Vec<f64> {
+    let mut c = Vec::with_capacity(nx.saturating_sub(1) * ny.saturating_sub(1));
+    for j in 0..ny.saturating_sub(1) {
+        c.extend_from_slice(&v[j * nx..j * nx + nx - 1]);
+    }
+    c
+}
+
+// binary形式のDataArray (u64 バイト数 + データ をbase64で符号化)
+fn data_array<W: Write>(w: &mut W, name: &str, v: &[f64], dtype: Dtype) -> Result<()> {
+    let (ty, size) = match dtype {
+        Dtype::F32 => ("Float32", 4),
+        Dtype::F64 => ("Float64", 8),
+    };
+    let mut b = Vec::with_capacity(8 + v.len() * size);
+    b.extend_from_slice(&((v.len() * size) as u64).to_le_bytes());
+    for &x in v {
+        match dtype {
+            Dtype::F32 => b.extend_from_slice(&(x as f32).to_le_bytes()),
+            Dtype::F64 => b.extend_from_slice(&x.to_le_bytes()),
+        }
+    }
+    writeln!(w, "        <DataArray type=\"{}\" Name=\"{}\" format=\"binary\">", ty, name)?;
+    writeln!(w, "          {}", base64(&b))?;
+    writeln!(w, "        </DataArray>")
+}
+
+// 時系列コレクション
+pub fn write_pvd<P: AsRef<Path>>(path: P, frames: &[(f64, String)]) -> Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    writeln!(w, "<?xml version=\"1.0\"?>")?;
+    writeln!(w, "<VTKFile type=\"Collection\" version=\"1.0\" byte_order=\"LittleEndian\">")?;
+    writeln!(w, "  <Collection>")?;
+    for (t, file) in frames {
+        writeln!(w, "    <DataSet timestep=\"{:e}\" group=\"\" part=\"0\" file=\"{}\"/>", t, file)?;
+    }
+    writeln!(w, "  </Collection>")?;
+    writeln!(w, "</VTKFile>")?;
+    w.flush()
+}
+
+fn base64(data: &[u8]) -> String {
+    const T: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut s = String::with_capacity(data.len().div_ceil(3) * 4);
+    for c in data.chunks(3) {
+        let n = (c[0] as u32) << 16 | (*c.get(1).unwrap_or(&0) as u32) << 8 | *c.get(2).unwrap_or(&0) as u32;
+        s.push(T[(n >> 18) as usize & 63] as char);
+        s.push(T[(n >> 12) as usize & 63] as char);
+        s.push(if c.len() > 1 { T[(n >> 6) as usize & 63] as char } else { '=' });
+        s.push(if c.len() > 2 { T[n as usize & 63] as char } else { '=' });
+    }
+    s
+}
@@ -0,0 +1,62 @@
+extern crate Rfdtd;
+
+use Rfdtd::adjoint::{AdjointSolver, Objective};
+use Rfdtd::error::Result;
+use Rfdtd::fdtd2d::*;
+use Rfdtd::port::Axis;
+use Rfdtd::snapshot::Region;
+
+const FREQ: f64 = 3e9;
+const DESIGN: Region = Region{x_s: 44, x_l: 50, y_s: 46, y_l: 52};
+
+// 設計領域の比誘電率 eps[x - x_s][y - y_s] の解析モジュール (給電点なし, medium(x, x + 2, ..) でセル x の1つになる)
+fn model(eps: &[Vec<f64>]) -> Result<fdtd> {
+    // PMLが薄いと反射と分離成分の非相反性で随伴計算の誤差が大きくなる
+    let mut f = fdtdBuilder::newGrid(98, 98, 0.005, 0.005, 24);
+    f.medium(54, 60, 34, 64, Medium{eps: 3.0, ..Medium::default()})?;
+    for (i, col) in eps.iter().enumerate() {
+        for (j, &e) in col.iter().enumerate() {
+            let (x, y) = (DESIGN.x_s + i, DESIGN.y_s + j);
+            f.medium(x, x + 2, y, y + 2, Medium{eps: e, ..Medium::default()})?;
+        }
+    }
+    f.setup();
+    Ok(f)
+}
+
+fn design() -> Vec<Vec<f64>> {
+    (0..6).map(|i| (0..6).map(|j| 2.0 + 0.1 * ((i * 7 + j * 3) % 5) as f64).collect()).collect()
+}
+
+// 随伴法の感度と中心差分の比較
+fn check(objective: Objective) {
+    let solver = AdjointSolver::new(objective, FREQ, DESIGN);
+    let sources = [Source::new(34, 49).pulse(2.0 / FREQ, 8.0 / FREQ).carrier(FREQ)];
+    let run = |f: &mut fdtd| { f.run(1500); };
+    let eps = design();
+    let s = solver.solve(&sources, || model(&eps), run).unwrap();
+    let scale = s.gradient.iter().flatten().fold(0.0f64, |a, g| a.max(g.abs()));
+    assert!(scale > 0.0);
+
+    let h = 1e-3;
+    for &(x, y) in [(44, 46), (49, 51)].iter() {
+        let value = |d: f64| {
+            let mut e = design();
+            e[x - DESIGN.x_s][y - DESIGN.y_s] += d;
+            solver.forward(&sources, || model(&e), run).unwrap()
+        };
+        let fd = (value(h) - value(-h)) / (2.0 * h);
+        let adj = s.at(x, y);
+        assert!((fd - adj).abs() < 1e-2 * scale, "({}, {}): adjoint {:e}, finite difference {:e}", x, y, adj, fd);
+    }
+}
+
+#[test]
+fn intensity_gradient_matches_finite_difference() {
+    check(Objective::Intensity{region: Region{x_s: 64, x_l: 67, y_s: 47, y_l: 50}});
+}
+
+#[test]
+fn flux_gradient_matches_finite_difference() {
+    check(Objective::Flux{normal: Axis::X, pos: 66, start: 29, end: 69});
+}
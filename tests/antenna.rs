@@ -0,0 +1,65 @@
+extern crate Rfdtd;
+
+use std::f64::consts::PI;
+
+use Rfdtd::antenna::Antenna;
+use Rfdtd::decay::Decay;
+use Rfdtd::error::Error;
+use Rfdtd::fdtd2d::*;
+use Rfdtd::snapshot::Region;
+
+#[test]
+fn lumped_feed_in_vacuum() {
+    // 真空中の長さlの線電流: 効率1, 無指向性 (D = 1), 放射抵抗 R = l ω μ0 / 4
+    let length = 1e-3;
+    let freqs = [2e9, 3e9, 4e9];
+    let mut f = fdtdBuilder::newGrid(120, 120, 0.002, 0.002, 16);
+    let feed = f.add_source(Source::new(76, 76).pulse(30e-12, 150e-12).lumped(50.0, length)).unwrap();
+    f.setup();
+    let ant = Antenna::new(&mut f, feed, Region{x_s: 26, x_l: 127, y_s: 26, y_l: 127}, &freqs).unwrap();
+    f.run_decay(&mut Decay::energy(-60.0, 20000));
+    let m = ant.metrics(&f, 50.0, 360);
+    for (n, &freq) in freqs.iter().enumerate() {
+        assert!((m.efficiency[n] - 1.0).abs() < 0.01, "efficiency {} at {:e}", m.efficiency[n], freq);
+        let d = &m.directivity[n];
+        let (lo, hi) = (d.iter().cloned().fold(f64::MAX, f64::min), d.iter().cloned().fold(0.0, f64::max));
+        assert!(lo > 0.99 && hi < 1.01, "directivity {}..{} at {:e}", lo, hi, freq);
+        // 格子の分散 ((kΔx)^2 程度) とPMLの反射でずれる
+        let r = length * 2.0 * PI * freq * MU0 / 4.0;
+        let z = m.impedance[n];
+        assert!((z.re - r).abs() < 0.03 * r, "Re Z {} != {} at {:e}", z.re, r, freq);
+        assert!(z.im > 0.0);
+    }
+}
+
+#[test]
+fn contour_near_the_edge_is_an_error() {
+    // PMLを含めて 56 x 56 セル, 解析領域は 8..48 (始点側の辺は外側のHを使うので閉曲線は 9..48)
+    let mut f = fdtdBuilder::newGrid(40, 40, 0.002, 0.002, 8);
+    let feed = f.add_source(Source::new(28, 28).lumped(50.0, 1e-3)).unwrap();
+    f.setup();
+    let new = |f: &mut fdtd, x_s, x_l, y_s, y_l| Antenna::new(f, feed, Region{x_s, x_l, y_s, y_l}, &[3e9]).map(|_| ());
+    match new(&mut f, 8, 40, 10, 40) {
+        Err(Error::InPml{what: "monitor", ..}) => {}
+        r => panic!("{:?}", r),
+    }
+    match new(&mut f, 10, 49, 10, 40) {
+        Err(Error::InPml{what: "monitor", ..}) => {}
+        r => panic!("{:?}", r),
+    }
+    match new(&mut f, 10, 40, 10, 60) {
+        Err(Error::OutOfBounds{what: "monitor", ..}) => {}
+        r => panic!("{:?}", r),
+    }
+    match new(&mut f, 0, 40, 10, 40) {
+        Err(Error::OutOfBounds{what: "far-field contour", ..}) => {}
+        r => panic!("{:?}", r),
+    }
+    match new(&mut f, 30, 40, 10, 40) {
+        Err(Error::OutOfBounds{what: "antenna feed inside the contour", ..}) => {}
+        r => panic!("{:?}", r),
+    }
+    assert!(f.monitors().is_empty());
+    new(&mut f, 9, 48, 9, 48).unwrap();
+    assert_eq!(f.monitors().len(), 11);
+}
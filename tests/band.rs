@@ -0,0 +1,52 @@
+extern crate Rfdtd;
+
+use Rfdtd::band::{k_path, BandSolver};
+use Rfdtd::eigenmode::Polarization;
+use Rfdtd::error::Result;
+use Rfdtd::fdtd2d::*;
+
+const DX: f64 = 0.005;
+const CELLS: i32 = 20;
+
+// 20 x 20 セルの真空の単位セル (外周の1層を含めて22 x 22)
+fn vacuum() -> Result<fdtd> {
+    let mut f = fdtdBuilder::newGrid(CELLS + 2, CELLS + 2, DX, DX, 0);
+    f.setup();
+    Ok(f)
+}
+
+#[test]
+fn empty_lattice() {
+    let points = k_path(&[(0.25, 0.0), (0.4, 0.2)], 1);
+    let (fmin, fmax) = (0.3e9, 3.3e9);
+    let bands = BandSolver::new(Polarization::Te, fmin, fmax).solve(&points, vacuum).unwrap();
+    let a = CELLS as f64 * DX;
+    assert!((bands.period.0 - a).abs() < 1e-12);
+    for (p, modes) in points.iter().zip(&bands.modes) {
+        // f = c |k + G| / a
+        let mut exact: Vec<f64> = Vec::new();
+        for m in -3..4 {
+            for n in -3..4 {
+                let f = C * ((p.0 + m as f64).powi(2) + (p.1 + n as f64).powi(2)).sqrt() / a;
+                if f > fmin && f < 0.95 * fmax && !exact.iter().any(|&e| (e - f).abs() < 1e-6 * f) {
+                    exact.push(f);
+                }
+            }
+        }
+        for &e in &exact {
+            assert!(modes.iter().any(|m| (m.freq - e).abs() < 0.005 * e), "k {:?}: no band at {:e}", p, e);
+        }
+        for m in modes {
+            assert!(exact.iter().any(|&e| (m.freq - e).abs() < 0.005 * e), "k {:?}: spurious band at {:e}", p, m.freq);
+        }
+    }
+}
+
+#[test]
+fn needs_probes_and_sources() {
+    let b = BandSolver::new(Polarization::Te, 1e9, 3e9);
+    let b = BandSolver{probes: 0, ..b};
+    assert!(b.solve(&[(0.1, 0.0)], vacuum).is_err());
+    let b = BandSolver{probes: 1, sources: 0, ..b};
+    assert!(b.solve(&[(0.1, 0.0)], vacuum).is_err());
+}
@@ -0,0 +1,129 @@
+extern crate Rfdtd;
+
+use std::env;
+use std::fs;
+
+use Rfdtd::binary::*;
+use Rfdtd::complex::Complex;
+use Rfdtd::json::{self, Value};
+
+fn u16_at(b: &[u8], i: usize) -> usize {
+    u16::from_le_bytes([b[i], b[i + 1]]) as usize
+}
+
+fn u32_at(b: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes([b[i], b[i + 1], b[i + 2], b[i + 3]])
+}
+
+// .npyのヘッダ文字列とデータ部
+fn split_npy(b: &[u8]) -> (String, &[u8]) {
+    assert_eq!(&b[..8], b"\x93NUMPY\x01\x00");
+    let end = 10 + u16_at(b, 8);
+    (String::from_utf8(b[10..end].to_vec()).unwrap(), &b[end..])
+}
+
+#[test]
+fn npy_header_is_aligned() {
+    // 名前や形状を変えてヘッダ長が変わっても64バイト境界に揃う
+    for shape in [vec![3], vec![2, 5], vec![7, 11, 13], vec![100000, 3]] {
+        let n: usize = shape.iter().product();
+        let a = Array::real("v", &shape, (0..n).map(|i| i as f64 * 0.5).collect());
+        for &dtype in &[Dtype::F32, Dtype::F64] {
+            let mut b = Vec::new();
+            write_npy(&mut b, &a, dtype).unwrap();
+            let (header, data) = split_npy(&b);
+            assert_eq!((b.len() - data.len()) % 64, 0);
+            assert!(header.ends_with('\n'));
+            assert!(header.contains(&format!("'descr': '{}'", dtype.descr(false))));
+            let back = Array::from_bytes("v", &shape, data, dtype, false);
+            match back.data {
+                ArrayData::Real(v) => assert!(v.iter().enumerate().all(|(i, &x)| x == i as f64 * 0.5)),
+                ArrayData::Complex(_) => panic!("complex"),
+            }
+        }
+    }
+    let mut b = Vec::new();
+    write_npy(&mut b, &Array::real("v", &[4], vec![0.0; 4]), Dtype::F64).unwrap();
+    assert!(split_npy(&b).0.contains("'shape': (4,)"));
+}
+
+#[test]
+fn npz_round_trip() {
+    let path = env::temp_dir().join(format!("rfdtd_binary_{}.npz", std::process::id()));
+    let arrays = vec![
+        Array::real("ez", &[2, 3], vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]),
+        Array::complex("dft", &[2], vec![Complex::new(1.0, -1.0), Complex::new(0.5, 2.0)]),
+    ];
+    save_npz(&path, &arrays, Dtype::F64).unwrap();
+    let b = fs::read(&path).unwrap();
+    fs::remove_file(&path).ok();
+
+    // 末尾のディレクトリ終端からセントラルディレクトリを辿り, 各エントリを読む
+    let end = b.len() - 22;
+    assert_eq!(u32_at(&b, end), 0x0605_4b50);
+    assert_eq!(u16_at(&b, end + 10), arrays.len());
+    let mut c = u32_at(&b, end + 16) as usize;
+    for a in &arrays {
+        assert_eq!(u32_at(&b, c), 0x0201_4b50);
+        let (crc, size, name_len) = (u32_at(&b, c + 16), u32_at(&b, c + 20) as usize, u16_at(&b, c + 28));
+        let offset = u32_at(&b, c + 42) as usize;
+        assert_eq!(&b[c + 46..c + 46 + name_len], format!("{}.npy", a.name).as_bytes());
+
+        assert_eq!(u32_at(&b, offset), 0x0403_4b50);
+        assert_eq!(u32_at(&b, offset + 14), crc);
+        let body = offset + 30 + u16_at(&b, offset + 26) + u16_at(&b, offset + 28);
+        let npy = &b[body..body + size];
+        assert_eq!(crc32(npy), crc);
+
+        let mut expected = Vec::new();
+        write_npy(&mut expected, a, Dtype::F64).unwrap();
+        assert_eq!(npy, &expected[..]);
+        c += 46 + name_len;
+    }
+    assert_eq!(c, end);
+}
+
+#[test]
+fn crc32_check_value() {
+    assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    assert_eq!(crc32(b""), 0);
+}
+
+#[test]
+fn raw_sidecar() {
+    let path = env::temp_dir().join(format!("rfdtd_binary_{}.raw", std::process::id()));
+    let a = Array::real("ez", &[2, 3], vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+    let meta = Metadata {
+        component: "ez".to_string(),
+        axes: vec!["y".to_string(), "x".to_string()],
+        spacing: vec![0.002, f64::NAN],
+        origin: vec![0.0, 0.01],
+        time: Some(1.5e-9),
+        step: Some(42),
+        extra: vec![("x".to_string(), vec![0.01, 0.012, 0.013])],
+    };
+    save_raw(&path, &a, &meta, Dtype::F32).unwrap();
+    let raw = fs::read(&path).unwrap();
+    let text = fs::read_to_string(path.with_extension("json")).unwrap();
+    fs::remove_file(&path).ok();
+    fs::remove_file(path.with_extension("json")).ok();
+
+    assert_eq!(raw, a.to_bytes(Dtype::F32));
+    let j = json::parse(&text).unwrap();
+    assert_eq!(j.get("dtype").and_then(Value::as_str), Some("<f4"));
+    let nums = |k: &str| j.get(k).and_then(Value::as_array).unwrap().iter().map(|v| v.as_f64()).collect::<Vec<_>>();
+    assert_eq!(nums("shape"), vec![Some(2.0), Some(3.0)]);
+    assert_eq!(nums("x"), vec![Some(0.01), Some(0.012), Some(0.013)]);
+    // 等間隔でない軸のspacingはnull
+    assert_eq!(nums("spacing"), vec![Some(0.002), None]);
+    assert_eq!(j.get("step").and_then(Value::as_f64), Some(42.0));
+    assert_eq!(j.get("time").and_then(Value::as_f64), Some(1.5e-9));
+}
+
+#[test]
+fn spacing_of_coordinates() {
+    assert_eq!(spacing(&[]), 0.0);
+    assert_eq!(spacing(&[0.3]), 0.0);
+    assert!((spacing(&[0.0, 0.1, 0.2, 0.3]) - 0.1).abs() < 1e-15);
+    assert!(spacing(&[0.0, 0.1, 0.15]).is_nan());
+}
@@ -0,0 +1,135 @@
+extern crate Rfdtd;
+
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+
+use Rfdtd::checkpoint::{load_checkpoint, save_checkpoint, Checkpointer};
+use Rfdtd::fdtd2d::*;
+use Rfdtd::monitor::{DftMonitor, Probe};
+use Rfdtd::snapshot::Region;
+
+fn model() -> fdtd {
+    let mut f = fdtdBuilder::newGrid(60, 50, 0.005, 0.004, 8);
+    f.medium(20, 40, 15, 35, Medium{eps: 4.0, mu: 1.5, sigma_e: 0.01, sigma_m: 0.0}).unwrap();
+    f.init_source(15, 25).unwrap();
+    f.setup();
+    f.pec_rect(50, 52, 10, 50).unwrap();
+    f.add_probe(Probe::new(Component::Ez, 45, 30)).unwrap();
+    f.add_probe(Probe::new(Component::Hy, 30, 20)).unwrap();
+    let d = DftMonitor::new(&f, Component::Ez, Region{x_s: 10, x_l: 60, y_s: 10, y_l: 50}, &[1e9, 3e9]).unwrap();
+    f.add_monitor(d).unwrap();
+    f
+}
+
+fn state(f: &fdtd) -> Vec<u8> {
+    let mut b = Vec::new();
+    f.write_state(&mut b).unwrap();
+    b
+}
+
+#[test]
+fn resume_is_bit_exact() {
+    let path = env::temp_dir().join(format!("rfdtd_checkpoint_{}.ckp", std::process::id()));
+
+    // 中断せずに計算
+    let mut reference = model();
+    reference.run(150);
+
+    // 途中で保存し, 読み込んでから残りを計算
+    let mut first = model();
+    first.run(70);
+    save_checkpoint(&path, &first).unwrap();
+    drop(first);
+    let mut resumed = load_checkpoint(&path).unwrap();
+    fs::remove_file(&path).ok();
+    assert_eq!(resumed.step_count(), 70);
+    resumed.run(80);
+
+    assert_eq!(resumed.step_count(), reference.step_count());
+    assert_eq!(resumed.time().to_bits(), reference.time().to_bits());
+    for &c in Component::ALL.iter() {
+        let (a, b) = (resumed.field(c), reference.field(c));
+        for (ca, cb) in a.iter().zip(b) {
+            assert!(ca.iter().zip(cb).all(|(x, y)| x.to_bits() == y.to_bits()), "{} differs", c.name());
+        }
+    }
+    for (p, q) in resumed.probes().iter().zip(reference.probes()) {
+        assert_eq!(p.values.len(), 150);
+        assert!(p.values.iter().zip(&q.values).all(|(x, y)| x.to_bits() == y.to_bits()));
+    }
+    assert!(resumed.field(Component::Ez).iter().flatten().any(|&v| v != 0.0));
+    assert_eq!(state(&resumed), state(&reference));
+}
+
+#[test]
+fn rejects_other_files() {
+    let path = env::temp_dir().join(format!("rfdtd_not_checkpoint_{}.ckp", std::process::id()));
+    fs::write(&path, b"RFDTDRES\x01\x00\x00\x00").unwrap();
+    assert!(load_checkpoint(&path).is_err());
+    fs::remove_file(&path).ok();
+}
+
+#[test]
+fn save_error_stops_run() {
+    // 通常のファイルの下には書けない (root でも書き込み権限に依らず失敗する)
+    let file = env::temp_dir().join(format!("rfdtd_not_dir_{}", std::process::id()));
+    fs::write(&file, b"").unwrap();
+    let mut f = model();
+    let c = Checkpointer::new(file.join("checkpoint.ckp"), 20);
+    let error = c.error();
+    f.add_observer(c);
+    let summary = f.run(100);
+    fs::remove_file(&file).ok();
+    assert_eq!(summary.stop, StopReason::Observer);
+    assert_eq!(summary.steps, 20);
+    assert!(error.borrow().is_some());
+}
+
+fn patch(b: &mut [u8], at: usize, v: u64) {
+    b[at..at + 8].copy_from_slice(&v.to_le_bytes());
+}
+
+#[test]
+fn corrupt_header_is_invalid_data() {
+    let good = state(&model());
+    assert!(fdtdBuilder::from_state(&mut &good[..]).is_ok());
+
+    // 各軸は上限内でもセル数の積が大きすぎる
+    let mut b = good.clone();
+    patch(&mut b, 0, 1 << 20);
+    patch(&mut b, 8, 1 << 20);
+    assert_eq!(fdtdBuilder::from_state(&mut &b[..]).err().unwrap().kind(), ErrorKind::InvalidData);
+
+    // PMLが領域より厚い
+    let mut b = good.clone();
+    patch(&mut b, 16, 40);
+    assert_eq!(fdtdBuilder::from_state(&mut &b[..]).err().unwrap().kind(), ErrorKind::InvalidData);
+
+    // 途中で切れている
+    assert_eq!(fdtdBuilder::from_state(&mut &good[..good.len() - 8]).err().unwrap().kind(), ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn corrupt_monitor_is_invalid_data() {
+    let good = state(&model());
+    // 末尾のDFTモニタ: 領域(4 x u64), 周波数の数, 周波数, x, y, 積算値 (50 x 40 点, 2周波数)
+    let tail = 2 * 50 * 40 * 16 + 40 * 8 + 50 * 8 + 2 * 8;
+    let region = good.len() - tail - 5 * 8;
+    assert_eq!(good[region..region + 8], 10u64.to_le_bytes());
+
+    // 始点が終点より後ろ
+    let mut b = good.clone();
+    patch(&mut b, region, 61);
+    assert_eq!(fdtdBuilder::from_state(&mut &b[..]).err().unwrap().kind(), ErrorKind::InvalidData);
+
+    // 格子の外
+    let mut b = good.clone();
+    patch(&mut b, region + 8, 1 << 40);
+    assert_eq!(fdtdBuilder::from_state(&mut &b[..]).err().unwrap().kind(), ErrorKind::InvalidData);
+
+    // 周波数の数だけが大きい (確保せずに読めた分で終わる)
+    let mut b = good.clone();
+    patch(&mut b, region + 4 * 8, u64::MAX / 16);
+    assert!(fdtdBuilder::from_state(&mut &b[..]).is_err());
+}
@@ -0,0 +1,135 @@
+// 実行ファイルの引数解析と表示レベル (ビルドした Rfdtd を実行して確かめる)
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+const SCENARIO: &str = r#"{
+  "grid": {"nx": 24, "ny": 20, "dx": 0.005, "dy": 0.005},
+  "boundary": {"pml_layers": 4},
+  "steps": 30,
+  "sources": [{"x": 12, "y": 10}],
+  "outputs": [{"type": "container", "path": "result.rfd", "every": 10}]
+}"#;
+
+fn rfdtd(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_Rfdtd")).args(args).arg("--non-interactive").output().unwrap()
+}
+
+fn stdout(o: &Output) -> String {
+    String::from_utf8_lossy(&o.stdout).into_owned()
+}
+
+fn stderr(o: &Output) -> String {
+    String::from_utf8_lossy(&o.stderr).into_owned()
+}
+
+// 一時ディレクトリに小さなシナリオを置く
+fn workdir(name: &str) -> (PathBuf, String) {
+    let d = env::temp_dir().join(format!("rfdtd_cli_{}_{}", name, std::process::id()));
+    fs::create_dir_all(&d).unwrap();
+    let s = d.join("small.json");
+    fs::write(&s, SCENARIO).unwrap();
+    let path = s.to_string_lossy().into_owned();
+    (d, path)
+}
+
+#[test]
+fn help_and_usage() {
+    let o = rfdtd(&["--help"]);
+    assert_eq!(o.status.code(), Some(0));
+    assert!(stdout(&o).contains("commands:"));
+    // コマンド無し, 入力ファイル無しは使い方を表示して 2
+    for args in [&[][..], &["run"][..], &["run", "a.json", "b.json"][..]] {
+        let o = rfdtd(args);
+        assert_eq!(o.status.code(), Some(2), "{:?}", args);
+        assert!(stdout(&o).contains("commands:"));
+    }
+}
+
+#[test]
+fn invalid_options() {
+    let cases: [(&[&str], &str); 9] = [
+        (&["run", "a.json", "--bogus"], "unknown option --bogus"),
+        (&["run", "a.json", "-j", "0"], "invalid thread count"),
+        (&["run", "a.json", "--checkpoint", "x"], "invalid step count"),
+        (&["sweep", "a.json", "--parallel", "0"], "invalid run count"),
+        (&["run", "a.json", "--log-level", "loud"], "unknown log level \"loud\""),
+        (&["dry-run", "a.json", "--freq", "inf"], "invalid number"),
+        (&["render", "a.rfd", "--component", "ew"], "unknown component \"ew\""),
+        (&["render", "a.rfd", "--scaling", "wide"], "invalid scaling \"wide\""),
+        (&["render", "a.rfd", "--colormap", "jet"], "unknown colormap \"jet\""),
+    ];
+    for (args, msg) in cases.iter() {
+        let o = rfdtd(args);
+        assert_eq!(o.status.code(), Some(2), "{:?}", args);
+        assert!(stderr(&o).contains(msg), "{:?}: {}", args, stderr(&o));
+    }
+    // 値が無い (--non-interactive を値として取らないよう最後に置く)
+    let o = Command::new(env!("CARGO_BIN_EXE_Rfdtd")).args(["run", "a.json", "-o"]).output().unwrap();
+    assert_eq!(o.status.code(), Some(2));
+    assert!(stderr(&o).contains("-o requires a value"));
+
+    // 未知のコマンド, 存在しない入力は実行時のエラーで 1
+    let o = rfdtd(&["frobnicate", "a.json"]);
+    assert_eq!(o.status.code(), Some(1));
+    assert!(stderr(&o).contains("unknown command \"frobnicate\""));
+    let o = rfdtd(&["validate", "/nonexistent/a.json"]);
+    assert_eq!(o.status.code(), Some(1));
+}
+
+#[test]
+fn validate_and_dry_run() {
+    let (d, s) = workdir("validate");
+    let o = rfdtd(&["validate", &s]);
+    assert_eq!(o.status.code(), Some(0));
+    assert_eq!(stdout(&o), format!("{}: OK\n", s));
+    let o = rfdtd(&["validate", &s, "-q"]);
+    assert_eq!((o.status.code(), stdout(&o)), (Some(0), String::new()));
+
+    let o = rfdtd(&["dry-run", &s, "--freq", "5e9"]);
+    fs::remove_dir_all(&d).ok();
+    assert_eq!(o.status.code(), Some(0));
+    let out = stdout(&o);
+    assert!(out.contains("セル数: 32 x 28"), "{}", out);
+    assert!(out.contains("ステップ数: 30"));
+    assert!(out.contains("(5e9 Hz"));
+}
+
+#[test]
+fn run_log_levels_and_render() {
+    let (d, s) = workdir("run");
+    let out = d.join("out");
+    let out_s = out.to_string_lossy().into_owned();
+
+    // 既定はInfo: 終了時の要約と最終ステップの進捗を表示し, Debugは表示しない
+    let o = rfdtd(&["run", &s, "-o", &out_s]);
+    let err = stderr(&o);
+    assert_eq!(o.status.code(), Some(0), "{}", err);
+    assert!(err.contains("[INFO] 終了: 30 ステップ"), "{}", err);
+    assert!(err.contains("[INFO] step 30/30"));
+    assert!(!err.contains("[DEBUG]"));
+    assert!(out.join("result.rfd").exists());
+
+    // -q は警告以上のみ, --no-progress は進捗のみ止める
+    let o = rfdtd(&["run", &s, "-o", &out_s, "-q"]);
+    assert_eq!((o.status.code(), stderr(&o)), (Some(0), String::new()));
+    let err = stderr(&rfdtd(&["run", &s, "-o", &out_s, "--no-progress"]));
+    assert!(err.contains("[INFO] 終了") && !err.contains("[INFO] step"), "{}", err);
+
+    // -v は毎ステップの時刻 (Debug) も表示し, --log-level が優先される
+    let err = stderr(&rfdtd(&["run", &s, "-o", &out_s, "-v"]));
+    assert!(err.contains("[DEBUG] Time step:30"), "{}", err);
+    let err = stderr(&rfdtd(&["run", &s, "-o", &out_s, "-v", "--log-level", "warn"]));
+    assert!(!err.contains("[DEBUG]") && !err.contains("[INFO]"), "{}", err);
+
+    let rfd = out.join("result.rfd").to_string_lossy().into_owned();
+    let img = d.join("img").to_string_lossy().into_owned();
+    let o = rfdtd(&["render", &rfd, "-o", &img, "--format", "ppm", "--scaling", "-1:1"]);
+    let err = stderr(&o);
+    let n = fs::read_dir(&img).map(|r| r.count()).unwrap_or(0);
+    fs::remove_dir_all(&d).ok();
+    assert_eq!(o.status.code(), Some(0), "{}", err);
+    assert!(n >= 3, "{} images", n);
+}
@@ -0,0 +1,124 @@
+extern crate Rfdtd;
+
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use Rfdtd::binary::{Array, ArrayData, Dtype};
+use Rfdtd::complex::Complex;
+use Rfdtd::container::{Attr, ResultFile, ResultWriter};
+
+fn sample(name: &str, index: bool) -> PathBuf {
+    let path = env::temp_dir().join(format!("rfdtd_container_{}_{}.rfd", name, std::process::id()));
+    let mut w = ResultWriter::create(&path, Dtype::F64).unwrap();
+    let attrs = [("dt", Attr::Num(1.5e-12)), ("steps", Attr::Int(-3)), ("unit", Attr::Text("V/m".to_string())), ("freqs", Attr::Nums(vec![1e9, 2e9]))];
+    w.write("/params", &attrs, None).unwrap();
+    let re = Array::real("ez", &[2, 3], vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0]);
+    w.write("/fields/ez", &[("step", Attr::Int(10))], Some(&re)).unwrap();
+    let c = Array::complex("dft", &[2], vec![Complex::new(1.0, -2.0), Complex::new(0.5, 0.25)]);
+    w.write("/monitors/dft", &[], Some(&c)).unwrap();
+    if index {
+        w.finish().unwrap();
+    }
+    path
+}
+
+// 先頭チャンクのsizeを書き換える
+fn patch_size(path: &PathBuf, size: u64) {
+    let mut b = fs::read(path).unwrap();
+    assert_eq!(&b[12..16], b"CHNK");
+    b[16..24].copy_from_slice(&size.to_le_bytes());
+    fs::write(path, &b).unwrap();
+}
+
+#[test]
+fn round_trip() {
+    let path = sample("round", true);
+    let mut f = ResultFile::open(&path).unwrap();
+    assert_eq!(f.entries().len(), 3);
+    assert_eq!(f.attr("/params", "dt"), Some(&Attr::Num(1.5e-12)));
+    assert_eq!(f.attr("/params", "steps"), Some(&Attr::Int(-3)));
+    assert_eq!(f.attr("/params", "unit"), Some(&Attr::Text("V/m".to_string())));
+    assert_eq!(f.attr("/params", "freqs"), Some(&Attr::Nums(vec![1e9, 2e9])));
+    assert_eq!(f.attr("/fields/ez", "step"), Some(&Attr::Int(10)));
+
+    let a = f.read("/fields/ez").unwrap();
+    assert_eq!(a.name, "ez");
+    assert_eq!(a.shape, vec![2, 3]);
+    match a.data {
+        ArrayData::Real(ref v) => assert_eq!(v, &[0.0, 1.0, 2.0, 3.0, 4.0, 5.0]),
+        _ => panic!("ez should be real"),
+    }
+    match f.read("/monitors/dft").unwrap().data {
+        ArrayData::Complex(ref v) => {
+            assert_eq!((v[0].re, v[0].im), (1.0, -2.0));
+            assert_eq!((v[1].re, v[1].im), (0.5, 0.25));
+        }
+        _ => panic!("dft should be complex"),
+    }
+    assert_eq!(f.read("/params").unwrap_err().kind(), ErrorKind::InvalidData);
+    assert_eq!(f.read("/nothing").unwrap_err().kind(), ErrorKind::NotFound);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn unfinished_file_is_scanned() {
+    // 索引の無い (途中で止まった) ファイルは先頭から読む
+    let path = sample("scan", false);
+    let mut f = ResultFile::open(&path).unwrap();
+    let paths: Vec<&str> = f.entries().iter().map(|e| e.path.as_str()).collect();
+    assert_eq!(paths, vec!["/params", "/fields/ez", "/monitors/dft"]);
+    assert_eq!(f.read("/fields/ez").unwrap().shape, vec![2, 3]);
+
+    // 末尾が欠けたチャンクは読み飛ばす
+    let len = fs::metadata(&path).unwrap().len();
+    fs::OpenOptions::new().write(true).open(&path).unwrap().set_len(len - 5).unwrap();
+    let f = ResultFile::open(&path).unwrap();
+    assert_eq!(f.entries().len(), 2);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn corrupt_chunk_is_invalid_data() {
+    // sizeが本体より小さい
+    let path = sample("short", true);
+    patch_size(&path, 1);
+    assert_eq!(ResultFile::open(&path).err().unwrap().kind(), ErrorKind::InvalidData);
+
+    // sizeがファイルを越える
+    patch_size(&path, u64::MAX);
+    assert_eq!(ResultFile::open(&path).err().unwrap().kind(), ErrorKind::InvalidData);
+    fs::remove_file(&path).unwrap();
+
+    // 索引の無いファイルでも桁あふれしない
+    let path = sample("huge", false);
+    patch_size(&path, u64::MAX - 4);
+    assert_eq!(ResultFile::open(&path).unwrap().entries().len(), 0);
+    fs::remove_file(&path).unwrap();
+
+    // 属性の長さが壊れている
+    let path = sample("attr", true);
+    let mut b = fs::read(&path).unwrap();
+    let at = b.windows(3).position(|w| w == b"V/m").unwrap() - 4;
+    b[at..at + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+    fs::write(&path, &b).unwrap();
+    assert_eq!(ResultFile::open(&path).err().unwrap().kind(), ErrorKind::InvalidData);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn shape_mismatch_is_invalid_data() {
+    // 配列の形状と本体の長さが食い違う
+    let path = sample("shape", true);
+    let mut b = fs::read(&path).unwrap();
+    let at = b.windows(10).position(|w| w == b"/fields/ez").unwrap() + 10;
+    // 属性 "step" (2+4+1+8) の後ろ: dtype, ndim, shape[0]
+    let s0 = at + 2 + 15 + 2;
+    assert_eq!(&b[s0..s0 + 8], &2u64.to_le_bytes());
+    b[s0..s0 + 8].copy_from_slice(&3u64.to_le_bytes());
+    fs::write(&path, &b).unwrap();
+    let mut f = ResultFile::open(&path).unwrap();
+    assert_eq!(f.read("/fields/ez").unwrap_err().kind(), ErrorKind::InvalidData);
+    fs::remove_file(&path).unwrap();
+}
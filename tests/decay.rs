@@ -0,0 +1,53 @@
+extern crate Rfdtd;
+
+use Rfdtd::decay::Decay;
+use Rfdtd::fdtd2d::*;
+use Rfdtd::monitor::Probe;
+
+// 完全導体で囲んだ損失のある共振器 (PMLに漏れない)
+fn cavity(sigma_e: f64) -> fdtd {
+    let mut f = fdtdBuilder::newGrid(40, 40, 0.005, 0.005, 8);
+    f.medium(10, 46, 10, 46, Medium{sigma_e, ..Medium::default()}).unwrap();
+    f.init_source(20, 23).unwrap();
+    f.setup();
+    for &(x_s, x_l, y_s, y_l) in &[(10, 46, 10, 11), (10, 46, 45, 46), (10, 11, 10, 46), (45, 46, 10, 46)] {
+        f.pec_rect(x_s, x_l, y_s, y_l).unwrap();
+    }
+    f.add_probe(Probe::new(Component::Ez, 30, 33)).unwrap();
+    f
+}
+
+#[test]
+fn lossy_cavity_decays() {
+    let mut f = cavity(0.05);
+    let mut d = Decay::energy(-30.0, 20000).window(100).interval(5);
+    let s = f.run_decay(&mut d);
+    match s.stop {
+        StopReason::Decayed(db) => assert!(db <= -30.0, "{}", db),
+        r => panic!("{:?}", r),
+    }
+    assert!(s.steps < 20000 && s.steps.is_multiple_of(5));
+    assert!(d.level_db() <= -30.0);
+
+    // 観測点を指標にしても減衰で終わる
+    let mut f = cavity(0.05);
+    let mut d = Decay::probes(&[0], -30.0, 20000).window(100).interval(1);
+    match f.run_decay(&mut d).stop {
+        StopReason::Decayed(_) => {}
+        r => panic!("{:?}", r),
+    }
+}
+
+#[test]
+fn lossless_cavity_hits_the_step_cap() {
+    let mut f = cavity(0.0);
+    let mut d = Decay::energy(-30.0, 600).window(100).interval(5);
+    let s = f.run_decay(&mut d);
+    assert_eq!((s.steps, s.stop), (600, StopReason::MaxSteps));
+
+    // 上限は通算のステップ数
+    let mut f = cavity(0.0);
+    f.run(550);
+    let mut d = Decay::energy(-30.0, 600);
+    assert_eq!(f.run_decay(&mut d).steps, 600);
+}
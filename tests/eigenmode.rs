@@ -0,0 +1,55 @@
+extern crate Rfdtd;
+
+use std::f64::consts::PI;
+
+use Rfdtd::eigenmode::{ModeSolver, Polarization};
+use Rfdtd::fdtd2d::*;
+use Rfdtd::port::Axis;
+
+const DY: f64 = 0.5e-3;
+
+// 厚さ d の対称スラブの偶/奇モードの特性方程式の根 (二分法)
+// κ = k0 sqrt(n1^2 - neff^2), γ = k0 sqrt(neff^2 - n2^2), 偶: κ tan(κd/2) = γ, 奇: -κ cot(κd/2) = γ
+fn slab(k0: f64, d: f64, n1: f64, n2: f64, order: usize) -> f64 {
+    let g = |neff: f64| {
+        let (kappa, gamma) = (k0 * (n1 * n1 - neff * neff).sqrt(), k0 * (neff * neff - n2 * n2).sqrt());
+        let a = 0.5 * kappa * d - 0.5 * PI * order as f64;
+        kappa * a.sin() - gamma * a.cos()
+    };
+    // κd/2 が [mπ/2, (m+1)π/2) の範囲
+    let neff = |a: f64| (n1 * n1 - (2.0 * a / (k0 * d)).powi(2)).max(n2 * n2).sqrt();
+    let (mut lo, mut hi) = (neff(0.5 * PI * (order + 1) as f64 - 1e-12), neff(0.5 * PI * order as f64));
+    for _ in 0..100 {
+        let mid = 0.5 * (lo + hi);
+        if (g(mid) > 0.0) == (g(hi) > 0.0) { hi = mid; } else { lo = mid; }
+    }
+    0.5 * (lo + hi)
+}
+
+#[test]
+fn slab_waveguide_neff() {
+    // x方向に伝搬する厚さ 10mm, εr = 4 のスラブ (両側 70mm の真空). 2つの導波モード (V = 2.18)
+    let (freq, eps) = (12e9, 4.0f64);
+    let k0 = 2.0 * PI * freq / C;
+    let exact = [slab(k0, 20.0 * DY, eps.sqrt(), 1.0, 0), slab(k0, 20.0 * DY, eps.sqrt(), 1.0, 1)];
+    for &(r, tol) in &[(1, 2e-3), (2, 5e-4)] {
+        let dy = DY / r as f64;
+        let mut f = fdtdBuilder::newGrid(10, 300 * r as i32, dy, dy, 4);
+        // medium(y_s, y_l) はセル y_s..y_l-2
+        let (s, e) = (4 + 140 * r, 4 + 160 * r);
+        f.medium(0, 18, s, e + 1, Medium{eps, ..Medium::default()}).unwrap();
+        f.setup();
+        let modes = ModeSolver::new(Axis::X, 8, 5, 300 * r + 3, Polarization::Te).solve(&f, freq, 4).unwrap();
+        let guided: Vec<_> = modes.iter().filter(|m| m.neff > 1.0).collect();
+        assert_eq!(guided.len(), 2);
+        for (k, m) in guided.iter().enumerate() {
+            assert!((m.neff - exact[k]).abs() < tol * exact[k], "mode {} (dy {}): {} != {}", k, dy, m.neff, exact[k]);
+            // スラブの中心 (s + e) / 2 について偶, 奇
+            let sign = if k == 0 { 1.0 } else { -1.0 };
+            let c = (s + e) / 2 - 5;
+            for d in 1..40 * r {
+                assert!((m.profile[c + d] - sign * m.profile[c - d]).abs() < 1e-6, "mode {} parity", k);
+            }
+        }
+    }
+}
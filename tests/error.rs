@@ -0,0 +1,103 @@
+extern crate Rfdtd;
+
+use Rfdtd::error::Error;
+use Rfdtd::fdtd2d::*;
+use Rfdtd::monitor::{DftMonitor, Probe};
+use Rfdtd::snapshot::Region;
+
+// PMLを含めて 56 x 46 セル, 解析領域は 8..48, 8..38
+fn model() -> fdtd {
+    let mut f = fdtdBuilder::newGrid(40, 30, 0.005, 0.005, 8);
+    f.setup();
+    f
+}
+
+fn region(x_s: usize, x_l: usize, y_s: usize, y_l: usize) -> Region {
+    Region{x_s, x_l, y_s, y_l}
+}
+
+#[test]
+fn reversed_range() {
+    let mut f = model();
+    match f.epsmu(30, 20, 10, 20, 2.0) {
+        Err(Error::ReversedRange{axis: 'x', start: 30, end: 20, ..}) => {}
+        r => panic!("{:?}", r),
+    }
+    match f.pec_rect(10, 20, 15, 15) {
+        Err(Error::ReversedRange{axis: 'y', start: 15, end: 15, ..}) => {}
+        r => panic!("{:?}", r),
+    }
+    match DftMonitor::new(&f, Component::Ez, region(20, 10, 10, 20), &[1e9]) {
+        Err(Error::ReversedRange{what: "monitor", axis: 'x', ..}) => {}
+        r => panic!("{:?}", r.map(|_| ())),
+    }
+}
+
+#[test]
+fn out_of_bounds() {
+    let mut f = model();
+    match f.medium(10, 57, 10, 20, Medium::default()) {
+        Err(Error::OutOfBounds{x: 57, nx: 56, ny: 46, ..}) => {}
+        r => panic!("{:?}", r),
+    }
+    assert!(f.pec_rect(10, 56, 10, 46).is_ok());
+    match f.init_source(20, 46) {
+        Err(Error::OutOfBounds{what: "source", x: 20, y: 46, ..}) => {}
+        r => panic!("{:?}", r),
+    }
+    match f.add_probe(Probe::new(Component::Ez, 100, 20)) {
+        Err(Error::OutOfBounds{what: "probe", ..}) => {}
+        r => panic!("{:?}", r),
+    }
+    match DftMonitor::new(&f, Component::Ez, region(10, 20, 30, 47), &[1e9]) {
+        Err(Error::OutOfBounds{what: "monitor", ..}) => {}
+        r => panic!("{:?}", r.map(|_| ())),
+    }
+}
+
+#[test]
+fn inside_pml() {
+    let mut f = model();
+    match f.init_source(7, 20) {
+        Err(Error::InPml{what: "source", x: 7, y: 20, lpml: 8}) => {}
+        r => panic!("{:?}", r),
+    }
+    match f.add_source(Source::new(20, 38)) {
+        Err(Error::InPml{what: "source", ..}) => {}
+        r => panic!("{:?}", r),
+    }
+    match f.add_probe(Probe::new(Component::Ez, 48, 20)) {
+        Err(Error::InPml{what: "probe", ..}) => {}
+        r => panic!("{:?}", r),
+    }
+    match DftMonitor::new(&f, Component::Ez, region(8, 48, 5, 20), &[1e9]) {
+        Err(Error::InPml{what: "monitor", ..}) => {}
+        r => panic!("{:?}", r.map(|_| ())),
+    }
+    // 解析領域の端までは置ける
+    f.init_source(8, 37).unwrap();
+    f.add_probe(Probe::new(Component::Ez, 47, 8)).unwrap();
+    let m = DftMonitor::new(&f, Component::Ez, region(8, 48, 8, 38), &[1e9]).unwrap();
+    f.add_monitor(m).unwrap();
+}
+
+#[test]
+fn feed_needs_source() {
+    let mut f = model();
+    match f.feed(20, 20, 0.0) {
+        Err(Error::NoSource) => {}
+        r => panic!("{:?}", r),
+    }
+}
+
+#[test]
+fn graded_mesh_is_unsupported() {
+    let f = model();
+    assert!(f.require_uniform("test").is_ok());
+    let xw: Vec<f64> = (0..40).map(|i| if i < 20 { 0.005 } else { 0.0025 }).collect();
+    let g = fdtdBuilder::newGraded(&xw, &[0.005; 30], 8).unwrap();
+    match g.require_uniform("test") {
+        Err(Error::Unsupported{what: "test"}) => {}
+        r => panic!("{:?}", r),
+    }
+}
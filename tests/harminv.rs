@@ -0,0 +1,49 @@
+extern crate Rfdtd;
+
+use std::f64::consts::PI;
+
+use Rfdtd::harminv::Harminv;
+
+// A exp(-γt) cos(2πft + φ) の和
+fn signal(modes: &[(f64, f64, f64, f64)], dt: f64, n: usize) -> Vec<f64> {
+    (0..n).map(|k| {
+        let t = k as f64 * dt;
+        modes.iter().map(|&(f, g, a, p)| a * (-g * t).exp() * (2.0 * PI * f * t + p).cos()).sum()
+    }).collect()
+}
+
+#[test]
+fn decaying_sinusoids() {
+    let dt = 1e-11;
+    let exact = [(1.3e9, 2e7, 2.0, 0.4), (2.1e9, 5e7, 0.5, -1.0)];
+    let modes = Harminv::new(0.5e9, 3e9).analyze(&signal(&exact, dt, 3000), dt).unwrap();
+    assert_eq!(modes.len(), 2, "{:?}", modes);
+    for (m, &(f, g, a, p)) in modes.iter().zip(&exact) {
+        assert!((m.freq - f).abs() < 1e-6 * f, "freq {:e} != {:e}", m.freq, f);
+        assert!((m.decay - g).abs() < 1e-4 * g, "decay {:e} != {:e}", m.decay, g);
+        assert!((m.q - PI * f / g).abs() < 1e-4 * m.q);
+        assert!((m.amplitude - a).abs() < 1e-4 * a && (m.phase - p).abs() < 1e-4);
+        assert!(m.error < 1e-6);
+    }
+}
+
+#[test]
+fn t_start_skips_the_beginning() {
+    // 先頭の区間だけ別の周波数
+    let dt = 1e-11;
+    let mut y = signal(&[(0.8e9, 0.0, 1.0, 0.0)], dt, 500);
+    y.extend(signal(&[(1.7e9, 1e7, 1.0, 0.0)], dt, 2000));
+    let modes = Harminv::new(0.5e9, 3e9).t_start(500.0 * dt).analyze(&y, dt).unwrap();
+    assert_eq!(modes.len(), 1);
+    assert!((modes[0].freq - 1.7e9).abs() < 1e-6 * 1.7e9);
+}
+
+#[test]
+fn invalid_input() {
+    let y = vec![0.0; 100];
+    assert!(Harminv::new(1e9, 2e9).analyze(&y, 0.0).is_err());
+    // fmax がナイキスト周波数以上
+    assert!(Harminv::new(1e9, 6e10).analyze(&y, 1e-11).is_err());
+    assert!(Harminv::new(1e9, 2e9).analyze(&y[..4], 1e-11).is_err());
+    assert!(Harminv::new(1e9, 2e9).analyze(&y, 1e-11).unwrap().is_empty());
+}
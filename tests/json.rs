@@ -0,0 +1,23 @@
+extern crate Rfdtd;
+
+use Rfdtd::json::{self, Value};
+
+const SRC: &str = r#"{"grid": {"nx": 40}, "geometry": [{"x": [10, 20]}, {"x": [30, 40]}]}"#;
+
+#[test]
+fn path_mut_follows_keys_and_indices() {
+    let mut v = json::parse(SRC).unwrap();
+    assert_eq!(v.path_mut("geometry[0].x[1]"), Some(&mut Value::Num(20.0)));
+    *v.path_mut("geometry[1].x[0]").unwrap() = Value::Num(35.0);
+    assert_eq!(v.path_mut("geometry[1].x[0]"), Some(&mut Value::Num(35.0)));
+    assert_eq!(v.path_mut("grid.nx"), Some(&mut Value::Num(40.0)));
+}
+
+#[test]
+fn path_mut_rejects_invalid_paths() {
+    let mut v = json::parse(SRC).unwrap();
+    for p in &["grid.ny", "geometry[2].x", "geometry[0].x[2]", "geometry.x", "grid[0]",
+               "geometry[0", "geometry[a].x", "geometry[-1].x", "grid.nx.y"] {
+        assert!(v.path_mut(p).is_none(), "{}", p);
+    }
+}
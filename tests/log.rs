@@ -0,0 +1,64 @@
+#[macro_use]
+extern crate Rfdtd;
+
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use Rfdtd::log::{self, Level};
+
+// 出力先を共有のバッファにする
+#[derive(Clone,Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Buffer {
+    fn write(&mut self, b: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(b);
+        Ok(b.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Buffer {
+    fn take(&self) -> String {
+        String::from_utf8(std::mem::take(&mut *self.0.lock().unwrap())).unwrap()
+    }
+}
+
+fn emit_all() {
+    log_error!("e{}", 1);
+    log_warn!("w{}", 2);
+    log_info!("i{}", 3);
+    log_debug!("d{}", 4);
+    log_trace!("t{}", 5);
+}
+
+// レベルはプロセス全体で共有なので1つのテストで順に確かめる
+#[test]
+fn level_filtering() {
+    let buf = Buffer::default();
+    log::set_sink(Box::new(buf.clone()));
+
+    // 既定はInfo以上
+    assert!(log::enabled(Level::Info) && !log::enabled(Level::Debug));
+    emit_all();
+    assert_eq!(buf.take(), "[ERROR] e1\n[WARN] w2\n[INFO] i3\n");
+
+    log::set_level(Some(Level::Warn));
+    emit_all();
+    assert_eq!(buf.take(), "[ERROR] e1\n[WARN] w2\n");
+
+    log::set_level(Some(Level::Trace));
+    emit_all();
+    assert_eq!(buf.take(), "[ERROR] e1\n[WARN] w2\n[INFO] i3\n[DEBUG] d4\n[TRACE] t5\n");
+
+    // 無効なレベルでは引数を評価しない
+    log::set_level(None);
+    let mut evaluated = false;
+    log_error!("{}", { evaluated = true; 0 });
+    assert!(!evaluated);
+    assert_eq!(buf.take(), "");
+    assert!(!log::enabled(Level::Error));
+}
@@ -0,0 +1,64 @@
+extern crate Rfdtd;
+
+use Rfdtd::mesh::{nodes, Grading};
+
+// 合計が長さに一致し, セル幅が既定以下で, 隣り合うセル幅の比が上限以下であること
+fn check(g: &Grading) -> Vec<f64> {
+    let w = g.widths().unwrap();
+    let sum: f64 = w.iter().sum();
+    assert!((sum - g.length).abs() < 1e-12 * g.length, "sum {} != {}", sum, g.length);
+    assert!(w.iter().all(|&h| h > 0.0 && h <= g.size * (1.0 + 1e-12)));
+    for p in w.windows(2) {
+        let q = p[0].max(p[1]) / p[0].min(p[1]);
+        assert!(q <= g.ratio * (1.0 + 1e-9), "neighbour ratio {} > {}", q, g.ratio);
+    }
+    w
+}
+
+fn on_node(w: &[f64], x: f64) -> bool {
+    nodes(w).iter().any(|&n| (n - x).abs() < 1e-12)
+}
+
+#[test]
+fn uniform_without_refinement() {
+    let w = check(&Grading::new(0.6, 0.005));
+    assert_eq!(w.len(), 120);
+}
+
+#[test]
+fn refinement_edges_on_nodes() {
+    let g = Grading::new(0.6, 0.005).refine(0.2, 0.25, 0.001).refine(0.4, 0.41, 0.0005);
+    let w = check(&g);
+    for &(s, e, size) in &g.refinements {
+        assert!(on_node(&w, s) && on_node(&w, e));
+        let n = nodes(&w);
+        let inside: Vec<f64> = w.iter().zip(&n).filter(|&(_, &x)| x >= s - 1e-12 && x < e - 1e-12).map(|(&h, _)| h).collect();
+        assert!(inside.iter().all(|&h| h <= size * (1.0 + 1e-12)));
+    }
+}
+
+#[test]
+fn ratio_bound_next_to_narrow_refinement() {
+    let g = Grading::new(0.6, 0.005).refine(0.3, 0.3004, 0.0005);
+    let w = check(&g);
+    assert!(on_node(&w, 0.3) && on_node(&w, 0.3004));
+    let g = Grading::new(0.6, 0.005).refine(0.3, 0.3004, 0.0005).ratio(1.5);
+    check(&g);
+}
+
+#[test]
+fn tiny_gap_is_merged() {
+    let g = Grading::new(0.6, 0.005).refine(0.1, 0.2, 0.001).refine(0.2000001, 0.3, 0.001);
+    let w = check(&g);
+    let min = w.iter().cloned().fold(f64::INFINITY, f64::min);
+    assert!(min > 0.0009, "cell {} in the gap", min);
+    assert!(on_node(&w, 0.1) && on_node(&w, 0.3));
+}
+
+#[test]
+fn refinement_at_domain_edges() {
+    check(&Grading::new(0.3, 0.005).refine(0.0, 0.02, 0.001).refine(0.28, 0.3, 0.0002));
+    // 端との隙間が狭い場合は端まで延ばす
+    let w = check(&Grading::new(0.3, 0.005).refine(0.00001, 0.02, 0.001));
+    assert!(on_node(&w, 0.02));
+}
@@ -0,0 +1,88 @@
+extern crate Rfdtd;
+
+use std::f64::consts::PI;
+
+use Rfdtd::eigenmode::{ModeSolver, Polarization};
+use Rfdtd::fdtd2d::*;
+use Rfdtd::error::Error;
+use Rfdtd::monitor::{FluxMonitor, ModeMonitor};
+use Rfdtd::port::{Axis, Port};
+
+#[test]
+fn mode_monitor_separates_directions() {
+    // y = 20, 36 の完全導体の壁に挟まれた平行平板導波路 (遮断周波数 4.7GHz) を x = 366 の線から最低次モードで励振する
+    // 完全導体はPML内で途切れて端で反射するので, 端からの反射が観測線へ戻る前 (4.5ns) に止める
+    let freqs = [6e9, 7.5e9, 9e9];
+    let mut f = fdtdBuilder::newGrid(700, 24, 0.002, 0.002, 16);
+    f.setup();
+    let (nx, _) = f.dims();
+    f.pec_rect(0, nx, 20, 21).unwrap();
+    f.pec_rect(0, nx, 36, 37).unwrap();
+    let d = 1.0 / (PI * 0.5 * 4e9);
+    for s in Port::new("feed", Axis::X, 366, 21, 36, true).sources(&Source::new(0, 0).pulse(d, 4.0 * d).carrier(7.5e9)) {
+        f.add_source(s).unwrap();
+    }
+    // 右側は+x方向, 左側は-x方向に進む波だけ
+    let right = ModeMonitor::new(&mut f, &ModeSolver::new(Axis::X, 406, 21, 36, Polarization::Te), &freqs, 1).unwrap();
+    let left = ModeMonitor::new(&mut f, &ModeSolver::new(Axis::X, 325, 21, 36, Polarization::Te), &freqs, 1).unwrap();
+    let steps = (4.5e-9 / f.dt) as usize;
+    f.run(steps);
+    let (r, l) = (right.amplitudes(&f), left.amplitudes(&f));
+    for (n, &freq) in freqs.iter().enumerate() {
+        let (r, l) = (r[n][0], l[n][0]);
+        // 打ち切りの誤差が残る
+        assert!(r.backward.abs() < 0.02 * r.forward.abs(), "right: backward {} forward {} at {:e}", r.backward.abs(), r.forward.abs(), freq);
+        assert!(l.forward.abs() < 0.02 * l.backward.abs(), "left: forward {} backward {} at {:e}", l.forward.abs(), l.backward.abs(), freq);
+        // 給電線の両側へ同じ振幅
+        assert!((r.forward.abs() - l.backward.abs()).abs() < 1e-6 * r.forward.abs());
+    }
+}
+
+#[test]
+fn flux_line_is_validated() {
+    // PMLを含めて 56 x 46 セル
+    let mut f = fdtdBuilder::newGrid(40, 30, 0.005, 0.005, 8);
+    f.setup();
+    let freqs = [1e9];
+    match FluxMonitor::new(&mut f, Axis::X, 20, 30, 10, &freqs) {
+        Err(Error::ReversedRange{axis: 'y', start: 30, end: 10, ..}) => {}
+        r => panic!("{:?}", r.map(|_| ())),
+    }
+    match FluxMonitor::new(&mut f, Axis::Y, 20, 15, 15, &freqs) {
+        Err(Error::ReversedRange{axis: 'x', ..}) => {}
+        r => panic!("{:?}", r.map(|_| ())),
+    }
+    for &(normal, pos, end) in [(Axis::X, 55, 20), (Axis::X, 500, 20), (Axis::X, 20, 47), (Axis::Y, 45, 20), (Axis::Y, 20, 57)].iter() {
+        match FluxMonitor::new(&mut f, normal, pos, 10, end, &freqs) {
+            Err(Error::OutOfBounds{what: "flux line", ..}) => {}
+            r => panic!("{:?} {} {}: {:?}", normal, pos, end, r.map(|_| ())),
+        }
+    }
+    assert!(FluxMonitor::new(&mut f, Axis::X, 0, 10, 20, &freqs).is_err());
+    assert!(f.monitors().is_empty());
+    FluxMonitor::new(&mut f, Axis::X, 30, 10, 30, &freqs).unwrap();
+    FluxMonitor::new(&mut f, Axis::Y, 30, 10, 40, &freqs).unwrap();
+}
+
+#[test]
+fn mode_line_near_the_edge_is_an_error() {
+    // PMLを含めて 56 x 46 セル, 解析領域は x = 8..48
+    let mut f = fdtdBuilder::newGrid(40, 30, 0.005, 0.005, 8);
+    f.setup();
+    let line = |pos, start, end| ModeSolver::new(Axis::X, pos, start, end, Polarization::Te);
+    match ModeMonitor::new(&mut f, &line(47, 10, 30), &[1e9], 1) {
+        Err(Error::InPml{what: "monitor", x: 48, ..}) => {}
+        r => panic!("{:?}", r.map(|_| ())),
+    }
+    match ModeMonitor::new(&mut f, &line(60, 10, 30), &[], 1) {
+        Err(Error::OutOfBounds{what: "monitor", ..}) => {}
+        r => panic!("{:?}", r.map(|_| ())),
+    }
+    match ModeMonitor::new(&mut f, &line(20, 30, 10), &[1e9], 1) {
+        Err(Error::ReversedRange{what: "monitor", ..}) => {}
+        r => panic!("{:?}", r.map(|_| ())),
+    }
+    assert!(f.monitors().is_empty());
+    ModeMonitor::new(&mut f, &line(46, 10, 30), &[1e9], 1).unwrap();
+    assert_eq!(f.monitors().len(), 2);
+}
@@ -0,0 +1,98 @@
+extern crate Rfdtd;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use Rfdtd::fdtd2d::*;
+use Rfdtd::monitor::Probe;
+use Rfdtd::observer::{Control, Interval, Observer};
+
+fn model() -> fdtd {
+    let mut f = fdtdBuilder::newGrid(30, 30, 0.005, 0.005, 8);
+    f.init_source(15, 15).unwrap();
+    f.setup();
+    f.add_probe(Probe::new(Component::Ez, 20, 15)).unwrap();
+    f
+}
+
+// 呼ばれた順の (フック, ステップ数, 観測点の記録数)
+type Log = Rc<RefCell<Vec<(&'static str, usize, usize)>>>;
+
+struct Recorder {
+    log: Log,
+    interval: usize,
+    stop_e: Option<usize>,
+}
+
+impl Recorder {
+    fn push(&self, hook: &'static str, f: &fdtd) {
+        self.log.borrow_mut().push((hook, f.step_count(), f.probes()[0].values.len()));
+    }
+}
+
+impl Observer for Recorder {
+    fn after_e(&mut self, f: &fdtd) -> Control {
+        self.push("e", f);
+        if self.stop_e == Some(f.step_count()) { Control::Stop } else { Control::Continue }
+    }
+
+    fn after_h(&mut self, f: &fdtd) -> Control {
+        self.push("h", f);
+        Control::Continue
+    }
+
+    fn interval(&self) -> usize {
+        self.interval
+    }
+
+    fn every(&mut self, f: &fdtd) -> Control {
+        self.push("every", f);
+        Control::Continue
+    }
+}
+
+#[test]
+fn hooks_are_called_in_order() {
+    let mut f = model();
+    let log = Rc::new(RefCell::new(Vec::new()));
+    f.add_observer(Recorder{log: log.clone(), interval: 2, stop_e: None});
+    let s = f.run(4);
+    assert_eq!(s.stop, StopReason::Steps);
+    assert_eq!(*log.borrow(), vec![
+        ("e", 1, 1), ("h", 1, 1),
+        ("e", 2, 2), ("h", 2, 2), ("every", 2, 2),
+        ("e", 3, 3), ("h", 3, 3),
+        ("e", 4, 4), ("h", 4, 4), ("every", 4, 4),
+    ]);
+}
+
+#[test]
+fn stop_ends_the_run_after_the_current_step() {
+    // after_eで止めてもそのステップのH更新までは行う
+    let mut f = model();
+    let log = Rc::new(RefCell::new(Vec::new()));
+    f.add_observer(Recorder{log: log.clone(), interval: 0, stop_e: Some(3)});
+    let s = f.run(100);
+    assert_eq!((s.steps, s.stop), (3, StopReason::Observer));
+    assert!(f.stop_requested());
+    assert_eq!(log.borrow().last(), Some(&("h", 3, 3)));
+
+    // 次の呼び出しでは再び進む
+    let s = f.run(2);
+    assert_eq!((s.steps, s.stop), (5, StopReason::Steps));
+    assert!(!f.stop_requested());
+}
+
+#[test]
+fn interval_stops_run_until() {
+    let mut f = model();
+    let calls = Rc::new(RefCell::new(0));
+    let c = calls.clone();
+    f.add_observer(Interval::new(5, move |f: &fdtd| {
+        *c.borrow_mut() += 1;
+        if f.step_count() >= 15 { Control::Stop } else { Control::Continue }
+    }));
+    let s = f.run_until(1.0);
+    assert_eq!((s.steps, s.stop), (15, StopReason::Observer));
+    assert_eq!(*calls.borrow(), 3);
+}
@@ -0,0 +1,64 @@
+extern crate Rfdtd;
+
+use std::f64::consts::PI;
+
+use Rfdtd::error::{Error, Result};
+use Rfdtd::fdtd2d::*;
+use Rfdtd::port::{extract, Axis, Port};
+
+// y = 20, 36 の完全導体の壁に挟まれた平行平板導波路 (x方向に両端のPMLまで, 幅16セル)
+fn guide() -> Result<fdtd> {
+    let mut f = fdtdBuilder::newGrid(200, 24, 0.002, 0.002, 16);
+    f.setup();
+    let (nx, _) = f.dims();
+    f.pec_rect(0, nx, 20, 21)?;
+    f.pec_rect(0, nx, 36, 37)?;
+    Ok(f)
+}
+
+#[test]
+fn straight_guide_is_matched() {
+    // 遮断周波数 c / (2 × 32mm) = 4.7GHz
+    let freqs = [6e9, 7e9, 8e9, 9e9];
+    let ports = [Port::new("p1", Axis::X, 40, 21, 36, true), Port::new("p2", Axis::X, 192, 21, 36, false)];
+    let d = 1.0 / (PI * 0.5 * 4e9);
+    let pulse = Source::new(0, 0).pulse(d, 4.0 * d).carrier(7.5e9);
+    let s = extract(&ports, &freqs, &pulse, guide, |f| { f.run(6000); }).unwrap();
+    for (n, &freq) in freqs.iter().enumerate() {
+        let m = &s.s[n];
+        for i in 0..2 {
+            assert!(m[i][i].abs() < 0.01, "|S{}{}| = {} at {:e}", i + 1, i + 1, m[i][i].abs(), freq);
+            assert!((m[1 - i][i].abs() - 1.0).abs() < 0.01, "|S{}{}| = {} at {:e}", 2 - i, i + 1, m[1 - i][i].abs(), freq);
+        }
+        // 伝搬定数 β = sqrt(k^2 - (π/w)^2)
+        let k = 2.0 * PI * freq / C;
+        let b = (k * k - (PI / 0.032).powi(2)).sqrt();
+        for p in &s.beta {
+            assert!((p[n].re - b).abs() < 0.005 * b && p[n].im.abs() < 0.005 * b, "beta {:?} != {} at {:e}", p[n], b, freq);
+        }
+    }
+}
+
+#[test]
+fn port_lines_must_fit_the_grid() {
+    let pulse = Source::new(0, 0).pulse(1e-10, 4e-10).carrier(7.5e9);
+    // 観測線 (給電線から3, 6, 9セル) が配列の端 (232) を越える
+    for p in [Port::new("p", Axis::X, 225, 21, 36, true), Port::new("p", Axis::X, 240, 21, 36, false), Port::new("p", Axis::X, 40, 21, 60, true)].chunks(1) {
+        match extract(p, &[7e9], &pulse, guide, |_| {}) {
+            Err(Error::OutOfBounds{what: "port line", ..}) => {}
+            r => panic!("{:?}: {:?}", p[0], r.map(|_| ())),
+        }
+    }
+    assert!(extract(&[Port::new("p", Axis::X, 5, 21, 36, false)], &[7e9], &pulse, guide, |_| {}).is_err());
+}
+
+#[test]
+fn unexcited_port_is_an_error() {
+    // モード分布が0のポートは入射波がなく, Sの行が決まらない
+    let p = Port::new("p", Axis::X, 40, 21, 36, true).profile(vec![0.0; 15]);
+    let pulse = Source::new(0, 0).pulse(1e-10, 4e-10).carrier(7.5e9);
+    match extract(&[p], &[7e9], &pulse, guide, |f| { f.run(10); }) {
+        Err(Error::InvalidValue{value, ..}) => assert_eq!(value, 7e9),
+        r => panic!("{:?}", r.map(|_| ())),
+    }
+}
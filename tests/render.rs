@@ -0,0 +1,31 @@
+extern crate Rfdtd;
+
+use std::io::ErrorKind;
+
+use Rfdtd::fdtd2d::Component;
+use Rfdtd::render::Renderer;
+use Rfdtd::snapshot::Snapshot;
+
+fn snapshot(components: Vec<(Component, Vec<f64>)>) -> Snapshot {
+    Snapshot{step: 0, time: 0.0, xs: vec![0.0, 1.0, 2.0], ys: vec![0.0, 1.0], components, materials: Vec::new()}
+}
+
+#[test]
+fn renders_requested_component() {
+    let s = snapshot(vec![(Component::Ez, vec![0.0; 6]), (Component::Hx, vec![-1.0, 0.0, 1.0, 1.0, 0.0, -1.0])]);
+    let r = Renderer{component: Some(Component::Hx), outline: false, ..Renderer::default()};
+    let img = r.render(&s).unwrap();
+    assert_eq!((img.width, img.height), (3, 2));
+    // 上下反転: 1行目がj=1
+    assert_eq!(img.pixels, vec![254, 127, 0, 0, 127, 254]);
+}
+
+#[test]
+fn missing_component_is_an_error() {
+    let s = snapshot(vec![(Component::Ez, vec![0.0; 6])]);
+    let r = Renderer{component: Some(Component::Hx), ..Renderer::default()};
+    let e = r.render(&s).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::NotFound);
+    assert!(e.to_string().contains("hx"));
+    assert_eq!(Renderer::default().render(&snapshot(Vec::new())).unwrap_err().kind(), ErrorKind::NotFound);
+}
@@ -0,0 +1,68 @@
+extern crate Rfdtd;
+
+use Rfdtd::fdtd2d::*;
+use Rfdtd::monitor::Probe;
+
+fn model() -> fdtd {
+    let mut f = fdtdBuilder::newGrid(30, 30, 0.005, 0.005, 8);
+    f.init_source(15, 15).unwrap();
+    f.setup();
+    f.add_probe(Probe::new(Component::Ez, 20, 15)).unwrap();
+    f
+}
+
+#[test]
+fn run_counts_steps_and_time() {
+    let mut f = model();
+    assert_eq!((f.step_count(), f.time()), (0, 0.0));
+    let s = f.run(1);
+    let dt = f.time();
+    assert!(dt > 0.0);
+
+    let s2 = f.run(24);
+    assert_eq!((s.steps, s.stop), (1, StopReason::Steps));
+    assert_eq!((s2.steps, s2.stop), (25, StopReason::Steps));
+    assert_eq!(f.step_count(), 25);
+    assert_eq!(s2.time, f.time());
+    assert!((f.time() - 25.0 * dt).abs() < 1e-12 * f.time());
+
+    // 観測点は各ステップのE更新後 (時刻 n*dt) に1つずつ記録する
+    let p = &f.probes()[0];
+    assert_eq!(p.values.len(), 25);
+    assert!(p.times.iter().enumerate().all(|(i, &t)| (t - (i + 1) as f64 * dt).abs() < 1e-12 * t));
+}
+
+#[test]
+fn run_until_stops_at_the_nearest_step() {
+    let mut f = model();
+    f.run(1);
+    let dt = f.time();
+
+    // 半ステップ未満の端数は丸める
+    let s = f.run_until(10.4 * dt);
+    assert_eq!((s.steps, s.stop), (10, StopReason::Time));
+    let s = f.run_until(10.6 * dt);
+    assert_eq!(s.steps, 11);
+
+    // 過去の時刻では進めない
+    let s = f.run_until(3.0 * dt);
+    assert_eq!((s.steps, s.stop), (11, StopReason::Time));
+}
+
+#[test]
+fn split_step_matches_step() {
+    let mut a = model();
+    let mut b = model();
+    a.run(40);
+    for _ in 0..40 {
+        b.step_e();
+        b.step_h();
+    }
+    assert_eq!(a.step_count(), b.step_count());
+    for &c in Component::ALL.iter() {
+        for (ca, cb) in a.field(c).iter().zip(b.field(c)) {
+            assert!(ca.iter().zip(cb).all(|(x, y)| x.to_bits() == y.to_bits()), "{} differs", c.name());
+        }
+    }
+    assert!(a.field(Component::Ez).iter().flatten().any(|&v| v != 0.0));
+}
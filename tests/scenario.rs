@@ -0,0 +1,62 @@
+extern crate Rfdtd;
+
+use Rfdtd::scenario::{ScenarioError, ScenarioSpec};
+
+const BASE: &str = r#"{"grid": {"nx": 40, "ny": 40}, "boundary": {"pml_layers": 8},
+                       "probes": [{"name": "p0", "x": 20, "y": 20}]"#;
+
+fn parse(extra: &str) -> Result<ScenarioSpec, ScenarioError> {
+    ScenarioSpec::parse(&format!("{}{}}}", BASE, extra))
+}
+
+fn path_of(r: Result<ScenarioSpec, ScenarioError>) -> String {
+    match r {
+        Err(ScenarioError::Invalid{path, ..}) => path,
+        r => panic!("expected Invalid, got {:?}", r.map(|_| ())),
+    }
+}
+
+#[test]
+fn default_scenario_loads() {
+    let s = ScenarioSpec::load(concat!(env!("CARGO_MANIFEST_DIR"), "/scenarios/default.json")).unwrap();
+    assert_eq!((s.grid.nx, s.grid.ny, s.grid.pml_layers), (1000, 1000, 8));
+    assert_eq!(s.steps, 2000);
+    assert_eq!(s.geometry.len(), 1);
+    assert_eq!(s.sources.len(), 1);
+}
+
+#[test]
+fn unknown_key_is_reported_with_path() {
+    assert_eq!(path_of(parse(r#", "sources": [{"x": 10, "y": 10, "z": 0}]"#)), "sources[0].z");
+    assert_eq!(path_of(parse(r#", "stpes": 10"#)), "stpes");
+}
+
+#[test]
+fn wrong_type_is_reported_with_path() {
+    let r = parse(r#", "steps": "many""#);
+    assert_eq!(path_of(r.clone()), "steps");
+    assert!(r.unwrap_err().to_string().starts_with("steps: expected number"));
+    assert_eq!(path_of(parse(r#", "materials": {"d": {"eps": true}}"#)), "materials.d.eps");
+}
+
+#[test]
+fn out_of_range_is_reported_with_path() {
+    // PMLを含めて56セル
+    let ok = r#", "geometry": [{"type": "pec", "x": [0, 56], "y": [10, 20]}]"#;
+    assert!(parse(ok).is_ok());
+    let r = parse(r#", "geometry": [{"type": "pec", "x": [10, 20], "y": [10, 57]}]"#);
+    assert_eq!(path_of(r.clone()), "geometry[0].y");
+    assert!(r.unwrap_err().to_string().contains("outside the grid"));
+    assert_eq!(path_of(parse(r#", "geometry": [{"type": "pec", "x": [20, 10], "y": [10, 20]}]"#)), "geometry[0].x");
+    assert_eq!(path_of(parse(r#", "geometry": [{"type": "pec", "x": [-1, 10], "y": [10, 20]}]"#)), "geometry[0].x[0]");
+}
+
+#[test]
+fn empty_termination_probes_is_rejected() {
+    let r = parse(r#", "termination": {"signal": "probes", "probes": []}"#);
+    assert_eq!(path_of(r), "termination.probes");
+    // 省略時は全プローブ
+    assert!(parse(r#", "termination": {"signal": "probes"}"#).is_ok());
+    assert!(parse(r#", "termination": {"signal": "probes", "probes": ["p0"]}"#).is_ok());
+    assert_eq!(path_of(parse(r#", "termination": {"signal": "probes", "probes": ["p1"]}"#)), "termination.probes[0]");
+}
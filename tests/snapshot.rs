@@ -0,0 +1,54 @@
+extern crate Rfdtd;
+
+use Rfdtd::fdtd2d::*;
+use Rfdtd::snapshot::{Region, SnapshotWriter};
+
+#[test]
+fn region_is_relative_to_interior() {
+    let mut f = fdtdBuilder::newGrid(20, 10, 0.005, 0.005, 4);
+    for (i, col) in f.ez.iter_mut().enumerate() {
+        for (j, v) in col.iter_mut().enumerate() {
+            *v = (100 * i + j) as f64;
+        }
+    }
+    let r = Region{x_s: 0, x_l: 2, y_s: 1, y_l: 2};
+    let a = SnapshotWriter::new("").region(r).capture(&f, 0, 0.0);
+    assert_eq!(a.components[0].1, vec![405.0, 505.0]);
+    assert_eq!(a.xs[0], 0.0);
+
+    // 配列番号での指定
+    let b = SnapshotWriter::new("").array_region(r).capture(&f, 0, 0.0);
+    assert_eq!(b.components[0].1, vec![1.0, 101.0]);
+
+    // 標準はPMLを除いた解析領域全体
+    let c = SnapshotWriter::new("").capture(&f, 0, 0.0);
+    assert_eq!((c.xs.len(), c.ys.len()), (20, 10));
+    assert_eq!(c.components[0].1[0], 404.0);
+}
+
+// 不均一メッシュでは節点の座標をそのまま出力し, 等間隔でない軸のspacingはNaNにする
+#[test]
+fn graded_mesh_keeps_node_coordinates() {
+    use Rfdtd::binary::Export;
+    use Rfdtd::binary::Dtype;
+    use Rfdtd::vtk::write_geometry;
+
+    let xw: Vec<f64> = (0..16).map(|i| if i < 8 { 0.002 } else { 0.001 }).collect();
+    let yw = vec![0.002; 12];
+    let f = fdtdBuilder::newGraded(&xw, &yw, 2).unwrap();
+    let s = SnapshotWriter::new("").capture(&f, 0, 0.0);
+    let m = s.metadata();
+    assert!(m.spacing[1].is_nan());
+    assert!((m.spacing[0] - 0.002).abs() < 1e-15);
+    let x = &m.extra.iter().find(|e| e.0 == "x").unwrap().1;
+    assert!(x.windows(2).any(|p| (p[1] - p[0] - 0.001).abs() < 1e-12));
+    assert!(x.windows(2).any(|p| (p[1] - p[0] - 0.002).abs() < 1e-12));
+
+    let path = std::env::temp_dir().join(format!("rfdtd_graded_{}.vtr", std::process::id()));
+    write_geometry(&path, &f, Dtype::F64).unwrap();
+    let text = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(text.contains("<RectilinearGrid WholeExtent=\"0 19 0 15 0 0\">"));
+    assert!(text.contains("<Coordinates>"));
+    assert!(!text.contains("Spacing="));
+}
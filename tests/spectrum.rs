@@ -0,0 +1,58 @@
+extern crate Rfdtd;
+
+use Rfdtd::complex::Complex;
+use Rfdtd::spectrum::{fft, Spectral, Spectrum, Window};
+
+#[test]
+fn fft_round_trip() {
+    let x: Vec<Complex> = (0..16).map(|k| Complex::new((k as f64 * 0.7).sin(), k as f64 * 0.1)).collect();
+    let mut y = x.clone();
+    fft(&mut y, false).unwrap();
+    // 直流成分は総和
+    let sum = x.iter().fold(Complex::default(), |a, &b| a + b);
+    assert!((y[0] - sum).abs() < 1e-12);
+    fft(&mut y, true).unwrap();
+    for (a, b) in x.iter().zip(&y) {
+        assert!((*a - b.scale(1.0 / 16.0)).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn fft_rejects_other_lengths() {
+    let mut x = vec![Complex::default(); 12];
+    assert!(fft(&mut x, false).is_err());
+    assert!(fft(&mut [], false).is_err());
+}
+
+#[test]
+fn db_of_zero_spectrum() {
+    let s = Spectral::new(Window::Hann).analyze(&[0.0; 10], 0.0, 1e-12);
+    assert!(s.db().iter().all(|&v| v == f64::NEG_INFINITY));
+    let s = Spectrum{freqs: vec![0.0, 1.0], values: vec![Complex::new(2.0, 0.0), Complex::new(0.0, 0.2)]};
+    let db = s.db();
+    assert_eq!(db[0], 0.0);
+    assert!((db[1] + 20.0).abs() < 1e-12);
+}
+
+#[test]
+fn short_probe_is_an_error() {
+    use Rfdtd::fdtd2d::{Component, Source};
+    use Rfdtd::monitor::Probe;
+
+    let sp = Spectral::new(Window::Rect);
+    let src = Source::new(10, 10);
+    let mut p = Probe::new(Component::Ez, 10, 10);
+    assert!(sp.probe(&p).is_err());
+    p.times.push(1e-12);
+    p.values.push(1.0);
+    assert!(sp.probe(&p).is_err());
+    assert!(sp.source(&src, &p).is_err());
+    assert!(sp.transfer(&p, &src, 1e-3).is_err());
+
+    p.times.push(2e-12);
+    p.values.push(0.5);
+    let s = sp.probe(&p).unwrap();
+    assert!(s.freqs.iter().all(|f| f.is_finite()));
+    assert_eq!(s.freqs.len(), 2);
+    assert!((s.freqs[1] - 0.5 / 1e-12).abs() < 1.0);
+}
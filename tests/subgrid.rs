@@ -0,0 +1,73 @@
+extern crate Rfdtd;
+
+use Rfdtd::fdtd2d::*;
+use Rfdtd::monitor::Probe;
+use Rfdtd::snapshot::Region;
+use Rfdtd::subgrid::{Subgrid, SubgridSolver};
+
+const REGION: Region = Region{x_s: 32, x_l: 47, y_s: 32, y_l: 47};
+
+#[test]
+fn energy_stays_bounded_after_source() {
+    let mut f = fdtdBuilder::newGrid(60, 60, 0.005, 0.005, 8);
+    // 細分格子の外周を跨ぐ誘電体
+    f.medium(30, 50, 36, 42, Medium{eps: 4.0, ..Medium::default()}).unwrap();
+    f.setup();
+    let mut s = SubgridSolver::new(f);
+    let mut g = Subgrid::new(&s.parent, REGION, 3).unwrap();
+    let (x, y) = g.fine_index(38, 38);
+    g.fine.add_source(Source::new(x, y)).unwrap();
+    let (x, y) = g.fine_index(42, 36);
+    g.fine.pec_rect(x, x + 1, y, y + 12).unwrap();
+    s.add(g).unwrap();
+
+    // 給電 (t0 + 4 duration = 0.8ns) が終わるまで
+    s.run((0.8e-9 / s.parent.dt).ceil() as usize);
+    let energy = |s: &SubgridSolver| s.parent.energy() + s.patches[0].fine.energy();
+    let w0 = energy(&s);
+    assert!(w0 > 0.0);
+    // 給電の後は増えず, PMLへ抜けて減り続ける
+    for n in 0..20000 {
+        s.step();
+        let w = energy(&s);
+        assert!(w.is_finite() && w <= w0, "energy {:e} > {:e} at step {}", w, w0, n);
+        if n >= 10000 {
+            assert!(w < 1e-6 * w0, "energy {:e} at step {}", w / w0, n);
+        }
+    }
+}
+
+#[test]
+fn empty_subgrid_is_transparent() {
+    // outer の外の観測点 (手前の反射, 奥の透過)
+    let trace = |sub: bool| {
+        let mut f = fdtdBuilder::newGrid(60, 60, 0.005, 0.005, 8);
+        f.setup();
+        f.add_source(Source::new(20, 39)).unwrap();
+        f.add_probe(Probe::new(Component::Ez, 14, 39)).unwrap();
+        f.add_probe(Probe::new(Component::Ez, 60, 39)).unwrap();
+        let mut s = SubgridSolver::new(f);
+        if sub {
+            let g = Subgrid::new(&s.parent, REGION, 3).unwrap();
+            assert!(g.outer().x_s > 20 && g.outer().x_l < 60);
+            s.add(g).unwrap();
+        }
+        s.run(800);
+        s.parent.probes().to_vec()
+    };
+    let (a, b) = (trace(false), trace(true));
+    for (k, &tol) in [0.01, 0.04].iter().enumerate() {
+        let peak = a[k].values.iter().fold(0.0f64, |m, v| m.max(v.abs()));
+        let diff = a[k].values.iter().zip(&b[k].values).fold(0.0f64, |m, (p, q)| m.max((p - q).abs()));
+        assert!(diff < tol * peak, "probe {}: {:e} of peak", k, diff / peak);
+    }
+}
+
+#[test]
+fn ratio_must_be_odd() {
+    let mut f = fdtdBuilder::newGrid(60, 60, 0.005, 0.005, 8);
+    f.setup();
+    assert!(Subgrid::new(&f, REGION, 2).is_err());
+    assert!(Subgrid::new(&f, REGION, 4).is_err());
+    assert!(Subgrid::new(&f, REGION, 5).is_ok());
+}
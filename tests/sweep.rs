@@ -0,0 +1,70 @@
+extern crate Rfdtd;
+
+use std::path::Path;
+
+use Rfdtd::json;
+use Rfdtd::scenario::{GeometrySpec, ScenarioError};
+use Rfdtd::sweep::SweepSpec;
+
+const BASE: &str = r#"{"grid": {"nx": 40, "ny": 40}, "materials": {"d": {"eps": 2.0}},
+                       "geometry": [{"type": "rect", "material": "d", "x": [10, 20], "y": [10, 20]}],
+                       "sources": [{"x": 20, "y": 20}]}"#;
+
+fn spec(rest: &str) -> Result<SweepSpec, ScenarioError> {
+    let v = json::parse(&format!(r#"{{"base": {}, {}}}"#, BASE, rest)).unwrap();
+    SweepSpec::from_value(&v, Path::new(""))
+}
+
+#[test]
+fn grid_is_cartesian_product() {
+    let s = spec(r#""mode": "grid", "parameters": [
+                    {"path": "materials.d.eps", "values": [2, 3, 4]},
+                    {"path": "geometry[0].x[1]", "range": [20, 30], "count": 2}]"#).unwrap();
+    let runs = s.runs();
+    assert_eq!(runs.len(), 6);
+    for &a in &[2.0, 3.0, 4.0] {
+        for &b in &[20.0, 30.0] {
+            assert_eq!(runs.iter().filter(|r| **r == [a, b]).count(), 1, "{} {}", a, b);
+        }
+    }
+    // 代入先に値が入ること
+    match s.scenario(&[4.0, 30.0]).unwrap().geometry[0] {
+        GeometrySpec::Rect{region, medium} => assert_eq!((region.x_l, medium.eps), (30, 4.0)),
+        ref g => panic!("{:?}", g),
+    }
+}
+
+#[test]
+fn list_length_mismatch_is_rejected() {
+    let ok = spec(r#""mode": "list", "parameters": [
+                     {"path": "materials.d.eps", "values": [2, 3]},
+                     {"path": "geometry[0].x[1]", "values": [20, 30]}]"#).unwrap();
+    assert_eq!(ok.runs(), vec![vec![2.0, 20.0], vec![3.0, 30.0]]);
+    match spec(r#""mode": "list", "parameters": [
+                  {"path": "materials.d.eps", "values": [2, 3, 4]},
+                  {"path": "geometry[0].x[1]", "values": [20, 30]}]"#) {
+        Err(ScenarioError::Invalid{path, ..}) => assert_eq!(path, "parameters"),
+        r => panic!("expected Invalid, got {:?}", r.map(|_| ())),
+    }
+}
+
+#[test]
+fn lhs_one_sample_per_stratum() {
+    let n = 16;
+    let s = spec(&format!(r#""mode": "lhs", "samples": {}, "seed": 7, "parameters": [
+                              {{"path": "materials.d.eps", "range": [1, 5]}},
+                              {{"path": "geometry[0].x[1]", "range": [20, 36]}}]"#, n)).unwrap();
+    let runs = s.runs();
+    assert_eq!(runs.len(), n);
+    for (k, &(lo, hi)) in [(1.0, 5.0), (20.0, 36.0)].iter().enumerate() {
+        let mut count = vec![0; n];
+        for r in &runs {
+            let t = (r[k] - lo) / (hi - lo) * n as f64;
+            assert!(t >= 0.0 && t < n as f64, "{} outside [{}, {})", r[k], lo, hi);
+            count[t as usize] += 1;
+        }
+        assert!(count.iter().all(|&c| c == 1), "parameter {}: {:?}", k, count);
+    }
+    // 同じseedなら同じ標本
+    assert_eq!(runs, s.runs());
+}
@@ -0,0 +1,126 @@
+extern crate Rfdtd;
+
+use Rfdtd::adjoint::{AdjointSolver, Objective};
+use Rfdtd::error::Error;
+use Rfdtd::fdtd2d::*;
+use Rfdtd::snapshot::Region;
+use Rfdtd::topology::{self, project, project_derivative, Filter, Mma, TopologyOptimizer};
+
+const W: usize = 7;
+const H: usize = 5;
+
+fn density(k: usize) -> f64 {
+    0.5 + 0.45 * ((k * 37 % 11) as f64 / 10.0 - 0.5)
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(p, q)| p * q).sum()
+}
+
+#[test]
+fn filter_transpose_is_adjoint() {
+    let f = Filter::new(W, H, 2.5);
+    let x: Vec<f64> = (0..W * H).map(density).collect();
+    let y: Vec<f64> = (0..W * H).map(|k| ((k * 13 % 7) as f64 - 3.0) * 0.3).collect();
+    let (a, b) = (dot(&f.apply(&x), &y), dot(&x, &f.transpose(&y)));
+    assert!((a - b).abs() < 1e-12 * a.abs().max(1.0), "{} != {}", a, b);
+    // 一様な密度は変わらない
+    assert!(f.apply(&[0.3; W * H]).iter().all(|v| (v - 0.3).abs() < 1e-14));
+}
+
+#[test]
+fn project_derivative_matches_difference() {
+    for &(beta, eta) in &[(1.0, 0.5), (8.0, 0.5), (16.0, 0.3)] {
+        for k in 0..=20 {
+            let v = k as f64 / 20.0;
+            let d = 1e-6;
+            let fd = (project(v + d, beta, eta) - project(v - d, beta, eta)) / (2.0 * d);
+            let a = project_derivative(v, beta, eta);
+            assert!((a - fd).abs() < 1e-6 * fd.abs().max(1.0), "beta {} v {}: {} != {}", beta, v, a, fd);
+        }
+        assert!(project(0.0, beta, eta).abs() < 1e-14 && (project(1.0, beta, eta) - 1.0).abs() < 1e-14);
+    }
+}
+
+#[test]
+fn sensitivity_matches_difference() {
+    let design = Region{x_s: 10, x_l: 10 + W, y_s: 20, y_l: 20 + H};
+    let adjoint = AdjointSolver::new(Objective::Intensity{region: design}, 3e9, design);
+    let t = TopologyOptimizer::new(adjoint, 1.0, 4.0).filter(2.0).projection(4.0, 0.5);
+    let filter = Filter::new(W, H, t.radius);
+    let eps = |x: &[f64]| -> Vec<f64> {
+        filter.apply(x).iter().map(|&v| t.eps_min + project(v, t.beta, t.eta) * (t.eps_max - t.eps_min)).collect()
+    };
+    // F(ε) = Σ c_k ε_k^2
+    let c = |k: usize| 1.0 + (k % 4) as f64;
+    let objective = |x: &[f64]| eps(x).iter().enumerate().map(|(k, e)| c(k) * e * e).sum::<f64>();
+
+    let x: Vec<f64> = (0..W * H).map(density).collect();
+    let e = eps(&x);
+    let gradient: Vec<Vec<f64>> = (0..W).map(|i| (0..H).map(|j| 2.0 * c(i * H + j) * e[i * H + j]).collect()).collect();
+    let g = t.sensitivity(&filter, &filter.apply(&x), t.beta, &gradient);
+    for k in 0..W * H {
+        let d = 1e-6;
+        let (mut a, mut b) = (x.clone(), x.clone());
+        a[k] += d;
+        b[k] -= d;
+        let fd = (objective(&a) - objective(&b)) / (2.0 * d);
+        assert!((g[k] - fd).abs() < 1e-6 * fd.abs().max(1.0), "cell {}: {} != {}", k, g[k], fd);
+    }
+}
+
+#[test]
+fn mma_decreases_separable_quadratic() {
+    // f = Σ (x_k - t_k)^2, 最小は t を [0, 1] に収めた点
+    let target = [-0.3, 0.25, 0.7, 1.4, 0.5, 0.05];
+    let f = |x: &[f64]| x.iter().zip(&target).map(|(a, t)| (a - t) * (a - t)).sum::<f64>();
+    let best: Vec<f64> = target.iter().map(|t| t.clamp(0.0, 1.0)).collect();
+    let mut x = vec![0.5; target.len()];
+    let mut mma = Mma::new(x.len());
+    let first = f(&x);
+    for _ in 0..40 {
+        let df: Vec<f64> = x.iter().zip(&target).map(|(a, t)| 2.0 * (a - t)).collect();
+        mma.update(&mut x, &df, 0.2);
+        assert!(x.iter().all(|&v| (0.0..=1.0).contains(&v)), "{:?}", x);
+        assert!(f(&x) < first);
+    }
+    // 上下限に当たる変数は上下限に, 内側は漸近線の最小幅 (0.01) の範囲で最小に近づく
+    for k in 0..x.len() {
+        assert!((x[k] - best[k]).abs() < 0.01, "{} for target {}", x[k], target[k]);
+    }
+    assert_eq!((x[0], x[3]), (0.0, 1.0));
+    assert!(f(&x) - f(&best) < 1e-3);
+}
+
+#[test]
+fn reversed_design_region_is_an_error() {
+    let design = Region{x_s: 30, x_l: 20, y_s: 20, y_l: 25};
+    let adjoint = AdjointSolver::new(Objective::Intensity{region: Region{x_s: 20, x_l: 25, y_s: 20, y_l: 25}}, 3e9, design);
+    let t = TopologyOptimizer::new(adjoint, 1.0, 4.0);
+    let r = t.optimize(&[], &[], |_| -> Rfdtd::error::Result<fdtd> { panic!("build before validation") }, |_| {}, None);
+    match r {
+        Err(Error::ReversedRange{what: "design region", axis: 'x', start: 30, end: 20}) => {}
+        r => panic!("{:?}", r.map(|_| ())),
+    }
+}
+
+#[test]
+fn history_covers_every_iteration_and_the_final_design() {
+    let design = Region{x_s: 24, x_l: 28, y_s: 24, y_l: 28};
+    let adjoint = AdjointSolver::new(Objective::Intensity{region: Region{x_s: 34, x_l: 36, y_s: 25, y_l: 27}}, 3e9, design);
+    let t = TopologyOptimizer::new(adjoint, 1.0, 4.0).filter(1.5).continuation(2, 4.0).iterations(3);
+    let build = |eps: &[Vec<f64>]| {
+        let mut f = fdtdBuilder::newGrid(40, 40, 0.005, 0.005, 8);
+        topology::apply(&mut f, design, eps)?;
+        f.setup();
+        Ok(f)
+    };
+    let d = t.optimize(&vec![vec![0.5; 4]; 4], &[Source::new(16, 26)], build, |f| { f.run(400); }, None).unwrap();
+    let it: Vec<usize> = d.history.iter().map(|h| h.iteration).collect();
+    assert_eq!(it, vec![0, 1, 2, 3]);
+    let beta: Vec<f64> = d.history.iter().map(|h| h.beta).collect();
+    assert_eq!(beta, vec![1.0, 1.0, 2.0, 2.0]);
+    assert_eq!(d.history[0].change, 0.0);
+    assert!(d.history[1..].iter().all(|h| h.change > 0.0 && h.change <= t.move_limit + 1e-12));
+    assert_eq!((d.eps.len(), d.eps[0].len()), (4, 4));
+}
@@ -0,0 +1,136 @@
+extern crate Rfdtd;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use Rfdtd::binary::Dtype;
+use Rfdtd::fdtd2d::*;
+use Rfdtd::snapshot::SnapshotWriter;
+use Rfdtd::vtk::{write_geometry, VtkBackend};
+
+fn model() -> fdtd {
+    let mut f = fdtdBuilder::newGrid(12, 10, 0.005, 0.004, 2);
+    f.medium(4, 8, 3, 6, Medium{eps: 4.0, ..Medium::default()}).unwrap();
+    f.setup();
+    f.pec_rect(10, 12, 4, 6).unwrap();
+    f
+}
+
+fn dir(name: &str) -> PathBuf {
+    let d = env::temp_dir().join(format!("rfdtd_vtk_{}_{}", name, std::process::id()));
+    fs::create_dir_all(&d).unwrap();
+    d
+}
+
+// 属性値 name="..." (最初に現れるもの)
+fn attr<'a>(text: &'a str, name: &str) -> &'a str {
+    let key = format!(" {}=\"", name);
+    let s = text.find(&key).unwrap() + key.len();
+    &text[s..s + text[s..].find('"').unwrap()]
+}
+
+// <tag>...</tag> の中身
+fn section<'a>(text: &'a str, tag: &str) -> &'a str {
+    let s = text.find(&format!("<{}>", tag)).unwrap() + tag.len() + 2;
+    &text[s..s + text[s..].find(&format!("</{}>", tag)).unwrap()]
+}
+
+fn decode64(s: &str) -> Vec<u8> {
+    let val = |c: u8| match c {
+        b'A'..=b'Z' => c - b'A',
+        b'a'..=b'z' => c - b'a' + 26,
+        b'0'..=b'9' => c - b'0' + 52,
+        b'+' => 62,
+        _ => 63,
+    } as u32;
+    let mut out = Vec::new();
+    for q in s.as_bytes().chunks(4) {
+        let n = q.iter().fold(0u32, |n, &c| n << 6 | if c == b'=' { 0 } else { val(c) });
+        let pad = q.iter().filter(|&&c| c == b'=').count();
+        out.extend_from_slice(&n.to_be_bytes()[1..4 - pad]);
+    }
+    out
+}
+
+// 区間内の DataArray (名前, 値)
+fn arrays(text: &str) -> Vec<(String, Vec<f64>)> {
+    text.split("<DataArray").skip(1).map(|a| {
+        assert_eq!(attr(a, "type"), "Float64");
+        let body = a[a.find('>').unwrap() + 1..a.find("</DataArray>").unwrap()].trim();
+        let b = decode64(body);
+        let size = u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as usize;
+        assert_eq!(size, b.len() - 8);
+        let v = b[8..].chunks(8).map(|c| f64::from_le_bytes([c[0], c[1], c[2], c[3], c[4], c[5], c[6], c[7]])).collect();
+        (attr(a, "Name").to_string(), v)
+    }).collect()
+}
+
+fn get<'a>(a: &'a [(String, Vec<f64>)], name: &str) -> &'a [f64] {
+    &a.iter().find(|e| e.0 == name).unwrap().1
+}
+
+#[test]
+fn geometry_structure() {
+    let f = model();
+    let d = dir("geometry");
+    let path = d.join("geometry.vtr");
+    write_geometry(&path, &f, Dtype::F64).unwrap();
+    let text = fs::read_to_string(&path).unwrap();
+    fs::remove_dir_all(&d).ok();
+
+    assert_eq!(attr(&text, "type"), "RectilinearGrid");
+    assert_eq!(attr(&text, "WholeExtent"), "0 15 0 13 0 0");
+    assert_eq!(attr(&text, "Extent"), "0 15 0 13 0 0");
+
+    let coords = arrays(section(&text, "Coordinates"));
+    let xs = get(&coords, "x");
+    assert_eq!(xs.len(), 16);
+    assert!(xs.iter().enumerate().all(|(i, &x)| (x - f.position(i, 0).0).abs() < 1e-15));
+    assert_eq!(get(&coords, "y").len(), 14);
+    assert_eq!(get(&coords, "z"), &[0.0]);
+
+    // 媒質定数はセルデータ, pecは点データ
+    let cells = arrays(section(&text, "CellData"));
+    let points = arrays(section(&text, "PointData"));
+    for name in &["epsd", "mud", "sgmed", "sgmmd"] {
+        assert_eq!(get(&cells, name).len(), 15 * 13, "{}", name);
+    }
+    assert_eq!(get(&points, "pec").len(), 16 * 14);
+    let eps = get(&cells, "epsd");
+    assert!((0..13).all(|y| (0..15).all(|x| eps[y * 15 + x] == f.material(Material::Eps, x, y))));
+    assert_eq!(eps[4 * 15 + 5], 4.0);
+    assert_eq!(eps.iter().filter(|&&e| e != 1.0).count(), 3 * 2);
+    assert_eq!(get(&points, "pec")[5 * 16 + 11], 1.0);
+}
+
+#[test]
+fn snapshots_and_collection() {
+    let mut f = model();
+    let d = dir("snapshots");
+    let mut w = SnapshotWriter::new("EData").dir(&d).every(2).stride(2, 1)
+        .components(&[Component::Ez, Component::Hx])
+        .backend(VtkBackend::new("EData").dtype(Dtype::F64));
+    for step in 0..5 {
+        f.run(1);
+        w.write(&f, step, f.time()).unwrap();
+    }
+    w.finish().unwrap();
+
+    let pvd = fs::read_to_string(d.join("EData.pvd")).unwrap();
+    let files: Vec<&str> = pvd.split("<DataSet").skip(1).map(|s| attr(s, "file")).collect();
+    assert_eq!(files, vec!["EData0.vtr", "EData1.vtr", "EData2.vtr"]);
+
+    let text = fs::read_to_string(d.join("EData1.vtr")).unwrap();
+    fs::remove_dir_all(&d).ok();
+    // 解析領域 12 x 10 をxのみ2セル毎に間引く
+    assert_eq!(attr(&text, "WholeExtent"), "0 5 0 9 0 0");
+    let points = arrays(section(&text, "PointData"));
+    assert_eq!(get(&points, "ez").len(), 6 * 10);
+    assert_eq!(get(&points, "hx").len(), 6 * 10);
+    assert_eq!(get(&points, "pec").len(), 6 * 10);
+    let cells = arrays(section(&text, "CellData"));
+    assert_eq!(get(&cells, "epsd").len(), 5 * 9);
+    let xs = get(&arrays(section(&text, "Coordinates")), "x").to_vec();
+    assert!(xs.windows(2).all(|p| (p[1] - p[0] - 0.01).abs() < 1e-12));
+}